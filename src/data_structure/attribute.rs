@@ -0,0 +1,109 @@
+use crate::IndexType;
+
+// one value with a fixed number of components per element (vertex or corner)
+#[derive(Clone)]
+pub struct Attribute {
+  pub name: String,
+  pub dimension: usize,
+  // components of each element one after the other
+  pub values: Vec<f32>,
+}
+
+impl Attribute {
+  pub fn new(name: &str, dimension: usize) -> Self {
+    assert!(dimension > 0, "attributes need at least one component");
+
+    Attribute {
+      name: name.to_owned(),
+      dimension,
+      values: Vec::new(),
+    }
+  }
+
+  // number of elements
+  pub fn len(&self) -> usize {
+    self.values.len() / self.dimension
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.values.is_empty()
+  }
+
+  pub fn get(&self, idx: IndexType) -> &[f32] {
+    let start = idx as usize * self.dimension;
+
+    &self.values[start..start + self.dimension]
+  }
+
+  pub fn set(&mut self, idx: IndexType, value: &[f32]) {
+    let start = idx as usize * self.dimension;
+
+    self.values[start..start + self.dimension].copy_from_slice(value);
+  }
+
+  pub fn push(&mut self, value: &[f32]) {
+    debug_assert_eq!(value.len(), self.dimension);
+
+    self.values.extend_from_slice(value);
+  }
+
+  // weighted sum of other elements
+  pub fn mix(&self, sources: &[(IndexType, f32)]) -> Vec<f32> {
+    let mut out = vec![0.0; self.dimension];
+
+    for (idx, weight) in sources {
+      for (out, value) in out.iter_mut().zip(self.get(*idx)) {
+        *out += weight * value;
+      }
+    }
+
+    out
+  }
+
+  pub fn push_mix(&mut self, sources: &[(IndexType, f32)]) {
+    let value = self.mix(sources);
+
+    self.values.extend(value);
+  }
+
+  pub fn set_mix(&mut self, idx: IndexType, sources: &[(IndexType, f32)]) {
+    let value = self.mix(sources);
+
+    self.set(idx, &value);
+  }
+
+  // keeps the elements where keep is true (in order)
+  pub fn retain(&mut self, keep: &[bool]) {
+    let dimension = self.dimension;
+    let mut idx = 0;
+
+    self.values.retain(|_| {
+      let out = keep[idx / dimension];
+      idx += 1;
+      out
+    });
+  }
+}
+
+// per vertex attributes have an element for each vertex, per corner
+// attributes have an element for each corner of each face (so uv seams and
+// hard normals can be represented)
+#[derive(Clone, Default)]
+pub struct Attributes {
+  pub vertex: Vec<Attribute>,
+  pub corner: Vec<Attribute>,
+}
+
+impl Attributes {
+  pub fn is_empty(&self) -> bool {
+    self.vertex.is_empty() && self.corner.is_empty()
+  }
+
+  pub fn find_vertex(&self, name: &str) -> Option<&Attribute> {
+    self.vertex.iter().find(|a| a.name == name)
+  }
+
+  pub fn find_corner(&self, name: &str) -> Option<&Attribute> {
+    self.corner.iter().find(|a| a.name == name)
+  }
+}
@@ -1,51 +1,74 @@
-use std::fs::File;
-use std::io::{prelude::*, BufWriter};
 use std::path::Path;
 
+use crate::data_structure::Attributes;
+use crate::file_format::{obj, ply, stl};
 use crate::get_normal;
+use crate::MeshError;
 
 use nalgebra;
 
 pub type Vector3 = nalgebra::base::Vector3<f32>;
-pub type Face = [u32; 3];
+// vertex indices in counterclockwise order (at least 3)
+pub type Face = Vec<IndexType>;
 pub type IndexType = u32;
 
-pub trait DataStructure: Sized {
-  fn from_iters<IterVert, IterFace>(
+// old index to new index for each kind of element (None if it was removed)
+pub struct IndexRemap {
+  pub vertices: Vec<Option<IndexType>>,
+  pub edges: Vec<Option<IndexType>>,
+  pub faces: Vec<Option<IndexType>>,
+}
+
+// Sync so operations can read the mesh from several threads (with the
+// parallel feature)
+pub trait DataStructure: Sized + Sync {
+  // errors if the faces don't form a manifold mesh (or refer to vertices
+  // which don't exist)
+  fn try_from_iters<IterVert, IterFace>(
     vertices: IterVert,
     faces: IterFace,
-  ) -> Self
+  ) -> Result<Self, MeshError>
   where
     IterVert: IntoIterator<Item = Vector3>,
     IterFace: IntoIterator<Item = Face>;
 
-  fn from_obj(path: &Path) -> Result<Self, tobj::LoadError> {
-    let (models, _) = tobj::load_obj(path)?;
-    let vertices = models
-      .iter()
-      .map(|m| {
-        let mesh = &m.mesh;
+  // panics if the input isn't valid (see try_from_iters)
+  fn from_iters<IterVert, IterFace>(vertices: IterVert, faces: IterFace) -> Self
+  where
+    IterVert: IntoIterator<Item = Vector3>,
+    IterFace: IntoIterator<Item = Face>,
+  {
+    Self::try_from_iters(vertices, faces).unwrap_or_else(|e| panic!("{}", e))
+  }
 
-        mesh
-          .positions
-          .chunks_exact(3)
-          .map(|vals| Vector3::new(vals[0], vals[1], vals[2]))
-      })
-      .flatten();
+  // texture coordinates and normals are loaded as corner attributes ("uv"
+  // and "normal") and vertex colors as a vertex attribute ("color")
+  fn from_obj(path: &Path) -> Result<Self, MeshError> {
+    let (vertices, faces, attributes) = obj::read(path)?;
 
-    let faces = models
-      .iter()
-      .map(|m| {
-        let mesh = &m.mesh;
+    let mut out = Self::try_from_iters(vertices, faces)?;
+    out.set_attributes(attributes)?;
 
-        mesh
-          .indices
-          .chunks_exact(3)
-          .map(|vals| [vals[0], vals[1], vals[2]])
-      })
-      .flatten();
+    Ok(out)
+  }
 
-    Ok(Self::from_iters(vertices, faces))
+  // extra vertex properties (normals, colors, ...) become vertex attributes
+  fn from_ply(path: &Path) -> Result<Self, MeshError> {
+    let data = ply::read(path)?;
+    let attributes = data.attributes();
+
+    let mut out = Self::try_from_iters(data.vertices, data.faces)?;
+    out.set_attributes(attributes)?;
+
+    Ok(out)
+  }
+
+  // stl stores separate corners for each triangle, so corners closer than
+  // epsilon are merged into one vertex
+  fn from_stl(path: &Path, epsilon: f32) -> Result<Self, MeshError> {
+    let (vertices, faces) = stl::weld(&stl::read(path)?, epsilon);
+
+    Self::try_from_iters(vertices, faces)
   }
 
   fn max_idx_vertices(&self) -> usize;
@@ -60,11 +83,18 @@ pub trait DataStructure: Sized {
 
   fn num_faces(&self) -> usize;
 
+  // purge removed elements so indices are dense again (max_idx_* == num_*);
+  // any stored indices must be translated with the returned tables
+  fn compact(&mut self) -> IndexRemap;
+
   // TODO: generic?
   fn initial_vertex(&self) -> Option<IndexType>;
 
   fn next_vertex(&self, key: IndexType) -> Option<IndexType>;
 
+  // false if the vertex was removed (or never existed)
+  fn has_vertex(&self, key: IndexType) -> bool;
+
   fn initial_edge(&self) -> Option<IndexType>;
 
   fn next_edge(&self, key: IndexType) -> Option<IndexType>;
@@ -73,21 +103,82 @@ pub trait DataStructure: Sized {
 
   fn next_face(&self, key: IndexType) -> Option<IndexType>;
 
-  fn flip_edge(&mut self, key: IndexType) -> Option<()>;
+  // errors if the edge is on the boundary or isn't between two triangles
+  fn try_flip_edge(&mut self, key: IndexType) -> Result<(), MeshError>;
+
+  // None if the edge can't be flipped (see try_flip_edge)
+  fn flip_edge(&mut self, key: IndexType) -> Option<()> {
+    self.try_flip_edge(key).ok()
+  }
 
   // new vertex,
   // order of returned edges:
-  // original edge left, original edge right
-  // new edge top, new edge bottom (same order as get_opposite_points)
-  fn split_edge(&mut self, key: IndexType) -> (IndexType, [IndexType; 4]);
+  // new edge to the far point of the first face, original edge (same index,
+  // now half as long), other half of the original edge
+  // and new edge to the far point of the second face (None if boundary)
+  // (far points are in the same order as get_edge_neighbors)
+  // errors if the adjacent faces aren't triangles
+  fn try_split_edge(
+    &mut self,
+    key: IndexType,
+  ) -> Result<(IndexType, [IndexType; 3], Option<IndexType>), MeshError>;
+
+  // panics if the edge can't be split (see try_split_edge)
+  fn split_edge(
+    &mut self,
+    key: IndexType,
+  ) -> (IndexType, [IndexType; 3], Option<IndexType>) {
+    self.try_split_edge(key).unwrap_or_else(|e| panic!("{}", e))
+  }
 
+  // returns the merged vertex, errors if the adjacent faces aren't triangles
+  // or the collapse would make the mesh non manifold (the mesh is unchanged);
+  // the attributes of the merged vertex and its corners are 1 - t of the
+  // first endpoint and t of the second (see get_endpoints)
+  fn try_collapse_edge_at(
+    &mut self,
+    key: IndexType,
+    t: f32,
+    // edge idx and other vertex
+    modified_edges: &mut Vec<(IndexType, IndexType)>,
+    removed_edges: &mut Vec<IndexType>,
+  ) -> Result<IndexType, MeshError>;
+
+  // None if the edge can't be collapsed (see try_collapse_edge_at)
+  fn collapse_edge_at(
+    &mut self,
+    key: IndexType,
+    t: f32,
+    // edge idx and other vertex
+    modified_edges: &mut Vec<(IndexType, IndexType)>,
+    removed_edges: &mut Vec<IndexType>,
+  ) -> Option<IndexType> {
+    self
+      .try_collapse_edge_at(key, t, modified_edges, removed_edges)
+      .ok()
+  }
+
+  // attributes are the average of both endpoints (see try_collapse_edge_at)
+  fn try_collapse_edge(
+    &mut self,
+    key: IndexType,
+    // edge idx and other vertex
+    modified_edges: &mut Vec<(IndexType, IndexType)>,
+    removed_edges: &mut Vec<IndexType>,
+  ) -> Result<IndexType, MeshError> {
+    self.try_collapse_edge_at(key, 0.5, modified_edges, removed_edges)
+  }
+
+  // None if the edge can't be collapsed (see try_collapse_edge)
   fn collapse_edge(
     &mut self,
     key: IndexType,
     // edge idx and other vertex
     modified_edges: &mut Vec<(IndexType, IndexType)>,
     removed_edges: &mut Vec<IndexType>,
-  ) -> Option<IndexType>;
+  ) -> Option<IndexType> {
+    self.collapse_edge_at(key, 0.5, modified_edges, removed_edges)
+  }
 
   fn set_position(&mut self, key: IndexType, position: &Vector3);
 
@@ -114,6 +205,45 @@ pub trait DataStructure: Sized {
     neighbors: &mut Vec<IndexType>,
   ) -> bool;
 
+  // edge index and other vertex of each edge adjacent to the vertex (same
+  // order as get_vertex_neighbors)
+  fn get_vertex_edges(
+    &self,
+    key: IndexType,
+    edges: &mut Vec<(IndexType, IndexType)>,
+  ) -> bool {
+    edges.clear();
+
+    self.get_vertex_edges_append(key, edges)
+  }
+
+  // same as get vertex edges, but appends to vec instead of clearing
+  fn get_vertex_edges_append(
+    &self,
+    key: IndexType,
+    edges: &mut Vec<(IndexType, IndexType)>,
+  ) -> bool;
+
+  // edge connecting the two vertices (if there is one)
+  fn find_edge(
+    &self,
+    first: IndexType,
+    second: IndexType,
+  ) -> Option<IndexType> {
+    if !self.has_vertex(first) || !self.has_vertex(second) {
+      return None;
+    }
+
+    let mut edges = Vec::new();
+
+    self.get_vertex_edges(first, &mut edges);
+
+    edges
+      .into_iter()
+      .find(|(_, other)| *other == second)
+      .map(|(edge_idx, _)| edge_idx)
+  }
+
   fn get_vertex_adjacent_faces(
     &self,
     key: IndexType,
@@ -126,9 +256,40 @@ pub trait DataStructure: Sized {
     key: IndexType,
   ) -> ([IndexType; 3], Option<IndexType>);
 
+  // first three vertices for polygons
   fn get_face_neighbors(&self, key: IndexType) -> [IndexType; 3];
 
-  // normal and positions of each vertex
+  // all vertices of the face in order
+  fn get_face_vertices(&self, key: IndexType, vertices: &mut Vec<IndexType>);
+
+  // edges of the face in order (edge i connects vertex i and vertex i + 1)
+  fn get_face_edges(&self, key: IndexType, edges: &mut Vec<IndexType>);
+
+  fn face_degree(&self, key: IndexType) -> usize;
+
+  fn is_triangle_mesh(&self) -> bool {
+    self.check_triangle_mesh().is_ok()
+  }
+
+  // errors with the first face which isn't a triangle
+  fn check_triangle_mesh(&self) -> Result<(), MeshError> {
+    let mut face_op = self.initial_face();
+
+    while let Some(face_idx) = face_op {
+      if self.face_degree(face_idx) != 3 {
+        return Err(MeshError::NonTriangleFace { face: face_idx });
+      }
+
+      face_op = self.next_face(face_idx);
+    }
+
+    Ok(())
+  }
+
+  // face on each side of the edge (second is None on the boundary)
+  fn get_edge_faces(&self, key: IndexType) -> (IndexType, Option<IndexType>);
+
+  // normal and positions of each vertex (first three for polygons)
   fn get_face_normal(&self, face_idx: IndexType) -> (Vector3, [Vector3; 3]) {
     let [v_0, v_1, v_2] = self.get_face_neighbors(face_idx);
 
@@ -161,29 +322,94 @@ pub trait DataStructure: Sized {
 
   fn get_endpoints(&self, key: IndexType) -> [IndexType; 2];
 
-  fn save_obj(self, path: &Path) -> std::io::Result<()> {
-    let mut writer = BufWriter::new(File::create(path)?);
+  // replaces all attributes: vertex attributes have an element for each
+  // vertex index (up to max_idx_vertices), corner attributes have the
+  // corners of each face (by face index) in the order of get_face_vertices
+  fn set_attributes(&mut self, attributes: Attributes)
+    -> Result<(), MeshError>;
 
-    let (vertices, faces) = self.to_vecs();
+  fn has_attributes(&self) -> bool;
 
-    for vertex in vertices {
-      writeln!(&mut writer, "v {} {} {}", vertex[0], vertex[1], vertex[2])?
-    }
+  // sets the vertex attributes of target to a weighted sum of the vertex
+  // attributes of sources (sources may include the vertex removed by the
+  // last collapse_edge, attributes are kept until compact)
+  fn mix_vertex_attributes(
+    &mut self,
+    target: IndexType,
+    sources: &[(IndexType, f32)],
+  );
 
-    for face in faces {
-      writeln!(
-        &mut writer,
-        "f {} {} {}",
-        face[0] + 1,
-        face[1] + 1,
-        face[2] + 1
-      )?;
-    }
+  // total number of components of all vertex and all corner attributes
+  fn attribute_dimensions(&self) -> [usize; 2];
 
-    writer.flush()?;
+  // components of all vertex attributes one after the other
+  fn get_vertex_attributes(&self, key: IndexType, values: &mut Vec<f32>);
 
-    Ok(())
+  fn set_vertex_attributes(&mut self, key: IndexType, values: &[f32]);
+
+  // components of all corner attributes at the corner of the face at the
+  // vertex
+  fn get_corner_attributes(
+    &self,
+    vertex: IndexType,
+    face: IndexType,
+    values: &mut Vec<f32>,
+  );
+
+  fn set_corner_attributes(
+    &mut self,
+    vertex: IndexType,
+    face: IndexType,
+    values: &[f32],
+  );
+
+  fn save_obj(&self, path: &Path) -> std::io::Result<()> {
+    let (vertices, faces, attributes) = self.to_vecs_with_attributes();
+
+    obj::write(path, &vertices, &faces, &attributes)
   }
 
-  fn to_vecs(self) -> (Vec<Vector3>, Vec<Face>);
+  fn save_ply(&self, path: &Path, binary: bool) -> std::io::Result<()> {
+    let (vertices, faces, attributes) = self.to_vecs_with_attributes();
+
+    ply::write(
+      path,
+      &ply::PlyData::new(vertices, faces, &attributes),
+      binary,
+    )
+  }
+
+  // binary, polygons are split into fans which share the normal of the
+  // polygon's first triangle
+  fn save_stl(&self, path: &Path) -> std::io::Result<()> {
+    let mut triangles = Vec::with_capacity(self.num_faces());
+    let mut vertices = Vec::new();
+
+    let mut face_op = self.initial_face();
+
+    while let Some(face) = face_op {
+      let (normal, _) = self.get_face_normal(face);
+
+      self.get_face_vertices(face, &mut vertices);
+
+      let p_0 = self.get_position(vertices[0]);
+
+      for pair in vertices[1..].windows(2) {
+        triangles.push((
+          normal,
+          [p_0, self.get_position(pair[0]), self.get_position(pair[1])],
+        ));
+      }
+
+      face_op = self.next_face(face);
+    }
+
+    stl::write(path, &triangles)
+  }
+
+  fn to_vecs(&self) -> (Vec<Vector3>, Vec<Face>);
+
+  // attributes are in the same order as the vertices and the corners of the
+  // faces
+  fn to_vecs_with_attributes(&self) -> (Vec<Vector3>, Vec<Face>, Attributes);
 }
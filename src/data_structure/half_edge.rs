@@ -1,7 +1,10 @@
 use crate::data_structure::base::Face;
+use crate::data_structure::base::IndexRemap;
 use crate::data_structure::base::IndexType;
 use crate::data_structure::base::Vector3;
 use crate::data_structure::DataStructure;
+use crate::data_structure::{Attribute, Attributes};
+use crate::MeshError;
 
 use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
@@ -39,12 +42,17 @@ pub struct HalfEdge {
   num_removed_edges: usize,
   num_removed_faces: usize,
   removed_half_edges: HashSet<u32>, // only used for debugging
+  // vertex attributes are indexed like vertex_refs and corner attributes
+  // like half_edge_refs (the corner at the start of the half edge)
+  attributes: Attributes,
 }
 
 enum Offset {
   Current,
   Next,
   NextNext,
+  // walks around the face, so this is O(face degree)
+  Prev,
 }
 
 struct IterVertexHalfEdges<'a> {
@@ -88,7 +96,7 @@ impl<'a> Iterator for IterVertexHalfEdges<'a> {
 
         self.half_edge_idx = self
           .half_edge
-          .relative_get(half_edge_idx, Offset::NextNext)
+          .relative_get(half_edge_idx, Offset::Prev)
           .twin_idx;
 
         self.first = false;
@@ -118,6 +126,21 @@ impl HalfEdge {
         &self.half_edge_refs
           [self.half_edge_refs[first.next_idx as usize].next_idx as usize]
       }
+      Offset::Prev => {
+        let mut prev_idx = idx;
+
+        loop {
+          let next_idx = self.half_edge_refs[prev_idx as usize].next_idx;
+
+          if next_idx == idx {
+            break;
+          }
+
+          prev_idx = next_idx;
+        }
+
+        &self.half_edge_refs[prev_idx as usize]
+      }
     }
   }
 
@@ -131,6 +154,41 @@ impl HalfEdge {
     None
   }
 
+  // half edge of the face which starts at the vertex
+  fn corner_half_edge(&self, vertex: IndexType, face: IndexType) -> IndexType {
+    let face = self.face_refs[face as usize].as_ref().unwrap();
+
+    self
+      .face_half_edges(face)
+      .find(|idx| self.half_edge_refs[*idx as usize].vertex_idx == vertex)
+      .expect("vertex isn't on the face")
+  }
+
+  fn face_half_edges<'a>(
+    &'a self,
+    face: &FaceRef,
+  ) -> impl Iterator<Item = IndexType> + 'a {
+    let half_edge_idx_orig = face.half_edge_idx;
+    let mut half_edge_idx = Some(half_edge_idx_orig);
+
+    std::iter::from_fn(move || {
+      let out = half_edge_idx;
+
+      if let Some(idx) = out {
+        let next_idx = self.half_edge_refs[idx as usize].next_idx;
+
+        half_edge_idx = if next_idx == half_edge_idx_orig {
+          None
+        } else {
+          Some(next_idx)
+        };
+      }
+
+      out
+    })
+  }
+
+  // first three vertices for polygons
   fn get_face_ref_neighbors(&self, face: &FaceRef) -> [IndexType; 3] {
     let half_edge = &self.half_edge_refs[face.half_edge_idx as usize];
     let next_half_edge = self.get_next(half_edge);
@@ -155,17 +213,18 @@ impl HalfEdge {
     let mut first = true;
     let mut has_boundary = false;
 
-    // iterate until we hit one side
+    // iterate backwards until we hit one side (so iterating forwards from
+    // there visits every half edge)
     while first || half_edge_idx != half_edge_idx_orig {
       debug_assert_eq!(
         self.relative_get(half_edge_idx, Offset::Current).vertex_idx,
         vertex_idx
       );
 
-      let next_edge = self.relative_get(half_edge_idx, Offset::NextNext);
-
-      if let Some(new_half_edge_idx) = next_edge.twin_idx {
-        half_edge_idx = new_half_edge_idx;
+      if let Some(twin_idx) =
+        self.relative_get(half_edge_idx, Offset::Current).twin_idx
+      {
+        half_edge_idx = self.relative_get(twin_idx, Offset::Current).next_idx;
       } else {
         has_boundary = true;
         break;
@@ -203,6 +262,53 @@ impl HalfEdge {
     )
   }
 
+  // vertex attributes for the vertex added by split_edge
+  fn push_split_vertex_attributes(
+    &mut self,
+    b_vertex_idx: IndexType,
+    c_vertex_idx: IndexType,
+  ) {
+    for attribute in &mut self.attributes.vertex {
+      attribute.push_mix(&[(b_vertex_idx, 0.5), (c_vertex_idx, 0.5)]);
+    }
+  }
+
+  fn edge_ref(&self, key: IndexType) -> Result<&EdgeRef, MeshError> {
+    self
+      .edge_refs
+      .get(key as usize)
+      .and_then(|edge| edge.as_ref())
+      .ok_or(MeshError::MissingEdge { edge: key })
+  }
+
+  // errors if either face next to the half edge isn't a triangle
+  fn check_edge_faces_triangles(
+    &self,
+    half_edge_idx: IndexType,
+  ) -> Result<(), MeshError> {
+    let half_edge = self.relative_get(half_edge_idx, Offset::Current);
+
+    let twin_face_idx = half_edge
+      .twin_idx
+      .map(|twin_idx| self.relative_get(twin_idx, Offset::Current).face_idx);
+
+    for face_idx in std::iter::once(half_edge.face_idx).chain(twin_face_idx) {
+      if !self.is_triangle(face_idx) {
+        return Err(MeshError::NonTriangleFace { face: face_idx });
+      }
+    }
+
+    Ok(())
+  }
+
+  fn is_triangle(&self, face_idx: IndexType) -> bool {
+    self.face_degree(face_idx) == 3
+  }
+
+  fn is_boundary_vertex(&self, vertex_idx: IndexType) -> bool {
+    self.get_start_iter_half_edge_idx(vertex_idx).0
+  }
+
   fn combine_twins(
     &mut self,
     first_half_edge_idx: IndexType,
@@ -210,40 +316,48 @@ impl HalfEdge {
     edge_idx: IndexType,
     vertex_idx: IndexType,
   ) {
-    // TODO: fix vertex and edge behavior in boundary case
-    if let Some(c_a_idx) = self
+    let c_a_idx_op = self
       .relative_get(first_half_edge_idx, Offset::Current)
-      .twin_idx
-    {
-      if let Some(a_d_idx) = self
-        .relative_get(second_half_edge_idx, Offset::Current)
-        .twin_idx
-      {
-        self.vertex_refs[vertex_idx as usize]
-          .as_mut()
-          .unwrap()
-          .half_edge_idx = a_d_idx;
-
-        self.edge_refs[edge_idx as usize]
-          .as_mut()
-          .unwrap()
-          .half_edge_idx = a_d_idx;
+      .twin_idx;
+    let a_d_idx_op = self
+      .relative_get(second_half_edge_idx, Offset::Current)
+      .twin_idx;
 
+    match (c_a_idx_op, a_d_idx_op) {
+      (Some(c_a_idx), Some(a_d_idx)) => {
         self.half_edge_refs[c_a_idx as usize].twin_idx = Some(a_d_idx);
         self.half_edge_refs[a_d_idx as usize].twin_idx = Some(c_a_idx);
         self.half_edge_refs[c_a_idx as usize].edge_idx = edge_idx;
         self.half_edge_refs[a_d_idx as usize].edge_idx = edge_idx;
-      } else {
+      }
+      (Some(c_a_idx), None) => {
         self.half_edge_refs[c_a_idx as usize].twin_idx = None;
         self.half_edge_refs[c_a_idx as usize].edge_idx = edge_idx;
       }
-    } else if let Some(a_d_idx) = self
-      .relative_get(second_half_edge_idx, Offset::Current)
-      .twin_idx
-    {
-      self.half_edge_refs[a_d_idx as usize].twin_idx = None;
-      self.half_edge_refs[a_d_idx as usize].edge_idx = edge_idx;
+      (None, Some(a_d_idx)) => {
+        self.half_edge_refs[a_d_idx as usize].twin_idx = None;
+        self.half_edge_refs[a_d_idx as usize].edge_idx = edge_idx;
+      }
+      (None, None) => unreachable!("collapse should have been rejected"),
     }
+
+    // if a->d doesn't exist, a->c's twin (c->a) is on the boundary and
+    // the next half edge starts at a
+    let vertex_half_edge_idx = a_d_idx_op.unwrap_or_else(|| {
+      self
+        .relative_get(c_a_idx_op.unwrap(), Offset::Current)
+        .next_idx
+    });
+
+    self.vertex_refs[vertex_idx as usize]
+      .as_mut()
+      .unwrap()
+      .half_edge_idx = vertex_half_edge_idx;
+
+    self.edge_refs[edge_idx as usize]
+      .as_mut()
+      .unwrap()
+      .half_edge_idx = a_d_idx_op.or(c_a_idx_op).unwrap();
   }
 
   fn verify_half_edge_valid(&self, half_edge_idx: IndexType) {
@@ -252,15 +366,25 @@ impl HalfEdge {
       debug_assert!(self.vertex_refs[half_edge.vertex_idx as usize].is_some());
       debug_assert!(self.edge_refs[half_edge.edge_idx as usize].is_some());
       debug_assert!(self.face_refs[half_edge.face_idx as usize].is_some());
-      debug_assert_eq!(
-        self.relative_get(half_edge_idx, Offset::Next).vertex_idx,
-        self.half_edge_refs[half_edge.twin_idx.unwrap() as usize].vertex_idx
-      );
+      if let Some(twin_idx) = half_edge.twin_idx {
+        let twin = &self.half_edge_refs[twin_idx as usize];
+        debug_assert_eq!(
+          self.relative_get(half_edge_idx, Offset::Next).vertex_idx,
+          twin.vertex_idx
+        );
+        debug_assert_eq!(twin.twin_idx, Some(half_edge_idx));
+        debug_assert_eq!(twin.edge_idx, half_edge.edge_idx);
+      }
 
-      debug_assert_eq!(
-        self.relative_get(half_edge_idx, Offset::NextNext).next_idx,
-        half_edge_idx
-      );
+      let face = self.face_refs[half_edge.face_idx as usize]
+        .as_ref()
+        .unwrap();
+
+      debug_assert!(self.face_half_edges(face).count() >= 3);
+      debug_assert!(self.face_half_edges(face).any(|idx| idx == half_edge_idx));
+      debug_assert!(self.face_half_edges(face).all(|idx| {
+        self.half_edge_refs[idx as usize].face_idx == half_edge.face_idx
+      }));
     }
   }
 
@@ -329,27 +453,19 @@ impl HalfEdge {
     }
   }
 
-  fn verify_face_valid(
-    &self,
-    face_idx: IndexType,
-    first_vertex_idx: IndexType,
-    second_vertex_idx: IndexType,
-    third_vertex_idx: IndexType,
-  ) {
+  fn verify_face_valid(&self, face_idx: IndexType, vertices: &[IndexType]) {
     if cfg!(debug_assertions) {
-      let [first, second, third] = self.get_face_neighbors(face_idx);
+      let mut face_vertices = Vec::new();
+      self.get_face_vertices(face_idx, &mut face_vertices);
 
       let vertex_hash_set =
-        HashSet::<IndexType>::from_iter([first, second, third].iter().cloned());
+        HashSet::<IndexType>::from_iter(face_vertices.iter().cloned());
 
-      debug_assert_eq!(vertex_hash_set.len(), 3);
+      debug_assert!(face_vertices.len() >= 3);
+      debug_assert_eq!(vertex_hash_set.len(), face_vertices.len());
       debug_assert_eq!(
         vertex_hash_set,
-        HashSet::<IndexType>::from_iter(
-          vec![first_vertex_idx, second_vertex_idx, third_vertex_idx]
-            .iter()
-            .cloned()
-        )
+        HashSet::<IndexType>::from_iter(vertices.iter().cloned())
       );
 
       debug_assert_eq!(
@@ -361,9 +477,9 @@ impl HalfEdge {
           .face_idx
       );
 
-      self.verify_vertex_valid(first);
-      self.verify_vertex_valid(second);
-      self.verify_vertex_valid(third);
+      for vertex_idx in face_vertices {
+        self.verify_vertex_valid(vertex_idx);
+      }
     }
   }
 
@@ -414,10 +530,11 @@ impl HalfEdge {
         .filter_map(|(i, x)| x.as_ref().map(|x| (i, x)));
 
       let mut true_count = 0;
+      let mut vertices = Vec::new();
       for (face_idx, _) in face_iter {
         true_count += 1;
         let face_idx = face_idx.try_into().unwrap();
-        let [v_0, v_1, v_2] = self.get_face_neighbors(face_idx);
+        self.get_face_vertices(face_idx, &mut vertices);
 
         let check_edge_exists = |v_l, v_r| {
           debug_assert!(
@@ -426,11 +543,11 @@ impl HalfEdge {
           );
         };
 
-        check_edge_exists(v_0, v_1);
-        check_edge_exists(v_0, v_2);
-        check_edge_exists(v_1, v_2);
+        for (i, v_l) in vertices.iter().enumerate() {
+          check_edge_exists(*v_l, vertices[(i + 1) % vertices.len()]);
+        }
 
-        self.verify_face_valid(face_idx, v_0, v_1, v_2);
+        self.verify_face_valid(face_idx, &vertices);
       }
 
       assert_eq!(true_count, self.num_faces());
@@ -452,56 +569,92 @@ impl HalfEdge {
 }
 
 impl DataStructure for HalfEdge {
-  fn from_iters<IterVert, IterFace>(vertices: IterVert, faces: IterFace) -> Self
+  fn try_from_iters<IterVert, IterFace>(
+    vertices: IterVert,
+    faces: IterFace,
+  ) -> Result<Self, MeshError>
   where
     IterVert: IntoIterator<Item = Vector3>,
     IterFace: IntoIterator<Item = Face>,
   {
     let vertices_vec: Vec<Vector3> = vertices.into_iter().collect();
 
-    let mut vertex_orig_idx_to_vertex_new_idx = HashMap::new();
     let mut vertex_pair_to_half_edge_idx = HashMap::new();
+    // every directed edge may only be used once
+    let mut used_vertex_pairs = HashSet::new();
+    let mut num_outgoing = vec![0; vertices_vec.len()];
 
     let mut half_edge_refs = Vec::<HalfEdgeRef>::new();
-    let mut vertex_refs = Vec::new();
+    // vertices keep their original indices (unreferenced vertices are
+    // treated as removed)
+    let mut vertex_refs = vec![None; vertices_vec.len()];
     let mut edge_refs = Vec::new();
     let mut face_refs = Vec::new();
 
     for face in faces.into_iter() {
       let face_idx = face_refs.len() as IndexType;
-      let next_vertex = [face[1], face[2], face[0]];
+
+      if let Some(vertex_idx) =
+        face.iter().find(|v| **v as usize >= vertices_vec.len())
+      {
+        return Err(MeshError::VertexIndexOutOfRange {
+          face: face_idx,
+          vertex: *vertex_idx,
+          num_vertices: vertices_vec.len(),
+        });
+      }
+
+      if HashSet::<&IndexType>::from_iter(face.iter()).len() != face.len()
+        || face.len() < 3
+      {
+        return Err(MeshError::DegenerateFace { face: face_idx });
+      }
+
+      let next_vertex = face.iter().cycle().skip(1);
       let start_idx = half_edge_refs.len() as IndexType;
-      let next_idxs = [start_idx + 1, start_idx + 2, start_idx];
+      let next_idxs = (1..face.len())
+        .map(|i| start_idx + i as IndexType)
+        .chain(std::iter::once(start_idx));
       for ((vertex_orig_idx, next_vertex_orig_idx), next_idx) in
-        face.iter().zip(next_vertex.iter()).zip(next_idxs.iter())
+        face.iter().zip(next_vertex).zip(next_idxs)
       {
         let half_edge_idx = half_edge_refs.len() as IndexType;
 
-        let vertex_idx =
-          match vertex_orig_idx_to_vertex_new_idx.get(vertex_orig_idx) {
-            Some(v) => *v,
-            None => {
-              let vertex_idx = vertex_refs.len() as IndexType;
-              vertex_orig_idx_to_vertex_new_idx
-                .insert(*vertex_orig_idx, vertex_idx);
-              vertex_refs.push(Some(VertexRef {
-                half_edge_idx,
-                vertex: vertices_vec[*vertex_orig_idx as usize],
-              }));
-              vertex_idx
-            }
-          };
-
-        let twin_idx = match vertex_pair_to_half_edge_idx
-          .get(&(*vertex_orig_idx, *next_vertex_orig_idx))
-        {
+        let vertex_idx = *vertex_orig_idx;
+
+        if vertex_refs[vertex_idx as usize].is_none() {
+          let vertex = vertices_vec[vertex_idx as usize];
+
+          if !vertex.iter().all(|v| v.is_finite()) {
+            return Err(MeshError::InvalidPosition { vertex: vertex_idx });
+          }
+
+          vertex_refs[vertex_idx as usize] = Some(VertexRef {
+            half_edge_idx,
+            vertex,
+          });
+        }
+
+        num_outgoing[vertex_idx as usize] += 1;
+
+        let vertex_pair = (*vertex_orig_idx, *next_vertex_orig_idx);
+
+        if !used_vertex_pairs.insert(vertex_pair) {
+          return Err(MeshError::NonManifoldEdge {
+            face: face_idx,
+            vertices: [vertex_pair.0, vertex_pair.1],
+          });
+        }
+
+        // removed once matched, so a third face on the edge is caught above
+        let twin_idx = match vertex_pair_to_half_edge_idx.remove(&vertex_pair) {
           None => {
             vertex_pair_to_half_edge_idx
               .insert((*next_vertex_orig_idx, *vertex_orig_idx), half_edge_idx);
             None
           }
           Some(v) => {
-            let twin_idx = *v;
+            let twin_idx = v;
             half_edge_refs[twin_idx as usize].twin_idx = Some(half_edge_idx);
 
             Some(twin_idx)
@@ -518,7 +671,7 @@ impl DataStructure for HalfEdge {
 
         half_edge_refs.push(HalfEdgeRef {
           twin_idx,
-          next_idx: *next_idx,
+          next_idx,
           vertex_idx,
           edge_idx,
           face_idx,
@@ -530,20 +683,33 @@ impl DataStructure for HalfEdge {
       }));
     }
 
+    let num_removed_vertices =
+      vertex_refs.iter().filter(|v| v.is_none()).count();
+
     let out = HalfEdge {
       half_edge_refs,
       vertex_refs,
       edge_refs,
       face_refs,
-      num_removed_vertices: 0,
+      num_removed_vertices,
       num_removed_edges: 0,
       num_removed_faces: 0,
       removed_half_edges: HashSet::new(),
+      attributes: Attributes::default(),
     };
 
+    // a vertex where separate fans meet can't be iterated around
+    for (vertex_idx, count) in num_outgoing.into_iter().enumerate() {
+      let vertex_idx = vertex_idx as IndexType;
+
+      if count != 0 && out.vertex_half_edges(vertex_idx).1.count() != count {
+        return Err(MeshError::NonManifoldVertex { vertex: vertex_idx });
+      }
+    }
+
     out.check_all();
 
-    out
+    Ok(out)
   }
 
   fn max_idx_vertices(&self) -> usize {
@@ -570,6 +736,123 @@ impl DataStructure for HalfEdge {
     self.face_refs.len() - self.num_removed_faces
   }
 
+  fn compact(&mut self) -> IndexRemap {
+    self.check_all();
+
+    fn get_remap<T>(vals: &[Option<T>]) -> Vec<Option<IndexType>> {
+      let mut count = 0;
+
+      vals
+        .iter()
+        .map(|v| {
+          v.as_ref().map(|_| {
+            count += 1;
+            count - 1
+          })
+        })
+        .collect()
+    }
+
+    let vertices = get_remap(&self.vertex_refs);
+    let edges = get_remap(&self.edge_refs);
+    let faces = get_remap(&self.face_refs);
+
+    // half edges of removed faces were removed
+    let half_edges: Vec<Option<IndexType>> = {
+      let mut count = 0;
+
+      self
+        .half_edge_refs
+        .iter()
+        .map(|half_edge| {
+          if self.face_refs[half_edge.face_idx as usize].is_some() {
+            count += 1;
+            Some(count - 1)
+          } else {
+            None
+          }
+        })
+        .collect()
+    };
+
+    let get = |remap: &[Option<IndexType>], idx: IndexType| {
+      remap[idx as usize].expect("reference to removed element")
+    };
+
+    self.half_edge_refs = self
+      .half_edge_refs
+      .iter()
+      .zip(half_edges.iter())
+      .filter(|(_, new_idx)| new_idx.is_some())
+      .map(|(half_edge, _)| HalfEdgeRef {
+        twin_idx: half_edge.twin_idx.map(|idx| get(&half_edges, idx)),
+        next_idx: get(&half_edges, half_edge.next_idx),
+        vertex_idx: get(&vertices, half_edge.vertex_idx),
+        edge_idx: get(&edges, half_edge.edge_idx),
+        face_idx: get(&faces, half_edge.face_idx),
+      })
+      .collect();
+
+    self.vertex_refs = self
+      .vertex_refs
+      .iter()
+      .filter_map(|v| v.as_ref())
+      .map(|v| {
+        Some(VertexRef {
+          half_edge_idx: get(&half_edges, v.half_edge_idx),
+          vertex: v.vertex,
+        })
+      })
+      .collect();
+
+    self.edge_refs = self
+      .edge_refs
+      .iter()
+      .filter_map(|e| e.as_ref())
+      .map(|e| {
+        Some(EdgeRef {
+          half_edge_idx: get(&half_edges, e.half_edge_idx),
+        })
+      })
+      .collect();
+
+    self.face_refs = self
+      .face_refs
+      .iter()
+      .filter_map(|f| f.as_ref())
+      .map(|f| {
+        Some(FaceRef {
+          half_edge_idx: get(&half_edges, f.half_edge_idx),
+        })
+      })
+      .collect();
+
+    let kept_vertices: Vec<bool> =
+      vertices.iter().map(Option::is_some).collect();
+    let kept_half_edges: Vec<bool> =
+      half_edges.iter().map(Option::is_some).collect();
+
+    for attribute in &mut self.attributes.vertex {
+      attribute.retain(&kept_vertices);
+    }
+    for attribute in &mut self.attributes.corner {
+      attribute.retain(&kept_half_edges);
+    }
+
+    self.num_removed_vertices = 0;
+    self.num_removed_edges = 0;
+    self.num_removed_faces = 0;
+    self.removed_half_edges.clear();
+
+    self.check_all();
+
+    IndexRemap {
+      vertices,
+      edges,
+      faces,
+    }
+  }
+
   fn initial_vertex(&self) -> Option<IndexType> {
     HalfEdge::get_at(0, &self.vertex_refs)
   }
@@ -578,6 +861,10 @@ impl DataStructure for HalfEdge {
     HalfEdge::get_at(key + 1, &self.vertex_refs)
   }
 
+  fn has_vertex(&self, key: IndexType) -> bool {
+    matches!(self.vertex_refs.get(key as usize), Some(Some(_)))
+  }
+
   fn initial_edge(&self) -> Option<IndexType> {
     HalfEdge::get_at(0, &self.edge_refs)
   }
@@ -594,11 +881,11 @@ impl DataStructure for HalfEdge {
     HalfEdge::get_at(key + 1, &self.face_refs)
   }
 
-  fn flip_edge(&mut self, key: IndexType) -> Option<()> {
+  fn try_flip_edge(&mut self, key: IndexType) -> Result<(), MeshError> {
     // see page 24 of lecture slides "meshes_geoprocessing" for
     // a,b,c,d reference
 
-    let edge = &self.edge_refs[key as usize].as_ref().unwrap();
+    let edge = self.edge_ref(key)?;
 
     let b_c_idx = edge.half_edge_idx;
     let b_c_half_edge = self.relative_get(b_c_idx, Offset::Current);
@@ -606,6 +893,12 @@ impl DataStructure for HalfEdge {
     if let Some(c_b_idx) = b_c_half_edge.twin_idx {
       let c_b_half_edge = &self.half_edge_refs[c_b_idx as usize];
 
+      for face_idx in &[b_c_half_edge.face_idx, c_b_half_edge.face_idx] {
+        if !self.is_triangle(*face_idx) {
+          return Err(MeshError::NonTriangleFace { face: *face_idx });
+        }
+      }
+
       let a_d_idx = c_b_idx;
       let d_a_idx = b_c_idx;
 
@@ -674,24 +967,36 @@ impl DataStructure for HalfEdge {
       self.verify_vertex_valid(c_vertex_idx);
       self.verify_vertex_valid(d_vertex_idx);
 
-      Some(())
+      // a->d and d->a now start at different vertices, so copy the corners
+      // of those vertices in the same face
+      for attribute in &mut self.attributes.corner {
+        attribute.set_mix(a_d_idx, &[(a_b_idx, 1.0)]);
+        attribute.set_mix(d_a_idx, &[(d_c_idx, 1.0)]);
+      }
+
+      Ok(())
     } else {
-      // later consider making this do nothing
-      panic!("AHHHH boundary");
+      // boundary edges can't be flipped
+      Err(MeshError::BoundaryEdge { edge: key })
     }
   }
 
-  fn split_edge(&mut self, key: IndexType) -> (IndexType, [IndexType; 4]) {
+  fn try_split_edge(
+    &mut self,
+    key: IndexType,
+  ) -> Result<(IndexType, [IndexType; 3], Option<IndexType>), MeshError> {
     // see page 26 of lecture slides "meshes_geoprocessing" for
-    // a,b,c,d,m reference
+    // a,b,c,d,m reference (d doesn't exist if the edge is on the boundary)
 
-    let edge = &self.edge_refs[key as usize].as_ref().unwrap();
+    let edge = self.edge_ref(key)?;
 
     // b->c will become m->c
     let b_c_idx = edge.half_edge_idx;
 
     let twin_idx = self.relative_get(b_c_idx, Offset::Current).twin_idx;
 
+    self.check_edge_faces_triangles(b_c_idx)?;
+
     // twin/second: c->b will become c->m
     if let Some(c_b_idx) = twin_idx {
       // HALF EDGES:
@@ -896,245 +1201,464 @@ impl DataStructure for HalfEdge {
       self.verify_vertex_valid(d_vertex_idx);
       self.verify_vertex_valid(m_vertex_idx);
 
-      (
+      self.push_split_vertex_attributes(b_vertex_idx, c_vertex_idx);
+
+      // corners of m are between the corners of b and c in the same face,
+      // other new corners copy the corner of their vertex in the old face
+      let mid_first = [(b_c_idx, 0.5), (c_a_idx, 0.5)];
+      let mid_second = [(c_b_idx, 0.5), (b_d_idx, 0.5)];
+
+      for attribute in &mut self.attributes.corner {
+        attribute.push_mix(&mid_second); // m->d
+        attribute.push_mix(&[(d_c_idx, 1.0)]); // d->m
+        attribute.push_mix(&mid_second); // m->b
+        attribute.push_mix(&[(b_c_idx, 1.0)]); // b->m
+        attribute.push_mix(&mid_first); // m->a
+        attribute.push_mix(&[(a_b_idx, 1.0)]); // a->m
+        attribute.set_mix(m_c_idx, &mid_first);
+      }
+
+      Ok((
         m_vertex_idx,
-        [m_a_edge_idx, m_d_edge_idx, m_c_edge_idx, m_b_edge_idx],
-      )
+        [m_a_edge_idx, m_c_edge_idx, m_b_edge_idx],
+        Some(m_d_edge_idx),
+      ))
     } else {
-      // later consider making this just split on one side
-      panic!("AHHHH boundary");
-    }
-  }
-
-  // Very complex I'm afraid...
-  #[allow(clippy::cognitive_complexity)]
-  fn collapse_edge(
-    &mut self,
-    key: IndexType,
-    modified_edges: &mut Vec<(IndexType, IndexType)>,
-    removed_edges: &mut Vec<IndexType>,
-  ) -> Option<IndexType> {
-    modified_edges.clear();
-    removed_edges.clear();
+      // only the face on one side is split
 
-    // see page 28 of lecture slides "meshes_geoprocessing" for
-    // a,b,c,d,m reference
+      let m_c_idx = b_c_idx;
 
-    let edge = &self.edge_refs[key as usize].as_ref().unwrap();
+      let c_a_idx = self.relative_get(b_c_idx, Offset::Current).next_idx;
+      let a_b_idx = self.relative_get(b_c_idx, Offset::Next).next_idx;
 
-    // c->d will be removed
-    let c_d_idx = edge.half_edge_idx;
+      // Order of new half edges is:
+      //  - m->b
+      //  - b->m
+      //  - m->a
+      //  - a->m
+      let b_m_idx = self.half_edge_refs.len() as IndexType;
+      let m_a_idx = (self.half_edge_refs.len() + 1) as IndexType;
+      let a_m_idx = (self.half_edge_refs.len() + 2) as IndexType;
 
-    let twin_idx = self.relative_get(c_d_idx, Offset::Current).twin_idx;
+      // VERTICES:
 
-    if let Some(d_c_idx) = twin_idx {
-      // remove d->a
-      let d_a_idx = self.relative_get(c_d_idx, Offset::Current).next_idx;
-      // remove a->c
-      let a_c_idx = self.relative_get(c_d_idx, Offset::Next).next_idx;
+      let a_vertex_idx = self.relative_get(a_b_idx, Offset::Current).vertex_idx;
+      let b_vertex_idx = self.relative_get(b_c_idx, Offset::Current).vertex_idx;
+      let c_vertex_idx = self.relative_get(c_a_idx, Offset::Current).vertex_idx;
 
-      // remove c->b
-      let c_b_idx = self.relative_get(d_c_idx, Offset::Current).next_idx;
-      // remove b->d
-      let b_d_idx = self.relative_get(d_c_idx, Offset::Next).next_idx;
+      self.verify_half_edge_valid(c_a_idx);
+      self.verify_half_edge_valid(a_b_idx);
+      self.verify_half_edge_valid(b_c_idx);
 
-      // will be removed
-      let c_vertex_idx = self.relative_get(c_d_idx, Offset::Current).vertex_idx;
-      // will become m
-      let d_vertex_idx = self.relative_get(d_c_idx, Offset::Current).vertex_idx;
-      let a_vertex_idx = self.relative_get(a_c_idx, Offset::Current).vertex_idx;
-      let b_vertex_idx = self.relative_get(b_d_idx, Offset::Current).vertex_idx;
+      self.verify_vertex_valid(a_vertex_idx);
+      self.verify_vertex_valid(b_vertex_idx);
+      self.verify_vertex_valid(c_vertex_idx);
 
-      debug_assert_ne!(a_vertex_idx, c_vertex_idx);
-      debug_assert_ne!(b_vertex_idx, c_vertex_idx);
-      debug_assert_ne!(d_vertex_idx, c_vertex_idx);
-      debug_assert_ne!(a_vertex_idx, d_vertex_idx);
-      debug_assert_ne!(b_vertex_idx, d_vertex_idx);
-      debug_assert_ne!(a_vertex_idx, b_vertex_idx);
+      // ensure still valid
+      self.vertex_refs[b_vertex_idx as usize]
+        .as_mut()
+        .unwrap()
+        .half_edge_idx = b_m_idx;
+      self.vertex_refs[c_vertex_idx as usize]
+        .as_mut()
+        .unwrap()
+        .half_edge_idx = c_a_idx;
 
-      // SPEED: too much memory allocation inside hot portion...
-      let mut store = Vec::new();
-      self.get_vertex_neighbors(c_vertex_idx, &mut store);
-      let c_neighbors = HashSet::<IndexType>::from_iter(store);
-      let mut store = Vec::new();
-      self.get_vertex_neighbors(d_vertex_idx, &mut store);
-      let d_neighbors = HashSet::<IndexType>::from_iter(store);
+      // new vertex (m)
+      let m_vertex_idx = self.vertex_refs.len() as IndexType;
 
-      let mut num_common = 0;
+      // add vertex m (for now copy of b)
+      self.vertex_refs.push(Some(VertexRef {
+        half_edge_idx: m_c_idx,
+        vertex: self.vertex_refs[b_vertex_idx as usize]
+          .as_ref()
+          .unwrap()
+          .vertex,
+      }));
 
-      for vertex_idx in c_neighbors.intersection(&d_neighbors) {
-        num_common += 1;
-        if self.degree(*vertex_idx) <= 3 {
-          return None;
-        }
-      }
+      // EDGES:
 
-      if num_common > 2 {
-        return None;
-      }
+      // Order of new edges is:
+      //  - m<->b
+      //  - m<->a
+      let m_c_edge_idx = key;
+      let m_b_edge_idx = self.edge_refs.len() as IndexType;
+      let m_a_edge_idx = (self.edge_refs.len() + 1) as IndexType;
 
-      let m_vertex_idx = d_vertex_idx;
+      // m_b
+      self.edge_refs.push(Some(EdgeRef {
+        half_edge_idx: b_m_idx,
+      }));
+      // m_a
+      self.edge_refs.push(Some(EdgeRef {
+        half_edge_idx: m_a_idx,
+      }));
 
-      let (_, half_edge_iter) = self.vertex_half_edges(d_vertex_idx);
+      // FACES:
 
-      for half_edge_idx in half_edge_iter {
-        debug_assert_eq!(
-          self.relative_get(half_edge_idx, Offset::Current).vertex_idx,
-          d_vertex_idx
-        );
+      // cab becomes mca
+      let m_c_a_face_idx = self.half_edge_refs[m_c_idx as usize].face_idx;
 
-        let other_edge_vertex =
-          self.relative_get(half_edge_idx, Offset::Next).vertex_idx;
+      // new face is mab
+      let m_a_b_face_idx = self.face_refs.len() as IndexType;
 
-        let edge = self.relative_get(half_edge_idx, Offset::Current).edge_idx;
+      self.face_refs[m_c_a_face_idx as usize] = Some(FaceRef {
+        half_edge_idx: m_c_idx,
+      });
+      // mab
+      self.face_refs.push(Some(FaceRef {
+        half_edge_idx: m_a_idx,
+      }));
 
-        if other_edge_vertex != c_vertex_idx {
-          modified_edges.push((edge, other_edge_vertex));
-        }
-      }
+      self.half_edge_refs[m_c_idx as usize].vertex_idx = m_vertex_idx;
 
-      // this can't use iter because it must mutate
-      {
-        let (_, half_edge_idx) =
-          self.get_start_iter_half_edge_idx(c_vertex_idx);
+      self.half_edge_refs[a_b_idx as usize].face_idx = m_a_b_face_idx;
+      self.half_edge_refs[a_b_idx as usize].next_idx = b_m_idx;
 
-        let mut half_edge_idx_in =
-          self.relative_get(half_edge_idx, Offset::Next).next_idx;
-        let half_edge_idx_orig_in = half_edge_idx_in;
+      self.half_edge_refs[c_a_idx as usize].next_idx = a_m_idx;
 
-        let mut first = true;
+      // b -> m
+      self.half_edge_refs.push(HalfEdgeRef {
+        twin_idx: None,
+        next_idx: m_a_idx,
+        vertex_idx: b_vertex_idx,
+        edge_idx: m_b_edge_idx,
+        face_idx: m_a_b_face_idx,
+      });
 
-        while first || half_edge_idx_in != half_edge_idx_orig_in {
-          let this_edge_idx = self
-            .relative_get(half_edge_idx_in, Offset::Current)
-            .next_idx;
+      // m -> a
+      self.half_edge_refs.push(HalfEdgeRef {
+        twin_idx: Some(a_m_idx),
+        next_idx: a_b_idx,
+        vertex_idx: m_vertex_idx,
+        edge_idx: m_a_edge_idx,
+        face_idx: m_a_b_face_idx,
+      });
 
-          debug_assert_eq!(
-            self.relative_get(this_edge_idx, Offset::Current).vertex_idx,
-            c_vertex_idx
-          );
+      // a -> m
+      self.half_edge_refs.push(HalfEdgeRef {
+        twin_idx: Some(m_a_idx),
+        next_idx: m_c_idx,
+        vertex_idx: a_vertex_idx,
+        edge_idx: m_a_edge_idx,
+        face_idx: m_c_a_face_idx,
+      });
 
-          let other_edge_vertex =
-            self.relative_get(this_edge_idx, Offset::Next).vertex_idx;
+      self.verify_half_edge_valid(c_a_idx);
+      self.verify_half_edge_valid(a_b_idx);
+      self.verify_half_edge_valid(b_c_idx);
+      self.verify_half_edge_valid(b_m_idx);
+      self.verify_half_edge_valid(m_a_idx);
+      self.verify_half_edge_valid(a_m_idx);
 
-          debug_assert!(c_neighbors.contains(&other_edge_vertex));
+      self.verify_vertex_valid(a_vertex_idx);
+      self.verify_vertex_valid(b_vertex_idx);
+      self.verify_vertex_valid(c_vertex_idx);
+      self.verify_vertex_valid(m_vertex_idx);
 
-          let edge = self.relative_get(this_edge_idx, Offset::Current).edge_idx;
+      self.push_split_vertex_attributes(b_vertex_idx, c_vertex_idx);
 
-          if d_neighbors.contains(&other_edge_vertex)
-            || other_edge_vertex == d_vertex_idx
-          {
-            removed_edges.push(edge);
-            self.edge_refs[edge as usize] = None;
-            if cfg!(debug_assertions)
-              && other_edge_vertex != a_vertex_idx
-              && other_edge_vertex != b_vertex_idx
-            {
-              self.removed_half_edges.insert(this_edge_idx);
-
-              if let Some(twin_idx) =
-                self.relative_get(this_edge_idx, Offset::Current).twin_idx
-              {
-                self.removed_half_edges.insert(twin_idx);
-              }
-            }
-          } else {
-            modified_edges.push((edge, other_edge_vertex));
-          }
+      let mid = [(b_c_idx, 0.5), (c_a_idx, 0.5)];
+
+      for attribute in &mut self.attributes.corner {
+        attribute.push_mix(&[(b_c_idx, 1.0)]); // b->m
+        attribute.push_mix(&mid); // m->a
+        attribute.push_mix(&[(a_b_idx, 1.0)]); // a->m
+        attribute.set_mix(m_c_idx, &mid);
+      }
 
-          self.half_edge_refs[this_edge_idx as usize].vertex_idx = m_vertex_idx;
+      Ok((
+        m_vertex_idx,
+        [m_a_edge_idx, m_c_edge_idx, m_b_edge_idx],
+        None,
+      ))
+    }
+  }
 
-          if let Some(new_half_edge_idx_in) =
-            self.relative_get(half_edge_idx_in, Offset::Next).twin_idx
+  // Very complex I'm afraid...
+  #[allow(clippy::cognitive_complexity)]
+  fn try_collapse_edge_at(
+    &mut self,
+    key: IndexType,
+    t: f32,
+    modified_edges: &mut Vec<(IndexType, IndexType)>,
+    removed_edges: &mut Vec<IndexType>,
+  ) -> Result<IndexType, MeshError> {
+    modified_edges.clear();
+    removed_edges.clear();
+
+    // see page 28 of lecture slides "meshes_geoprocessing" for
+    // a,b,c,d,m reference (b doesn't exist if the edge is on the boundary)
+
+    let edge = self.edge_ref(key)?;
+
+    // c->d will be removed
+    let c_d_idx = edge.half_edge_idx;
+
+    // d->c will be removed (if it exists)
+    let d_c_idx_op = self.relative_get(c_d_idx, Offset::Current).twin_idx;
+
+    self.check_edge_faces_triangles(c_d_idx)?;
+
+    // remove d->a
+    let d_a_idx = self.relative_get(c_d_idx, Offset::Current).next_idx;
+    // remove a->c
+    let a_c_idx = self.relative_get(c_d_idx, Offset::Next).next_idx;
+
+    // remove c->b and b->d
+    let c_b_b_d_idx_op = d_c_idx_op.map(|d_c_idx| {
+      (
+        self.relative_get(d_c_idx, Offset::Current).next_idx,
+        self.relative_get(d_c_idx, Offset::Next).next_idx,
+      )
+    });
+
+    // will be removed
+    let c_vertex_idx = self.relative_get(c_d_idx, Offset::Current).vertex_idx;
+    // will become m
+    let d_vertex_idx = self.relative_get(d_a_idx, Offset::Current).vertex_idx;
+    let a_vertex_idx = self.relative_get(a_c_idx, Offset::Current).vertex_idx;
+    let b_vertex_idx_op = c_b_b_d_idx_op.map(|(_, b_d_idx)| {
+      self.relative_get(b_d_idx, Offset::Current).vertex_idx
+    });
+
+    debug_assert_ne!(a_vertex_idx, c_vertex_idx);
+    debug_assert_ne!(d_vertex_idx, c_vertex_idx);
+    debug_assert_ne!(a_vertex_idx, d_vertex_idx);
+    if let Some(b_vertex_idx) = b_vertex_idx_op {
+      debug_assert_ne!(b_vertex_idx, c_vertex_idx);
+      debug_assert_ne!(b_vertex_idx, d_vertex_idx);
+      debug_assert_ne!(a_vertex_idx, b_vertex_idx);
+    }
+
+    // SPEED: too much memory allocation inside hot portion...
+    let mut c_edges = Vec::new();
+    let c_boundary = self.get_vertex_edges_append(c_vertex_idx, &mut c_edges);
+    let c_neighbors =
+      HashSet::<IndexType>::from_iter(c_edges.iter().map(|x| x.1));
+    let mut d_edges = Vec::new();
+    let d_boundary = self.get_vertex_edges_append(d_vertex_idx, &mut d_edges);
+    let d_neighbors =
+      HashSet::<IndexType>::from_iter(d_edges.iter().map(|x| x.1));
+
+    // an interior edge between two boundary vertices would collapse to a
+    // non manifold vertex
+    if d_c_idx_op.is_some() && c_boundary && d_boundary {
+      return Err(MeshError::CollapseRejected {
+        edge: key,
+        reason: "interior edge connects two boundary vertices",
+      });
+    }
+
+    let mut num_common = 0;
+
+    for vertex_idx in c_neighbors.intersection(&d_neighbors) {
+      num_common += 1;
+
+      let min_degree = if self.is_boundary_vertex(*vertex_idx) {
+        2
+      } else {
+        3
+      };
+
+      if self.degree(*vertex_idx) <= min_degree {
+        return Err(MeshError::CollapseRejected {
+          edge: key,
+          reason: "a common neighbor would be left with too few edges",
+        });
+      }
+    }
+
+    if num_common > if d_c_idx_op.is_some() { 2 } else { 1 } {
+      return Err(MeshError::CollapseRejected {
+        edge: key,
+        reason: "endpoints share neighbors outside the adjacent faces",
+      });
+    }
+
+    // c is the first endpoint, corners at m are between the corners of c and
+    // d on the same side: each removed face pairs a corner of c with one of d
+    // and the other corners of c and d join the side with the same value
+    // (corners past a seam which matches neither side keep their value)
+    if !self.attributes.corner.is_empty() {
+      let mut sides = vec![(c_d_idx, d_a_idx)];
+
+      if let (Some(d_c_idx), Some((c_b_idx, _))) = (d_c_idx_op, c_b_b_d_idx_op)
+      {
+        sides.push((c_b_idx, d_c_idx));
+      }
+
+      let c_corners: Vec<IndexType> = self
+        .vertex_half_edges(c_vertex_idx)
+        .1
+        .filter(|idx| sides.iter().all(|(c_idx, _)| c_idx != idx))
+        .collect();
+      let d_corners: Vec<IndexType> = self
+        .vertex_half_edges(d_vertex_idx)
+        .1
+        .filter(|idx| sides.iter().all(|(_, d_idx)| d_idx != idx))
+        .collect();
+
+      for attribute in &mut self.attributes.corner {
+        for corner in &c_corners {
+          if let Some((_, d_idx)) = sides
+            .iter()
+            .find(|(c_idx, _)| attribute.get(*c_idx) == attribute.get(*corner))
           {
-            half_edge_idx_in = new_half_edge_idx_in;
-          } else {
-            panic!("boundary!!!");
-            // break;
+            attribute.set_mix(*corner, &[(*corner, 1.0 - t), (*d_idx, t)]);
           }
+        }
 
-          first = false;
+        for corner in &d_corners {
+          if let Some((c_idx, _)) = sides
+            .iter()
+            .find(|(_, d_idx)| attribute.get(*d_idx) == attribute.get(*corner))
+          {
+            attribute.set_mix(*corner, &[(*c_idx, 1.0 - t), (*corner, t)]);
+          }
         }
       }
+    }
 
-      if cfg!(debug_assertions) {
-        self.removed_half_edges.insert(d_a_idx);
+    let m_vertex_idx = d_vertex_idx;
+
+    for (edge_idx, other_edge_vertex) in &d_edges {
+      if *other_edge_vertex != c_vertex_idx {
+        modified_edges.push((*edge_idx, *other_edge_vertex));
+      }
+    }
+
+    // collect because this must mutate
+    let c_half_edges: Vec<IndexType> =
+      self.vertex_half_edges(c_vertex_idx).1.collect();
+
+    for half_edge_idx in c_half_edges {
+      self.half_edge_refs[half_edge_idx as usize].vertex_idx = m_vertex_idx;
+    }
+
+    for (edge_idx, other_edge_vertex) in c_edges {
+      debug_assert!(c_neighbors.contains(&other_edge_vertex));
+
+      if d_neighbors.contains(&other_edge_vertex)
+        || other_edge_vertex == d_vertex_idx
+      {
+        removed_edges.push(edge_idx);
+        self.edge_refs[edge_idx as usize] = None;
+      } else {
+        modified_edges.push((edge_idx, other_edge_vertex));
+      }
+    }
+
+    if cfg!(debug_assertions) {
+      self.removed_half_edges.insert(d_a_idx);
+      self.removed_half_edges.insert(c_d_idx);
+      self.removed_half_edges.insert(a_c_idx);
+      if let (Some(d_c_idx), Some((c_b_idx, b_d_idx))) =
+        (d_c_idx_op, c_b_b_d_idx_op)
+      {
         self.removed_half_edges.insert(b_d_idx);
-        self.removed_half_edges.insert(c_d_idx);
-        self.removed_half_edges.insert(a_c_idx);
         self.removed_half_edges.insert(c_b_idx);
         self.removed_half_edges.insert(d_c_idx);
       }
+    }
 
-      self.vertex_refs[c_vertex_idx as usize] = None;
+    self.vertex_refs[c_vertex_idx as usize] = None;
 
-      self.num_removed_vertices += 1;
+    self.num_removed_vertices += 1;
 
-      // TODO (boundary)
-      let m_b_idx = self.relative_get(b_d_idx, Offset::Current).twin_idx;
+    // some half edge leaving m which isn't removed
+    let m_half_edge_idx = c_b_b_d_idx_op
+      .and_then(|(_, b_d_idx)| {
+        self.relative_get(b_d_idx, Offset::Current).twin_idx
+      })
+      .or_else(|| self.relative_get(a_c_idx, Offset::Current).twin_idx)
+      .or_else(|| {
+        self
+          .relative_get(d_a_idx, Offset::Current)
+          .twin_idx
+          .map(|a_d_idx| self.relative_get(a_d_idx, Offset::Current).next_idx)
+      })
+      .unwrap();
+
+    self.vertex_refs[m_vertex_idx as usize]
+      .as_mut()
+      .unwrap()
+      .half_edge_idx = m_half_edge_idx;
 
-      assert!(m_b_idx.is_some());
+    // d<->a retained
+    let d_a_edge_idx = self.relative_get(d_a_idx, Offset::Current).edge_idx;
+    let m_a_edge_idx = d_a_edge_idx;
 
-      self.vertex_refs[m_vertex_idx as usize] =
-        m_b_idx.map(|half_edge_idx| VertexRef {
-          vertex: self.vertex_refs[m_vertex_idx as usize]
-            .as_ref()
-            .unwrap()
-            .vertex,
-          half_edge_idx,
-        });
+    if cfg!(debug_assertions) {
+      // c<->a removed
+      let c_a_edge_idx = self.relative_get(a_c_idx, Offset::Current).edge_idx;
+      // c<->d removed
+      let c_d_edge_idx = self.relative_get(c_d_idx, Offset::Current).edge_idx;
+
+      debug_assert!(self.edge_refs[c_a_edge_idx as usize].is_none());
+      debug_assert!(self.edge_refs[c_d_edge_idx as usize].is_none());
+
+      debug_assert!(self.edge_refs[d_a_edge_idx as usize].is_some());
+
+      debug_assert_eq!(
+        removed_edges.len(),
+        if d_c_idx_op.is_some() { 3 } else { 2 }
+      );
+    }
+
+    self.num_removed_edges += removed_edges.len();
 
-      // d<->a retained
-      let d_a_edge_idx = self.relative_get(d_a_idx, Offset::Current).edge_idx;
+    // faces are removed
+    let c_a_d_face_idx = self.relative_get(c_d_idx, Offset::Current).face_idx;
+
+    self.face_refs[c_a_d_face_idx as usize] = None;
+
+    self.num_removed_faces += 1;
+
+    // reassign half_edges which had vertex c
+    self.combine_twins(a_c_idx, d_a_idx, m_a_edge_idx, a_vertex_idx);
+
+    if let (Some(d_c_idx), Some((c_b_idx, b_d_idx)), Some(b_vertex_idx)) =
+      (d_c_idx_op, c_b_b_d_idx_op, b_vertex_idx_op)
+    {
       // b<->d retained
       let b_d_edge_idx = self.relative_get(b_d_idx, Offset::Current).edge_idx;
-      let m_a_edge_idx = d_a_edge_idx;
       let b_m_edge_idx = b_d_edge_idx;
 
       if cfg!(debug_assertions) {
-        // c<->a removed
-        let c_a_edge_idx = self.relative_get(a_c_idx, Offset::Current).edge_idx;
         // b<->c removed
         let b_c_edge_idx = self.relative_get(c_b_idx, Offset::Current).edge_idx;
-        // c<->d removed
-        let c_d_edge_idx = self.relative_get(c_d_idx, Offset::Current).edge_idx;
 
-        debug_assert!(self.edge_refs[c_a_edge_idx as usize].is_none());
         debug_assert!(self.edge_refs[b_c_edge_idx as usize].is_none());
-        debug_assert!(self.edge_refs[c_d_edge_idx as usize].is_none());
-
-        debug_assert!(self.edge_refs[d_a_edge_idx as usize].is_some());
         debug_assert!(self.edge_refs[b_d_edge_idx as usize].is_some());
       }
 
-      self.num_removed_edges += 3;
-
-      // faces are removed
-      let c_a_d_face_idx = self.relative_get(c_d_idx, Offset::Current).face_idx;
       let c_b_d_face_idx = self.relative_get(d_c_idx, Offset::Current).face_idx;
 
-      self.face_refs[c_a_d_face_idx as usize] = None;
       self.face_refs[c_b_d_face_idx as usize] = None;
 
-      self.num_removed_faces += 2;
+      self.num_removed_faces += 1;
 
-      // reassign half_edges which had vertex c
-      self.combine_twins(a_c_idx, d_a_idx, m_a_edge_idx, a_vertex_idx);
       self.combine_twins(b_d_idx, c_b_idx, b_m_edge_idx, b_vertex_idx);
 
-      self.verify_vertex_valid(m_vertex_idx);
-      self.verify_vertex_valid(a_vertex_idx);
       self.verify_vertex_valid(b_vertex_idx);
-      self.verify_edge_valid(m_a_edge_idx, m_vertex_idx, a_vertex_idx);
       self.verify_edge_valid(b_m_edge_idx, m_vertex_idx, b_vertex_idx);
 
-      debug_assert!(modified_edges.contains(&(m_a_edge_idx, a_vertex_idx)));
       debug_assert!(modified_edges.contains(&(b_m_edge_idx, b_vertex_idx)));
-
-      Some(m_vertex_idx)
-    } else {
-      panic!("AHHHH boundary");
     }
+
+    self.verify_vertex_valid(m_vertex_idx);
+    self.verify_vertex_valid(a_vertex_idx);
+    self.verify_edge_valid(m_a_edge_idx, m_vertex_idx, a_vertex_idx);
+
+    debug_assert!(modified_edges.contains(&(m_a_edge_idx, a_vertex_idx)));
+
+    self.mix_vertex_attributes(
+      m_vertex_idx,
+      &[(c_vertex_idx, 1.0 - t), (d_vertex_idx, t)],
+    );
+
+    Ok(m_vertex_idx)
   }
 
   fn set_position(&mut self, key: IndexType, position: &Vector3) {
@@ -1146,7 +1670,9 @@ impl DataStructure for HalfEdge {
   }
 
   fn degree(&self, vertex_idx: IndexType) -> usize {
-    self.vertex_half_edges(vertex_idx).1.count()
+    let (has_boundary, half_edge_iter) = self.vertex_half_edges(vertex_idx);
+
+    half_edge_iter.count() + if has_boundary { 1 } else { 0 }
   }
 
   fn get_vertex_neighbors_append(
@@ -1156,8 +1682,49 @@ impl DataStructure for HalfEdge {
   ) -> bool {
     let (has_boundary, half_edge_iter) = self.vertex_half_edges(key);
 
+    let mut last_half_edge_idx = None;
+
     for half_edge_idx in half_edge_iter {
       neighbors.push(self.relative_get(half_edge_idx, Offset::Next).vertex_idx);
+      last_half_edge_idx = Some(half_edge_idx);
+    }
+
+    if has_boundary {
+      // the last neighbor is only connected by a half edge pointing into
+      // this vertex
+      neighbors.push(
+        self
+          .relative_get(last_half_edge_idx.unwrap(), Offset::Prev)
+          .vertex_idx,
+      );
+    }
+
+    has_boundary
+  }
+
+  fn get_vertex_edges_append(
+    &self,
+    vertex_idx: IndexType,
+    edges: &mut Vec<(IndexType, IndexType)>,
+  ) -> bool {
+    let (has_boundary, half_edge_iter) = self.vertex_half_edges(vertex_idx);
+
+    let mut last_half_edge_idx = None;
+
+    for half_edge_idx in half_edge_iter {
+      let half_edge = self.relative_get(half_edge_idx, Offset::Current);
+      edges.push((half_edge.edge_idx, self.get_next(half_edge).vertex_idx));
+      last_half_edge_idx = Some(half_edge_idx);
+    }
+
+    if has_boundary {
+      // the last edge only has a half edge pointing into the vertex
+      let half_edge =
+        self.relative_get(last_half_edge_idx.unwrap(), Offset::Prev);
+
+      debug_assert!(half_edge.twin_idx.is_none());
+
+      edges.push((half_edge.edge_idx, half_edge.vertex_idx));
     }
 
     has_boundary
@@ -1226,7 +1793,51 @@ impl DataStructure for HalfEdge {
     self.get_face_ref_neighbors(self.face_refs[key as usize].as_ref().unwrap())
   }
 
-  fn to_vecs(self) -> (Vec<Vector3>, Vec<Face>) {
+  fn get_face_vertices(&self, key: IndexType, vertices: &mut Vec<IndexType>) {
+    vertices.clear();
+
+    let face = self.face_refs[key as usize].as_ref().unwrap();
+
+    vertices.extend(
+      self
+        .face_half_edges(face)
+        .map(|idx| self.half_edge_refs[idx as usize].vertex_idx),
+    );
+  }
+
+  fn get_face_edges(&self, key: IndexType, edges: &mut Vec<IndexType>) {
+    edges.clear();
+
+    let face = self.face_refs[key as usize].as_ref().unwrap();
+
+    edges.extend(
+      self
+        .face_half_edges(face)
+        .map(|idx| self.half_edge_refs[idx as usize].edge_idx),
+    );
+  }
+
+  fn face_degree(&self, key: IndexType) -> usize {
+    self
+      .face_half_edges(self.face_refs[key as usize].as_ref().unwrap())
+      .count()
+  }
+
+  fn get_edge_faces(&self, key: IndexType) -> (IndexType, Option<IndexType>) {
+    let half_edge = self.relative_get(
+      self.edge_refs[key as usize].as_ref().unwrap().half_edge_idx,
+      Offset::Current,
+    );
+
+    (
+      half_edge.face_idx,
+      half_edge
+        .twin_idx
+        .map(|twin_idx| self.relative_get(twin_idx, Offset::Current).face_idx),
+    )
+  }
+
+  fn to_vecs(&self) -> (Vec<Vector3>, Vec<Face>) {
     self.check_all();
 
     // SPEED: GROSS
@@ -1248,19 +1859,455 @@ impl DataStructure for HalfEdge {
         .filter_map(|v| v.as_ref())
         .map(|v| v.vertex)
         .collect(),
-      (0..(self.face_refs.len() as IndexType))
-        .map(|i| &self.face_refs[i as usize])
+      self
+        .face_refs
+        .iter()
         .filter_map(|x| x.as_ref())
-        .map(|v| {
+        .map(|face| {
           self
-            .get_face_ref_neighbors(&v)
-            .iter()
-            .map(|index| exclusive_sum[*index as usize])
-            .collect::<Vec<IndexType>>()[..]
-            .try_into()
-            .unwrap()
+            .face_half_edges(face)
+            .map(|idx| {
+              exclusive_sum
+                [self.half_edge_refs[idx as usize].vertex_idx as usize]
+            })
+            .collect()
         })
         .collect(),
     )
   }
+
+  fn to_vecs_with_attributes(&self) -> (Vec<Vector3>, Vec<Face>, Attributes) {
+    let (vertices, faces) = self.to_vecs();
+
+    let vertex_idxs: Vec<IndexType> = self
+      .vertex_refs
+      .iter()
+      .enumerate()
+      .filter(|(_, v)| v.is_some())
+      .map(|(idx, _)| idx as IndexType)
+      .collect();
+
+    // same order as to_vecs
+    let corner_idxs: Vec<IndexType> = self
+      .face_refs
+      .iter()
+      .filter_map(|x| x.as_ref())
+      .flat_map(|face| self.face_half_edges(face))
+      .collect();
+
+    let select = |attributes: &[Attribute], idxs: &[IndexType]| {
+      attributes
+        .iter()
+        .map(|attribute| {
+          let mut out = Attribute::new(&attribute.name, attribute.dimension);
+          for idx in idxs {
+            out.push(attribute.get(*idx));
+          }
+          out
+        })
+        .collect()
+    };
+
+    let attributes = Attributes {
+      vertex: select(&self.attributes.vertex, &vertex_idxs),
+      corner: select(&self.attributes.corner, &corner_idxs),
+    };
+
+    (vertices, faces, attributes)
+  }
+
+  fn set_attributes(
+    &mut self,
+    attributes: Attributes,
+  ) -> Result<(), MeshError> {
+    let num_vertices = self.vertex_refs.len();
+
+    let corner_idxs: Vec<IndexType> = self
+      .face_refs
+      .iter()
+      .filter_map(|x| x.as_ref())
+      .flat_map(|face| self.face_half_edges(face))
+      .collect();
+
+    let wrong_len = |attribute: &Attribute, expected: usize, kind: &str| {
+      MeshError::InvalidArgument(format!(
+        "{} attribute \"{}\" has {} values, expected {}",
+        kind,
+        attribute.name,
+        attribute.len(),
+        expected
+      ))
+    };
+
+    for attribute in &attributes.vertex {
+      if attribute.len() != num_vertices {
+        return Err(wrong_len(attribute, num_vertices, "vertex"));
+      }
+    }
+
+    let mut corner = Vec::with_capacity(attributes.corner.len());
+
+    for attribute in &attributes.corner {
+      if attribute.len() != corner_idxs.len() {
+        return Err(wrong_len(attribute, corner_idxs.len(), "corner"));
+      }
+
+      // reorder by half edge
+      let mut by_half_edge =
+        Attribute::new(&attribute.name, attribute.dimension);
+      by_half_edge.values =
+        vec![0.0; self.half_edge_refs.len() * attribute.dimension];
+
+      for (corner_idx, half_edge_idx) in corner_idxs.iter().enumerate() {
+        by_half_edge
+          .set(*half_edge_idx, attribute.get(corner_idx as IndexType));
+      }
+
+      corner.push(by_half_edge);
+    }
+
+    self.attributes = Attributes {
+      vertex: attributes.vertex,
+      corner,
+    };
+
+    Ok(())
+  }
+
+  fn has_attributes(&self) -> bool {
+    !self.attributes.is_empty()
+  }
+
+  fn mix_vertex_attributes(
+    &mut self,
+    target: IndexType,
+    sources: &[(IndexType, f32)],
+  ) {
+    for attribute in &mut self.attributes.vertex {
+      attribute.set_mix(target, sources);
+    }
+  }
+
+  fn attribute_dimensions(&self) -> [usize; 2] {
+    let total =
+      |attributes: &[Attribute]| attributes.iter().map(|a| a.dimension).sum();
+
+    [
+      total(&self.attributes.vertex),
+      total(&self.attributes.corner),
+    ]
+  }
+
+  fn get_vertex_attributes(&self, key: IndexType, values: &mut Vec<f32>) {
+    values.clear();
+
+    for attribute in &self.attributes.vertex {
+      values.extend_from_slice(attribute.get(key));
+    }
+  }
+
+  fn set_vertex_attributes(&mut self, key: IndexType, values: &[f32]) {
+    let mut start = 0;
+
+    for attribute in &mut self.attributes.vertex {
+      attribute.set(key, &values[start..start + attribute.dimension]);
+      start += attribute.dimension;
+    }
+  }
+
+  fn get_corner_attributes(
+    &self,
+    vertex: IndexType,
+    face: IndexType,
+    values: &mut Vec<f32>,
+  ) {
+    values.clear();
+
+    let half_edge_idx = self.corner_half_edge(vertex, face);
+
+    for attribute in &self.attributes.corner {
+      values.extend_from_slice(attribute.get(half_edge_idx));
+    }
+  }
+
+  fn set_corner_attributes(
+    &mut self,
+    vertex: IndexType,
+    face: IndexType,
+    values: &[f32],
+  ) {
+    let half_edge_idx = self.corner_half_edge(vertex, face);
+    let mut start = 0;
+
+    for attribute in &mut self.attributes.corner {
+      attribute.set(half_edge_idx, &values[start..start + attribute.dimension]);
+      start += attribute.dimension;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn octahedron() -> HalfEdge {
+    let vertices = vec![
+      Vector3::new(0.0, 0.0, 1.0),
+      Vector3::new(0.0, 0.0, -1.0),
+      Vector3::new(1.0, 0.0, 0.0),
+      Vector3::new(0.0, 1.0, 0.0),
+      Vector3::new(-1.0, 0.0, 0.0),
+      Vector3::new(0.0, -1.0, 0.0),
+    ];
+    let faces = (0..4).flat_map(|i| {
+      let (a, b) = (2 + i, 2 + (i + 1) % 4);
+
+      vec![vec![0, a, b], vec![1, b, a]]
+    });
+
+    HalfEdge::from_iters(vertices, faces)
+  }
+
+  // two rows of three vertices, split into four triangles
+  fn quad_strip() -> HalfEdge {
+    let vertices =
+      (0..6).map(|i| Vector3::new((i % 3) as f32, (i / 3) as f32, 0.0));
+    let faces =
+      vec![vec![0, 1, 4], vec![0, 4, 3], vec![1, 2, 5], vec![1, 5, 4]];
+
+    HalfEdge::from_iters(vertices, faces)
+  }
+
+  fn find_edge(
+    mesh: &HalfEdge,
+    first: IndexType,
+    second: IndexType,
+  ) -> IndexType {
+    let mut edge_op = mesh.initial_edge();
+
+    while let Some(edge_idx) = edge_op {
+      let [a, b] = mesh.get_endpoints(edge_idx);
+
+      if [a, b] == [first, second] || [b, a] == [first, second] {
+        return edge_idx;
+      }
+
+      edge_op = mesh.next_edge(edge_idx);
+    }
+
+    panic!("no edge between {} and {}", first, second);
+  }
+
+  fn assert_counts(mesh: &HalfEdge, [vertices, edges, faces]: [usize; 3]) {
+    mesh.check_all();
+
+    assert_eq!(
+      [mesh.num_vertices(), mesh.num_edges(), mesh.num_faces()],
+      [vertices, edges, faces]
+    );
+  }
+
+  #[test]
+  fn single_triangle_boundary_edits() {
+    let vertices = vec![
+      Vector3::new(0.0, 0.0, 0.0),
+      Vector3::new(1.0, 0.0, 0.0),
+      Vector3::new(0.0, 1.0, 0.0),
+    ];
+    let mut mesh = HalfEdge::from_iters(vertices, vec![vec![0, 1, 2]]);
+
+    assert_counts(&mesh, [3, 3, 1]);
+    assert!(mesh.try_flip_edge(find_edge(&mesh, 0, 1)).is_err());
+
+    let (m, _, far_op) = mesh.try_split_edge(find_edge(&mesh, 0, 1)).unwrap();
+
+    assert!(far_op.is_none());
+    assert_counts(&mesh, [4, 5, 2]);
+    assert_eq!(mesh.degree(m), 3);
+    assert_eq!(mesh.degree(2), 3);
+    assert_eq!(mesh.degree(0), 2);
+
+    let kept = mesh
+      .try_collapse_edge(
+        find_edge(&mesh, 0, m),
+        &mut Vec::new(),
+        &mut Vec::new(),
+      )
+      .unwrap();
+
+    assert_counts(&mesh, [3, 3, 1]);
+    assert_eq!(mesh.degree(kept), 2);
+  }
+
+  #[test]
+  fn quad_strip_boundary_edits() {
+    let mut mesh = quad_strip();
+
+    assert_counts(&mesh, [6, 9, 4]);
+
+    // interior edges between the strip's triangles flip, boundary edges don't
+    mesh.try_flip_edge(find_edge(&mesh, 1, 4)).unwrap();
+    assert_counts(&mesh, [6, 9, 4]);
+    assert!(mesh.try_flip_edge(find_edge(&mesh, 0, 1)).is_err());
+    mesh.try_flip_edge(find_edge(&mesh, 0, 5)).unwrap();
+    assert_counts(&mesh, [6, 9, 4]);
+
+    let (m, _, far_op) = mesh.try_split_edge(find_edge(&mesh, 3, 4)).unwrap();
+
+    assert!(far_op.is_none());
+    assert_counts(&mesh, [7, 11, 5]);
+    assert_eq!(mesh.degree(m), 3);
+    assert_eq!(mesh.degree(3), 2);
+
+    // an interior edge between two boundary vertices would pinch the strip
+    assert!(mesh
+      .try_collapse_edge(
+        find_edge(&mesh, 1, 4),
+        &mut Vec::new(),
+        &mut Vec::new()
+      )
+      .is_err());
+    assert_counts(&mesh, [7, 11, 5]);
+
+    // collapsing along the boundary keeps the strip open
+    let kept = mesh
+      .try_collapse_edge(
+        find_edge(&mesh, 1, 2),
+        &mut Vec::new(),
+        &mut Vec::new(),
+      )
+      .unwrap();
+
+    assert_counts(&mesh, [6, 9, 4]);
+    assert_eq!(mesh.degree(kept), 3);
+    assert!(mesh.is_boundary_vertex(kept));
+
+    let kept = mesh
+      .try_collapse_edge(
+        find_edge(&mesh, m, 3),
+        &mut Vec::new(),
+        &mut Vec::new(),
+      )
+      .unwrap();
+
+    assert_counts(&mesh, [5, 7, 3]);
+    assert!(mesh.is_boundary_vertex(kept));
+  }
+
+  #[test]
+  fn compact_remaps_indices() {
+    let mut mesh = octahedron();
+    let edge_idx = mesh.initial_edge().unwrap();
+    mesh
+      .try_collapse_edge(edge_idx, &mut Vec::new(), &mut Vec::new())
+      .unwrap();
+
+    let before = mesh.to_vecs();
+    let positions: Vec<Option<Vector3>> = (0..mesh.max_idx_vertices())
+      .map(|idx| {
+        mesh.vertex_refs[idx]
+          .as_ref()
+          .map(|_| mesh.get_position(idx as _))
+      })
+      .collect();
+    let endpoints: Vec<Option<[IndexType; 2]>> = (0..mesh.max_idx_edges())
+      .map(|idx| {
+        mesh.edge_refs[idx]
+          .as_ref()
+          .map(|_| mesh.get_endpoints(idx as _))
+      })
+      .collect();
+    let face_vertices: Vec<Option<Vec<IndexType>>> = (0..mesh.max_idx_faces())
+      .map(|idx| {
+        mesh.face_refs[idx].as_ref().map(|_| {
+          let mut vertices = Vec::new();
+          mesh.get_face_vertices(idx as _, &mut vertices);
+          vertices
+        })
+      })
+      .collect();
+
+    let remap = mesh.compact();
+
+    assert_eq!(mesh.to_vecs(), before);
+    assert_eq!(mesh.max_idx_vertices(), mesh.num_vertices());
+    assert_eq!(mesh.max_idx_edges(), mesh.num_edges());
+    assert_eq!(mesh.max_idx_faces(), mesh.num_faces());
+
+    let vertex = |idx: IndexType| remap.vertices[idx as usize].unwrap();
+
+    for (old, position) in positions.iter().enumerate() {
+      assert_eq!(remap.vertices[old].is_some(), position.is_some());
+
+      if let (Some(new), Some(position)) = (remap.vertices[old], position) {
+        assert_eq!(mesh.get_position(new), *position);
+      }
+    }
+
+    for (old, endpoints) in endpoints.iter().enumerate() {
+      assert_eq!(remap.edges[old].is_some(), endpoints.is_some());
+
+      if let (Some(new), Some([a, b])) = (remap.edges[old], endpoints) {
+        assert_eq!(mesh.get_endpoints(new), [vertex(*a), vertex(*b)]);
+      }
+    }
+
+    for (old, vertices) in face_vertices.iter().enumerate() {
+      assert_eq!(remap.faces[old].is_some(), vertices.is_some());
+
+      if let (Some(new), Some(vertices)) = (remap.faces[old], vertices) {
+        let mut new_vertices = Vec::new();
+        mesh.get_face_vertices(new, &mut new_vertices);
+
+        let mapped: Vec<IndexType> =
+          vertices.iter().map(|idx| vertex(*idx)).collect();
+
+        assert_eq!(new_vertices, mapped);
+      }
+    }
+  }
+
+  #[test]
+  fn collapse_interpolates_corners() {
+    let mut mesh = octahedron();
+
+    // uvs are the x and y of each corner's vertex
+    let (vertices, faces) = mesh.to_vecs();
+    let mut uv = Attribute::new("uv", 2);
+
+    for face in &faces {
+      for vertex_idx in face {
+        let position = vertices[*vertex_idx as usize];
+        uv.push(&[position[0], position[1]]);
+      }
+    }
+
+    mesh
+      .set_attributes(Attributes {
+        vertex: Vec::new(),
+        corner: vec![uv],
+      })
+      .unwrap();
+
+    let edge_idx = mesh.initial_edge().unwrap();
+    let [first, second] = mesh.get_endpoints(edge_idx);
+    let t = 0.25;
+    let position =
+      mesh.get_position(first) * (1.0 - t) + mesh.get_position(second) * t;
+
+    let vertex_idx = mesh
+      .try_collapse_edge_at(edge_idx, t, &mut Vec::new(), &mut Vec::new())
+      .unwrap();
+
+    let mut faces = Vec::new();
+    mesh.get_vertex_adjacent_faces(vertex_idx, &mut faces);
+
+    let mut values = Vec::new();
+
+    for face_idx in faces {
+      mesh.get_corner_attributes(vertex_idx, face_idx, &mut values);
+
+      assert_eq!(values, [position[0], position[1]]);
+    }
+  }
 }
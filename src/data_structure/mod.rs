@@ -1,5 +1,11 @@
+mod attribute;
+pub use attribute::Attribute;
+pub use attribute::Attributes;
+
 mod base;
 pub use base::DataStructure;
+pub use base::Face;
+pub use base::IndexRemap;
 pub use base::IndexType;
 pub use base::Vector3;
 
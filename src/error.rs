@@ -0,0 +1,145 @@
+use crate::IndexType;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum MeshError {
+  Io(std::io::Error),
+  // a face refers to a vertex which doesn't exist
+  VertexIndexOutOfRange {
+    face: IndexType,
+    vertex: IndexType,
+    num_vertices: usize,
+  },
+  // a face with fewer than three distinct vertices
+  DegenerateFace {
+    face: IndexType,
+  },
+  // a position which is nan or infinite
+  InvalidPosition {
+    vertex: IndexType,
+  },
+  // an edge shared by more than two faces (or by two faces which don't
+  // agree on the orientation)
+  NonManifoldEdge {
+    face: IndexType,
+    vertices: [IndexType; 2],
+  },
+  // two faces on an edge which traverse it in the same direction
+  InconsistentOrientation {
+    faces: [IndexType; 2],
+    vertices: [IndexType; 2],
+  },
+  // a face with the same vertices as an earlier face
+  DuplicateFace {
+    face: IndexType,
+    original: IndexType,
+  },
+  // the faces around a vertex don't form a single fan
+  NonManifoldVertex {
+    vertex: IndexType,
+  },
+  // an edge index which was removed or never existed
+  MissingEdge {
+    edge: IndexType,
+  },
+  BoundaryEdge {
+    edge: IndexType,
+  },
+  NonTriangleFace {
+    face: IndexType,
+  },
+  // collapsing the edge would make the mesh non manifold
+  CollapseRejected {
+    edge: IndexType,
+    reason: &'static str,
+  },
+  // an iterative solver stopped before reaching its tolerance
+  NotConverged {
+    iterations: usize,
+    residual: f64,
+  },
+  // bad parameters or input files for an operation
+  InvalidArgument(String),
+}
+
+impl fmt::Display for MeshError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      MeshError::Io(e) => write!(f, "{}", e),
+      MeshError::VertexIndexOutOfRange {
+        face,
+        vertex,
+        num_vertices,
+      } => write!(
+        f,
+        "face {}: vertex index {} is out of range (there are {} vertices)",
+        face, vertex, num_vertices
+      ),
+      MeshError::DegenerateFace { face } => {
+        write!(f, "face {}: face needs at least 3 distinct vertices", face)
+      }
+      MeshError::InvalidPosition { vertex } => {
+        write!(f, "vertex {}: position isn't finite", vertex)
+      }
+      MeshError::NonManifoldEdge { face, vertices } => write!(
+        f,
+        "face {}: edge from vertex {} to {} is shared by more than two faces \
+         or by faces with opposite orientations",
+        face, vertices[0], vertices[1]
+      ),
+      MeshError::InconsistentOrientation { faces, vertices } => write!(
+        f,
+        "faces {} and {}: both go from vertex {} to {} (inconsistent \
+         orientation)",
+        faces[0], faces[1], vertices[0], vertices[1]
+      ),
+      MeshError::DuplicateFace { face, original } => write!(
+        f,
+        "face {}: face has the same vertices as face {}",
+        face, original
+      ),
+      MeshError::NonManifoldVertex { vertex } => write!(
+        f,
+        "vertex {}: faces around the vertex don't form a single fan",
+        vertex
+      ),
+      MeshError::MissingEdge { edge } => {
+        write!(f, "edge {}: edge doesn't exist", edge)
+      }
+      MeshError::BoundaryEdge { edge } => {
+        write!(f, "edge {}: edge is on the boundary", edge)
+      }
+      MeshError::NonTriangleFace { face } => {
+        write!(f, "face {}: face isn't a triangle", face)
+      }
+      MeshError::CollapseRejected { edge, reason } => {
+        write!(f, "edge {}: can't collapse, {}", edge, reason)
+      }
+      MeshError::NotConverged {
+        iterations,
+        residual,
+      } => write!(
+        f,
+        "solver didn't converge after {} iterations (relative residual {})",
+        iterations, residual
+      ),
+      MeshError::InvalidArgument(message) => write!(f, "{}", message),
+    }
+  }
+}
+
+impl std::error::Error for MeshError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      MeshError::Io(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<std::io::Error> for MeshError {
+  fn from(error: std::io::Error) -> Self {
+    MeshError::Io(error)
+  }
+}
@@ -0,0 +1,11 @@
+pub mod obj;
+pub mod ply;
+pub mod pm;
+pub mod stl;
+
+// file in the temporary directory for round trip tests (unique per process
+// so tests can run in parallel)
+#[cfg(test)]
+pub(crate) fn test_path(name: &str) -> std::path::PathBuf {
+  std::env::temp_dir().join(format!("mesh_{}_{}", std::process::id(), name))
+}
@@ -0,0 +1,329 @@
+use crate::Attribute;
+use crate::Attributes;
+use crate::Face;
+use crate::IndexType;
+use crate::Vector3;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{prelude::*, BufWriter};
+use std::path::Path;
+
+// per corner attribute from the indices of each corner (corners without an
+// index get zeros), None if no corner has an index
+fn corner_attribute(
+  name: &str,
+  dimension: usize,
+  values: &[Vec<f32>],
+  corners: &[Option<usize>],
+) -> Option<Attribute> {
+  if corners.iter().all(Option::is_none) {
+    return None;
+  }
+
+  let mut out = Attribute::new(name, dimension);
+  let zeros = vec![0.0; dimension];
+
+  for corner in corners {
+    out.push(corner.map_or(&zeros, |idx| &values[idx]));
+  }
+
+  Some(out)
+}
+
+// indices (offset into the values of all models) of the corners of a model,
+// None for each corner if the model has no values
+fn model_corners(
+  indices: &[u32],
+  num_corners: usize,
+  offset: usize,
+) -> impl Iterator<Item = Option<usize>> + '_ {
+  let missing = if indices.is_empty() { num_corners } else { 0 };
+
+  indices
+    .iter()
+    .map(move |idx| Some(*idx as usize + offset))
+    .chain((0..missing).map(|_| None))
+}
+
+// polygons are kept as is (not triangulated), vertices are numbered in the
+// order they are first used by the faces; texture coordinates and normals
+// become corner attributes ("uv" and "normal") and colors after the position
+// ("v x y z r g b") a vertex attribute ("color")
+pub fn read(
+  path: &Path,
+) -> std::io::Result<(Vec<Vector3>, Vec<Face>, Attributes)> {
+  let options = tobj::LoadOptions {
+    triangulate: false,
+    single_index: false,
+    ignore_points: true,
+    ignore_lines: true,
+  };
+
+  let (models, _) = tobj::load_obj(path, &options).map_err(|e| {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+  })?;
+
+  let mut vertices = Vec::new();
+  let mut colors = Vec::new();
+  let mut uvs = Vec::new();
+  let mut normals = Vec::new();
+  let mut faces = Vec::new();
+  // index into uvs and normals for each corner of each face
+  let mut corner_uvs = Vec::new();
+  let mut corner_normals = Vec::new();
+
+  for model in &models {
+    let mesh = &model.mesh;
+    let offset = vertices.len() as IndexType;
+    let num_vertices = mesh.positions.len() / 3;
+
+    vertices.extend(
+      mesh
+        .positions
+        .chunks_exact(3)
+        .map(|vals| Vector3::new(vals[0], vals[1], vals[2])),
+    );
+
+    if mesh.vertex_color.len() == mesh.positions.len() {
+      colors
+        .extend(mesh.vertex_color.chunks_exact(3).map(|c| Some(c.to_vec())));
+    } else {
+      colors.resize(colors.len() + num_vertices, None);
+    }
+
+    // face_arities is empty if all faces are triangles
+    let mut corners = mesh.indices.iter().map(|idx| idx + offset);
+
+    if mesh.face_arities.is_empty() {
+      faces.extend(
+        (0..mesh.indices.len() / 3)
+          .map(|_| corners.by_ref().take(3).collect::<Face>()),
+      );
+    } else {
+      faces.extend(
+        mesh.face_arities.iter().map(|arity| {
+          corners.by_ref().take(*arity as usize).collect::<Face>()
+        }),
+      );
+    }
+
+    let num_corners = mesh.indices.len();
+
+    corner_uvs.extend(model_corners(
+      &mesh.texcoord_indices,
+      num_corners,
+      uvs.len(),
+    ));
+    corner_normals.extend(model_corners(
+      &mesh.normal_indices,
+      num_corners,
+      normals.len(),
+    ));
+
+    uvs.extend(mesh.texcoords.chunks_exact(2).map(<[f32]>::to_vec));
+    normals.extend(mesh.normals.chunks_exact(3).map(<[f32]>::to_vec));
+  }
+
+  let mut attributes = Attributes::default();
+
+  if colors.iter().any(Option::is_some) {
+    let mut color = Attribute::new("color", 3);
+
+    for value in &colors {
+      color.push(value.as_deref().unwrap_or(&[1.0, 1.0, 1.0]));
+    }
+
+    attributes.vertex.push(color);
+  }
+
+  attributes
+    .corner
+    .extend(corner_attribute("uv", 2, &uvs, &corner_uvs));
+  attributes.corner.extend(corner_attribute(
+    "normal",
+    3,
+    &normals,
+    &corner_normals,
+  ));
+
+  Ok((vertices, faces, attributes))
+}
+
+// values of an attribute for each corner (corner or vertex attributes with
+// the name and dimension)
+fn attribute_by_corner<'a>(
+  attributes: &'a Attributes,
+  faces: &'a [Face],
+  name: &str,
+  dimension: usize,
+) -> Option<Vec<&'a [f32]>> {
+  let matches = |a: &&Attribute| a.name == name && a.dimension == dimension;
+
+  if let Some(attribute) = attributes.corner.iter().find(matches) {
+    return Some(
+      (0..attribute.len())
+        .map(|idx| attribute.get(idx as IndexType))
+        .collect(),
+    );
+  }
+
+  attributes.vertex.iter().find(matches).map(|attribute| {
+    faces
+      .iter()
+      .flat_map(|face| face.iter().map(move |idx| attribute.get(*idx)))
+      .collect()
+  })
+}
+
+// writes a line for each distinct value, returns the (1 based) index of the
+// line for each value
+fn write_distinct<W: Write>(
+  writer: &mut W,
+  prefix: &str,
+  values: &[&[f32]],
+) -> std::io::Result<Vec<usize>> {
+  let mut seen = HashMap::new();
+  let mut idxs = Vec::with_capacity(values.len());
+
+  for value in values {
+    let key: Vec<u32> = value.iter().map(|v| v.to_bits()).collect();
+    let next_idx = seen.len() + 1;
+
+    let idx = *seen.entry(key).or_insert_with(|| next_idx);
+
+    if idx == next_idx {
+      write!(writer, "{}", prefix)?;
+      for v in *value {
+        write!(writer, " {}", v)?;
+      }
+      writeln!(writer)?;
+    }
+
+    idxs.push(idx);
+  }
+
+  Ok(idxs)
+}
+
+// writes "uv" and "normal" attributes (per corner or per vertex) and vertex
+// colors, other attributes can't be stored
+pub fn write(
+  path: &Path,
+  vertices: &[Vector3],
+  faces: &[Face],
+  attributes: &Attributes,
+) -> std::io::Result<()> {
+  let mut writer = BufWriter::new(File::create(path)?);
+
+  let colors = attributes
+    .vertex
+    .iter()
+    .find(|a| a.name == "color" && a.dimension >= 3);
+
+  for (idx, vertex) in vertices.iter().enumerate() {
+    write!(&mut writer, "v {} {} {}", vertex[0], vertex[1], vertex[2])?;
+
+    if let Some(colors) = colors {
+      let color = colors.get(idx as IndexType);
+      write!(&mut writer, " {} {} {}", color[0], color[1], color[2])?;
+    }
+
+    writeln!(&mut writer)?;
+  }
+
+  let uv_idxs = match attribute_by_corner(attributes, faces, "uv", 2) {
+    Some(values) => Some(write_distinct(&mut writer, "vt", &values)?),
+    None => None,
+  };
+  let normal_idxs = match attribute_by_corner(attributes, faces, "normal", 3) {
+    Some(values) => Some(write_distinct(&mut writer, "vn", &values)?),
+    None => None,
+  };
+
+  let mut corner_idx = 0;
+
+  for face in faces {
+    write!(&mut writer, "f")?;
+
+    for idx in face {
+      write!(&mut writer, " {}", idx + 1)?;
+
+      match (&uv_idxs, &normal_idxs) {
+        (Some(uv), Some(normal)) => {
+          write!(&mut writer, "/{}/{}", uv[corner_idx], normal[corner_idx])?
+        }
+        (Some(uv), None) => write!(&mut writer, "/{}", uv[corner_idx])?,
+        (None, Some(normal)) => {
+          write!(&mut writer, "//{}", normal[corner_idx])?
+        }
+        (None, None) => {}
+      }
+
+      corner_idx += 1;
+    }
+
+    writeln!(&mut writer)?;
+  }
+
+  writer.flush()?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::file_format::test_path;
+
+  #[test]
+  fn round_trip() {
+    let vertices = vec![
+      Vector3::new(0.0, 0.0, 0.0),
+      Vector3::new(1.0, 0.0, 0.5),
+      Vector3::new(1.0, 1.0, 2.0),
+      Vector3::new(0.0, 1.0, -0.25),
+      Vector3::new(0.5, 2.0, 0.0),
+    ];
+    let faces = vec![vec![0, 1, 2, 3], vec![3, 2, 4]];
+
+    let mut color = Attribute::new("color", 3);
+    color.values = vec![
+      0.0, 0.5, 1.0, 1.0, 0.0, 0.0, 0.25, 0.25, 0.25, 1.0, 1.0, 1.0, 0.0, 0.0,
+      1.0,
+    ];
+    let mut uv = Attribute::new("uv", 2);
+    uv.values = vec![
+      0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.5, 1.0,
+    ];
+
+    let attributes = Attributes {
+      vertex: vec![color],
+      corner: vec![uv],
+    };
+
+    let path = test_path("round_trip.obj");
+
+    write(&path, &vertices, &faces, &attributes).unwrap();
+    let (read_vertices, read_faces, read_attributes) = read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // polygons aren't triangulated
+    assert_eq!(read_vertices, vertices);
+    assert_eq!(read_faces, faces);
+
+    assert_eq!(read_attributes.vertex.len(), 1);
+    assert_eq!(read_attributes.vertex[0].name, "color");
+    assert_eq!(
+      read_attributes.vertex[0].values,
+      attributes.vertex[0].values
+    );
+
+    assert_eq!(read_attributes.corner.len(), 1);
+    assert_eq!(read_attributes.corner[0].name, "uv");
+    assert_eq!(
+      read_attributes.corner[0].values,
+      attributes.corner[0].values
+    );
+  }
+}
@@ -0,0 +1,856 @@
+use crate::Attribute;
+use crate::Attributes;
+use crate::Face;
+use crate::IndexType;
+use crate::Vector3;
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{prelude::*, BufWriter};
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ScalarType {
+  Char,
+  UChar,
+  Short,
+  UShort,
+  Int,
+  UInt,
+  Float,
+  Double,
+}
+
+impl ScalarType {
+  fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "char" | "int8" => Some(ScalarType::Char),
+      "uchar" | "uint8" => Some(ScalarType::UChar),
+      "short" | "int16" => Some(ScalarType::Short),
+      "ushort" | "uint16" => Some(ScalarType::UShort),
+      "int" | "int32" => Some(ScalarType::Int),
+      "uint" | "uint32" => Some(ScalarType::UInt),
+      "float" | "float32" => Some(ScalarType::Float),
+      "double" | "float64" => Some(ScalarType::Double),
+      _ => None,
+    }
+  }
+
+  fn name(self) -> &'static str {
+    match self {
+      ScalarType::Char => "char",
+      ScalarType::UChar => "uchar",
+      ScalarType::Short => "short",
+      ScalarType::UShort => "ushort",
+      ScalarType::Int => "int",
+      ScalarType::UInt => "uint",
+      ScalarType::Float => "float",
+      ScalarType::Double => "double",
+    }
+  }
+
+  fn size(self) -> usize {
+    match self {
+      ScalarType::Char | ScalarType::UChar => 1,
+      ScalarType::Short | ScalarType::UShort => 2,
+      ScalarType::Int | ScalarType::UInt | ScalarType::Float => 4,
+      ScalarType::Double => 8,
+    }
+  }
+}
+
+// per vertex property besides the position (normals, colors, ...)
+pub struct VertexProperty {
+  pub name: String,
+  pub scalar_type: ScalarType,
+  pub values: Vec<f64>,
+}
+
+// per face list property besides the vertex indices (texcoord, ...)
+pub struct FaceProperty {
+  pub name: String,
+  pub scalar_type: ScalarType,
+  pub values: Vec<Vec<f64>>,
+}
+
+pub struct PlyData {
+  pub vertices: Vec<Vector3>,
+  pub faces: Vec<Face>,
+  pub properties: Vec<VertexProperty>,
+  pub face_properties: Vec<FaceProperty>,
+}
+
+// vertex properties which are grouped into one attribute (the last name of
+// colors is optional)
+const VERTEX_GROUPS: &[(&str, &[&str])] = &[
+  ("normal", &["nx", "ny", "nz"]),
+  ("color", &["red", "green", "blue", "alpha"]),
+  ("uv", &["s", "t"]),
+  ("uv", &["u", "v"]),
+  ("uv", &["texture_u", "texture_v"]),
+];
+
+// face list property for corner uvs
+const CORNER_UV: &str = "texcoord";
+
+impl PlyData {
+  // colors stored as uchar are scaled to [0, 1]
+  pub fn attributes(&self) -> Attributes {
+    let mut out = Attributes::default();
+    let mut used = vec![false; self.properties.len()];
+
+    let find = |name: &str| self.properties.iter().position(|p| p.name == name);
+
+    for (name, property_names) in VERTEX_GROUPS {
+      let mut idxs = Vec::new();
+
+      for property_name in *property_names {
+        match find(property_name) {
+          Some(idx) => idxs.push(idx),
+          None => break,
+        }
+      }
+
+      let required = if *name == "color" {
+        3
+      } else {
+        property_names.len()
+      };
+
+      // the first of several groups with the same name wins (e.g. both "s t"
+      // and "u v"), the others are kept as single properties
+      if idxs.len() < required
+        || idxs.iter().any(|idx| used[*idx])
+        || out.vertex.iter().any(|a| a.name == *name)
+      {
+        continue;
+      }
+
+      let mut attribute = Attribute::new(name, idxs.len());
+
+      for vertex_idx in 0..self.vertices.len() {
+        for idx in &idxs {
+          let property = &self.properties[*idx];
+          let value = property.values[vertex_idx];
+
+          attribute
+            .values
+            .push(if property.scalar_type == ScalarType::UChar
+              && *name == "color"
+            {
+              value / 255.0
+            } else {
+              value
+            } as f32);
+        }
+      }
+
+      for idx in idxs {
+        used[idx] = true;
+      }
+
+      out.vertex.push(attribute);
+    }
+
+    for (property, used) in self.properties.iter().zip(used) {
+      if !used {
+        let mut attribute = Attribute::new(&property.name, 1);
+        attribute.values = property.values.iter().map(|v| *v as f32).collect();
+        out.vertex.push(attribute);
+      }
+    }
+
+    for property in &self.face_properties {
+      let dimension = match (self.faces.first(), property.values.first()) {
+        (Some(face), Some(values)) => values.len() / face.len(),
+        _ => continue,
+      };
+
+      // every face needs a value for each corner
+      let complete = dimension > 0
+        && self
+          .faces
+          .iter()
+          .zip(&property.values)
+          .all(|(face, values)| values.len() == dimension * face.len());
+
+      if !complete {
+        continue;
+      }
+
+      let name = if property.name == CORNER_UV {
+        "uv"
+      } else {
+        &property.name
+      };
+
+      let mut attribute = Attribute::new(name, dimension);
+      attribute.values = property
+        .values
+        .iter()
+        .flatten()
+        .map(|v| *v as f32)
+        .collect();
+
+      out.corner.push(attribute);
+    }
+
+    out
+  }
+
+  // attributes become vertex properties (grouped attributes get the usual
+  // property names) and face list properties
+  pub fn new(
+    vertices: Vec<Vector3>,
+    faces: Vec<Face>,
+    attributes: &Attributes,
+  ) -> Self {
+    let mut properties = Vec::new();
+
+    for attribute in &attributes.vertex {
+      let group = VERTEX_GROUPS.iter().find(|(name, names)| {
+        *name == attribute.name
+          && (names.len() == attribute.dimension
+            || (*name == "color" && attribute.dimension == 3))
+      });
+
+      let is_color = attribute.name == "color" && group.is_some();
+
+      for i in 0..attribute.dimension {
+        let name = match group {
+          Some((_, names)) => names[i].to_owned(),
+          None if attribute.dimension == 1 => attribute.name.clone(),
+          None => format!("{}_{}", attribute.name, i),
+        };
+
+        let values = (0..attribute.len())
+          .map(|idx| {
+            let value = attribute.get(idx as IndexType)[i] as f64;
+
+            if is_color {
+              (value * 255.0).round().clamp(0.0, 255.0)
+            } else {
+              value
+            }
+          })
+          .collect();
+
+        properties.push(VertexProperty {
+          name,
+          scalar_type: if is_color {
+            ScalarType::UChar
+          } else {
+            ScalarType::Float
+          },
+          values,
+        });
+      }
+    }
+
+    let face_properties = attributes
+      .corner
+      .iter()
+      .map(|attribute| {
+        let mut corner_idx = 0;
+
+        let values = faces
+          .iter()
+          .map(|face| {
+            let start = corner_idx;
+            corner_idx += face.len();
+
+            (start..corner_idx)
+              .flat_map(|idx| attribute.get(idx as IndexType))
+              .map(|v| *v as f64)
+              .collect()
+          })
+          .collect();
+
+        FaceProperty {
+          name: if attribute.name == "uv" {
+            CORNER_UV.to_owned()
+          } else {
+            attribute.name.clone()
+          },
+          scalar_type: ScalarType::Float,
+          values,
+        }
+      })
+      .collect();
+
+    PlyData {
+      vertices,
+      faces,
+      properties,
+      face_properties,
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+  Ascii,
+  BinaryLittleEndian,
+  BinaryBigEndian,
+}
+
+enum PropertyKind {
+  Scalar(ScalarType),
+  // count type and item type
+  List(ScalarType, ScalarType),
+}
+
+struct Property {
+  name: String,
+  kind: PropertyKind,
+}
+
+struct Element {
+  name: String,
+  count: usize,
+  properties: Vec<Property>,
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(
+  error: E,
+) -> std::io::Error {
+  std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+}
+
+fn parse_header(header: &str) -> std::io::Result<(Format, Vec<Element>)> {
+  let mut lines = header.lines().map(|line| line.trim());
+
+  if lines.next() != Some("ply") {
+    return Err(invalid_data("missing ply magic number"));
+  }
+
+  let mut format = None;
+  let mut elements: Vec<Element> = Vec::new();
+
+  for line in lines {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+
+    match tokens.as_slice() {
+      ["format", name, _] => {
+        format = Some(match *name {
+          "ascii" => Format::Ascii,
+          "binary_little_endian" => Format::BinaryLittleEndian,
+          "binary_big_endian" => Format::BinaryBigEndian,
+          _ => return Err(invalid_data(format!("unknown format {}", name))),
+        })
+      }
+      ["element", name, count] => elements.push(Element {
+        name: (*name).to_owned(),
+        count: count.parse().map_err(invalid_data)?,
+        properties: Vec::new(),
+      }),
+      ["property", "list", count_type, item_type, name] => {
+        let kind = match (
+          ScalarType::from_name(count_type),
+          ScalarType::from_name(item_type),
+        ) {
+          (Some(count_type), Some(item_type)) => {
+            PropertyKind::List(count_type, item_type)
+          }
+          _ => return Err(invalid_data(format!("invalid property: {}", line))),
+        };
+
+        elements
+          .last_mut()
+          .ok_or_else(|| invalid_data("property before element"))?
+          .properties
+          .push(Property {
+            name: (*name).to_owned(),
+            kind,
+          });
+      }
+      ["property", scalar_type, name] => {
+        let scalar_type = ScalarType::from_name(scalar_type)
+          .ok_or_else(|| invalid_data(format!("invalid property: {}", line)))?;
+
+        elements
+          .last_mut()
+          .ok_or_else(|| invalid_data("property before element"))?
+          .properties
+          .push(Property {
+            name: (*name).to_owned(),
+            kind: PropertyKind::Scalar(scalar_type),
+          });
+      }
+      ["comment", ..] | ["obj_info", ..] | [] => {}
+      _ => return Err(invalid_data(format!("invalid header line: {}", line))),
+    }
+  }
+
+  Ok((
+    format.ok_or_else(|| invalid_data("missing format"))?,
+    elements,
+  ))
+}
+
+enum Body<'a> {
+  Ascii(std::str::SplitAsciiWhitespace<'a>),
+  Binary { data: &'a [u8], big_endian: bool },
+}
+
+impl<'a> Body<'a> {
+  fn read(&mut self, scalar_type: ScalarType) -> std::io::Result<f64> {
+    match self {
+      Body::Ascii(tokens) => tokens
+        .next()
+        .ok_or_else(|| invalid_data("unexpected end of file"))?
+        .parse::<f64>()
+        .map_err(invalid_data),
+      Body::Binary { data, big_endian } => {
+        let size = scalar_type.size();
+
+        if data.len() < size {
+          return Err(invalid_data("unexpected end of file"));
+        }
+
+        let (bytes, rest) = data.split_at(size);
+        *data = rest;
+
+        macro_rules! convert {
+          ($t:ty) => {{
+            let bytes = bytes.try_into().unwrap();
+            (if *big_endian {
+              <$t>::from_be_bytes(bytes)
+            } else {
+              <$t>::from_le_bytes(bytes)
+            }) as f64
+          }};
+        }
+
+        Ok(match scalar_type {
+          ScalarType::Char => convert!(i8),
+          ScalarType::UChar => convert!(u8),
+          ScalarType::Short => convert!(i16),
+          ScalarType::UShort => convert!(u16),
+          ScalarType::Int => convert!(i32),
+          ScalarType::UInt => convert!(u32),
+          ScalarType::Float => convert!(f32),
+          ScalarType::Double => convert!(f64),
+        })
+      }
+    }
+  }
+}
+
+pub fn read(path: &Path) -> std::io::Result<PlyData> {
+  let mut contents = Vec::new();
+  File::open(path)?.read_to_end(&mut contents)?;
+
+  const END_HEADER: &[u8] = b"end_header";
+
+  let end_header_idx = contents
+    .windows(END_HEADER.len())
+    .position(|w| w == END_HEADER)
+    .ok_or_else(|| invalid_data("missing end_header"))?;
+
+  // body starts after the newline following end_header
+  let body_idx = contents[end_header_idx..]
+    .iter()
+    .position(|c| *c == b'\n')
+    .map(|i| end_header_idx + i + 1)
+    .unwrap_or_else(|| contents.len());
+
+  let header =
+    std::str::from_utf8(&contents[..end_header_idx]).map_err(invalid_data)?;
+
+  let (format, elements) = parse_header(header)?;
+
+  let mut body = match format {
+    Format::Ascii => Body::Ascii(
+      std::str::from_utf8(&contents[body_idx..])
+        .map_err(invalid_data)?
+        .split_ascii_whitespace(),
+    ),
+    Format::BinaryLittleEndian | Format::BinaryBigEndian => Body::Binary {
+      data: &contents[body_idx..],
+      big_endian: format == Format::BinaryBigEndian,
+    },
+  };
+
+  let mut out = PlyData {
+    vertices: Vec::new(),
+    faces: Vec::new(),
+    properties: Vec::new(),
+    face_properties: Vec::new(),
+  };
+
+  for element in &elements {
+    let is_vertex = element.name == "vertex";
+    let is_face = element.name == "face";
+
+    let position_idxs: Vec<Option<usize>> = ["x", "y", "z"]
+      .iter()
+      .map(|name| element.properties.iter().position(|p| &p.name == name))
+      .collect();
+
+    if is_vertex {
+      if position_idxs.iter().any(|idx| idx.is_none()) {
+        return Err(invalid_data("vertex element must have x, y and z"));
+      }
+
+      out.vertices.reserve(element.count);
+
+      for (idx, property) in element.properties.iter().enumerate() {
+        if let PropertyKind::Scalar(scalar_type) = property.kind {
+          if !position_idxs.contains(&Some(idx)) {
+            out.properties.push(VertexProperty {
+              name: property.name.clone(),
+              scalar_type,
+              values: Vec::with_capacity(element.count),
+            });
+          }
+        }
+      }
+    }
+
+    // face list properties besides the indices, in order
+    let first_face_property = out.face_properties.len();
+
+    if is_face {
+      for property in &element.properties {
+        if let PropertyKind::List(_, item_type) = property.kind {
+          if property.name != "vertex_indices"
+            && property.name != "vertex_index"
+          {
+            out.face_properties.push(FaceProperty {
+              name: property.name.clone(),
+              scalar_type: item_type,
+              values: Vec::with_capacity(element.count),
+            });
+          }
+        }
+      }
+    }
+
+    let mut values = Vec::new();
+    let mut face = Vec::new();
+
+    for _ in 0..element.count {
+      values.clear();
+
+      let mut face_property_idx = first_face_property;
+
+      for property in &element.properties {
+        match property.kind {
+          PropertyKind::Scalar(scalar_type) => {
+            values.push(body.read(scalar_type)?);
+          }
+          PropertyKind::List(count_type, item_type) => {
+            let count = body.read(count_type)? as usize;
+
+            let is_indices = is_face
+              && (property.name == "vertex_indices"
+                || property.name == "vertex_index");
+
+            face.clear();
+
+            let mut list = Vec::new();
+
+            for _ in 0..count {
+              let idx = body.read(item_type)?;
+
+              if is_indices {
+                if idx < 0.0 {
+                  return Err(invalid_data("negative vertex index"));
+                }
+
+                face.push(idx as IndexType);
+              } else {
+                list.push(idx);
+              }
+            }
+
+            if is_face && !is_indices {
+              out.face_properties[face_property_idx].values.push(list);
+              face_property_idx += 1;
+            }
+
+            if is_indices {
+              if face.len() < 3 {
+                return Err(invalid_data("face needs at least 3 vertices"));
+              }
+
+              out.faces.push(face.clone());
+            }
+
+            // keep values aligned with the properties
+            values.push(f64::NAN);
+          }
+        }
+      }
+
+      if is_vertex {
+        let get = |i: usize| values[position_idxs[i].unwrap()] as f32;
+
+        out.vertices.push(Vector3::new(get(0), get(1), get(2)));
+
+        // list properties on vertices aren't kept
+        let mut extra = out.properties.iter_mut();
+
+        for (idx, property) in element.properties.iter().enumerate() {
+          if let PropertyKind::Scalar(_) = property.kind {
+            if !position_idxs.contains(&Some(idx)) {
+              extra.next().unwrap().values.push(values[idx]);
+            }
+          }
+        }
+      }
+    }
+  }
+
+  Ok(out)
+}
+
+pub fn write(path: &Path, data: &PlyData, binary: bool) -> std::io::Result<()> {
+  let vertices = &data.vertices;
+  let faces = &data.faces;
+  let properties = &data.properties;
+
+  let mut writer = BufWriter::new(File::create(path)?);
+
+  writeln!(&mut writer, "ply")?;
+  writeln!(
+    &mut writer,
+    "format {} 1.0",
+    if binary {
+      "binary_little_endian"
+    } else {
+      "ascii"
+    }
+  )?;
+  writeln!(&mut writer, "element vertex {}", vertices.len())?;
+  writeln!(&mut writer, "property float x")?;
+  writeln!(&mut writer, "property float y")?;
+  writeln!(&mut writer, "property float z")?;
+
+  for property in properties {
+    debug_assert_eq!(property.values.len(), vertices.len());
+
+    writeln!(
+      &mut writer,
+      "property {} {}",
+      property.scalar_type.name(),
+      property.name
+    )?;
+  }
+
+  writeln!(&mut writer, "element face {}", faces.len())?;
+  writeln!(&mut writer, "property list uchar int vertex_indices")?;
+
+  for property in &data.face_properties {
+    debug_assert_eq!(property.values.len(), faces.len());
+
+    writeln!(
+      &mut writer,
+      "property list uchar {} {}",
+      property.scalar_type.name(),
+      property.name
+    )?;
+  }
+
+  writeln!(&mut writer, "end_header")?;
+
+  fn write_value<W: Write>(
+    writer: &mut W,
+    value: f64,
+    scalar_type: ScalarType,
+    binary: bool,
+  ) -> std::io::Result<()> {
+    if !binary {
+      // print floats at their own precision
+      return match scalar_type {
+        ScalarType::Float => write!(writer, "{}", value as f32),
+        _ => write!(writer, "{}", value),
+      };
+    }
+
+    match scalar_type {
+      ScalarType::Char => writer.write_all(&(value as i8).to_le_bytes()),
+      ScalarType::UChar => writer.write_all(&(value as u8).to_le_bytes()),
+      ScalarType::Short => writer.write_all(&(value as i16).to_le_bytes()),
+      ScalarType::UShort => writer.write_all(&(value as u16).to_le_bytes()),
+      ScalarType::Int => writer.write_all(&(value as i32).to_le_bytes()),
+      ScalarType::UInt => writer.write_all(&(value as u32).to_le_bytes()),
+      ScalarType::Float => writer.write_all(&(value as f32).to_le_bytes()),
+      ScalarType::Double => writer.write_all(&value.to_le_bytes()),
+    }
+  }
+
+  for (vertex_idx, vertex) in vertices.iter().enumerate() {
+    let position = vertex.iter().map(|v| (*v as f64, ScalarType::Float));
+    let extra = properties
+      .iter()
+      .map(|p| (p.values[vertex_idx], p.scalar_type));
+
+    for (i, (value, scalar_type)) in position.chain(extra).enumerate() {
+      if !binary && i != 0 {
+        write!(&mut writer, " ")?;
+      }
+
+      write_value(&mut writer, value, scalar_type, binary)?;
+    }
+
+    if !binary {
+      writeln!(&mut writer)?;
+    }
+  }
+
+  for (face_idx, face) in faces.iter().enumerate() {
+    if face.len() > u8::MAX as usize {
+      return Err(invalid_data("face has too many vertices"));
+    }
+
+    write_value(&mut writer, face.len() as f64, ScalarType::UChar, binary)?;
+
+    for idx in face {
+      if !binary {
+        write!(&mut writer, " ")?;
+      }
+
+      write_value(&mut writer, *idx as f64, ScalarType::Int, binary)?;
+    }
+
+    for property in &data.face_properties {
+      let values = &property.values[face_idx];
+
+      if values.len() > u8::MAX as usize {
+        return Err(invalid_data("face property has too many values"));
+      }
+
+      if !binary {
+        write!(&mut writer, " ")?;
+      }
+
+      write_value(&mut writer, values.len() as f64, ScalarType::UChar, binary)?;
+
+      for value in values {
+        if !binary {
+          write!(&mut writer, " ")?;
+        }
+
+        write_value(&mut writer, *value, property.scalar_type, binary)?;
+      }
+    }
+
+    if !binary {
+      writeln!(&mut writer)?;
+    }
+  }
+
+  writer.flush()?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::file_format::test_path;
+
+  fn sample() -> PlyData {
+    PlyData {
+      vertices: vec![
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(1.0, 0.0, 0.5),
+        Vector3::new(0.0, 1.0, -0.25),
+        Vector3::new(1.0, 1.0, 2.0),
+      ],
+      faces: vec![vec![0, 1, 3, 2], vec![0, 2, 1]],
+      properties: vec![
+        VertexProperty {
+          name: "nx".to_owned(),
+          scalar_type: ScalarType::Float,
+          values: vec![0.0, 0.5, -1.0, 0.25],
+        },
+        VertexProperty {
+          name: "red".to_owned(),
+          scalar_type: ScalarType::UChar,
+          values: vec![0.0, 255.0, 17.0, 128.0],
+        },
+        VertexProperty {
+          name: "quality".to_owned(),
+          scalar_type: ScalarType::Double,
+          values: vec![0.1, -2.5, 1e-9, 3.0],
+        },
+      ],
+      face_properties: vec![FaceProperty {
+        name: CORNER_UV.to_owned(),
+        scalar_type: ScalarType::Float,
+        values: vec![
+          vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0],
+          vec![0.0, 0.0, 0.5, 1.0, 1.0, 0.5],
+        ],
+      }],
+    }
+  }
+
+  fn round_trip(binary: bool) {
+    let data = sample();
+    let path = test_path(if binary { "binary.ply" } else { "ascii.ply" });
+
+    write(&path, &data, binary).unwrap();
+    let read_data = read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(read_data.vertices, data.vertices);
+    assert_eq!(read_data.faces, data.faces);
+
+    assert_eq!(read_data.properties.len(), data.properties.len());
+
+    for (read_property, property) in
+      read_data.properties.iter().zip(&data.properties)
+    {
+      assert_eq!(read_property.name, property.name);
+      assert_eq!(read_property.scalar_type, property.scalar_type);
+      assert_eq!(read_property.values, property.values);
+    }
+
+    assert_eq!(read_data.face_properties.len(), 1);
+    assert_eq!(read_data.face_properties[0].name, CORNER_UV);
+    assert_eq!(
+      read_data.face_properties[0].values,
+      data.face_properties[0].values
+    );
+  }
+
+  #[test]
+  fn one_uv_attribute() {
+    let property = |name: &str| VertexProperty {
+      name: name.to_owned(),
+      scalar_type: ScalarType::Float,
+      values: vec![0.0, 0.25, 0.5, 1.0],
+    };
+
+    let data = PlyData {
+      properties: vec![
+        property("s"),
+        property("t"),
+        property("u"),
+        property("v"),
+      ],
+      ..sample()
+    };
+
+    let attributes = data.attributes();
+    let names: Vec<_> =
+      attributes.vertex.iter().map(|a| a.name.as_str()).collect();
+
+    assert_eq!(names, vec!["uv", "u", "v"]);
+    assert_eq!(attributes.vertex[0].dimension, 2);
+  }
+
+  #[test]
+  fn ascii_round_trip() {
+    round_trip(false);
+  }
+
+  #[test]
+  fn binary_round_trip() {
+    round_trip(true);
+  }
+}
@@ -0,0 +1,432 @@
+use crate::mesh_operation::{
+  FaceRecord, ProgressiveMesh, VertexRecord, VertexSplit,
+};
+use crate::IndexType;
+use crate::Vector3;
+
+use std::fs::File;
+use std::io::{prelude::*, BufReader, BufWriter};
+use std::path::Path;
+
+// text format for progressive meshes (indices are 0 based):
+//
+// pm
+// vertex_attribute <name> <dimension>
+// corner_attribute <name> <dimension>
+// v <idx> <x> <y> <z> <vertex attributes>
+// f <idx> <num vertices> <vertices> <corner attributes of each corner>
+// split
+// v <removed vertex>
+// v <vertex it was collapsed into>
+// f <faces around both before the collapse>
+//
+// vertices and faces before the first split are the coarsest mesh and the
+// splits follow from coarse to fine
+
+fn parse_error(line_num: usize, message: &str) -> std::io::Error {
+  std::io::Error::new(
+    std::io::ErrorKind::InvalidData,
+    format!("line {}: {}", line_num, message),
+  )
+}
+
+fn parse<T: std::str::FromStr>(
+  token: Option<&str>,
+  line_num: usize,
+  what: &str,
+) -> std::io::Result<T> {
+  token
+    .and_then(|token| token.parse().ok())
+    .ok_or_else(|| parse_error(line_num, &format!("invalid {}", what)))
+}
+
+fn parse_floats(
+  tokens: std::str::SplitWhitespace,
+  count: usize,
+  line_num: usize,
+  what: &str,
+) -> std::io::Result<Vec<f32>> {
+  let values = tokens
+    .map(|v| v.parse::<f32>())
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|_| parse_error(line_num, &format!("invalid {}", what)))?;
+
+  if values.len() != count {
+    return Err(parse_error(
+      line_num,
+      &format!("expected {} {} values", count, what),
+    ));
+  }
+
+  Ok(values)
+}
+
+fn finish_split(
+  vertices: &mut Vec<VertexRecord>,
+  faces: &mut Vec<FaceRecord>,
+  splits: &mut Vec<VertexSplit>,
+  line_num: usize,
+) -> std::io::Result<()> {
+  if vertices.len() != 2 {
+    return Err(parse_error(line_num, "split needs exactly 2 vertices"));
+  }
+
+  let parent = vertices.pop().unwrap();
+  let vertex = vertices.pop().unwrap();
+
+  splits.push(VertexSplit {
+    vertex,
+    parent,
+    faces: std::mem::take(faces),
+  });
+
+  Ok(())
+}
+
+fn mark_vertices(exists: &mut Vec<bool>, vertices: &[VertexRecord]) {
+  for record in vertices {
+    let idx = record.vertex as usize;
+
+    if exists.len() <= idx {
+      exists.resize(idx + 1, false);
+    }
+
+    exists[idx] = true;
+  }
+}
+
+fn check_faces(exists: &[bool], faces: &[FaceRecord]) -> std::io::Result<()> {
+  for record in faces {
+    for vertex in &record.vertices {
+      if !exists.get(*vertex as usize).cloned().unwrap_or(false) {
+        return Err(std::io::Error::new(
+          std::io::ErrorKind::InvalidData,
+          format!("face {}: vertex {} doesn't exist", record.face, vertex),
+        ));
+      }
+    }
+  }
+
+  Ok(())
+}
+
+fn total_dimension(layout: &[(String, usize)]) -> usize {
+  layout.iter().map(|(_, dimension)| dimension).sum()
+}
+
+pub fn read(path: &Path) -> std::io::Result<ProgressiveMesh> {
+  let reader = BufReader::new(File::open(path)?);
+
+  let mut out = ProgressiveMesh {
+    vertex_layout: Vec::new(),
+    corner_layout: Vec::new(),
+    base_vertices: Vec::new(),
+    base_faces: Vec::new(),
+    splits: Vec::new(),
+  };
+
+  // vertices of the current split (the removed one and its parent)
+  let mut split_vertices = Vec::new();
+  let mut split_faces = Vec::new();
+  let mut in_split = false;
+
+  let mut lines = reader.lines().enumerate();
+
+  let header = match lines.next() {
+    Some((_, line)) => line?,
+    None => String::new(),
+  };
+
+  if header.trim() != "pm" {
+    return Err(parse_error(1, "missing pm header"));
+  }
+
+  let mut line_num = 1;
+
+  for (line_idx, line) in lines {
+    let line = line?;
+    line_num = line_idx + 1;
+
+    let mut tokens = line.split_whitespace();
+
+    match tokens.next() {
+      Some(kind @ "vertex_attribute") | Some(kind @ "corner_attribute") => {
+        let name = tokens
+          .next()
+          .ok_or_else(|| parse_error(line_num, "attribute needs a name"))?;
+        let dimension = parse(tokens.next(), line_num, "dimension")?;
+
+        let layout = if kind == "vertex_attribute" {
+          &mut out.vertex_layout
+        } else {
+          &mut out.corner_layout
+        };
+
+        layout.push((name.to_owned(), dimension));
+      }
+      Some("v") => {
+        let vertex = parse(tokens.next(), line_num, "vertex index")?;
+        let mut values = parse_floats(
+          tokens,
+          3 + total_dimension(&out.vertex_layout),
+          line_num,
+          "vertex",
+        )?;
+        let attributes = values.split_off(3);
+
+        let record = VertexRecord {
+          vertex,
+          position: Vector3::new(values[0], values[1], values[2]),
+          attributes,
+        };
+
+        if in_split {
+          if split_vertices.len() == 2 {
+            return Err(parse_error(
+              line_num,
+              "split needs exactly 2 vertices",
+            ));
+          }
+
+          split_vertices.push(record);
+        } else {
+          out.base_vertices.push(record);
+        }
+      }
+      Some("f") => {
+        let face = parse(tokens.next(), line_num, "face index")?;
+        let num_vertices: usize =
+          parse(tokens.next(), line_num, "number of vertices")?;
+
+        if num_vertices < 3 {
+          return Err(parse_error(line_num, "face needs at least 3 vertices"));
+        }
+
+        let vertices = (0..num_vertices)
+          .map(|_| parse::<IndexType>(tokens.next(), line_num, "vertex index"))
+          .collect::<Result<Vec<_>, _>>()?;
+        let corners = parse_floats(
+          tokens,
+          num_vertices * total_dimension(&out.corner_layout),
+          line_num,
+          "corner",
+        )?;
+
+        let record = FaceRecord {
+          face,
+          vertices,
+          corners,
+        };
+
+        if in_split {
+          split_faces.push(record);
+        } else {
+          out.base_faces.push(record);
+        }
+      }
+      Some("split") => {
+        if in_split {
+          finish_split(
+            &mut split_vertices,
+            &mut split_faces,
+            &mut out.splits,
+            line_num,
+          )?;
+        }
+
+        in_split = true;
+      }
+      Some(token) if token.starts_with('#') => {}
+      None => {}
+      Some(token) => {
+        return Err(parse_error(
+          line_num,
+          &format!("unknown line type {}", token),
+        ))
+      }
+    }
+  }
+
+  if in_split {
+    finish_split(
+      &mut split_vertices,
+      &mut split_faces,
+      &mut out.splits,
+      line_num,
+    )?;
+  }
+
+  // every face has to refer to vertices which exist at its level
+  let mut exists = Vec::new();
+
+  mark_vertices(&mut exists, &out.base_vertices);
+  check_faces(&exists, &out.base_faces)?;
+
+  for split in &out.splits {
+    mark_vertices(&mut exists, &[split.vertex.clone(), split.parent.clone()]);
+    check_faces(&exists, &split.faces)?;
+  }
+
+  Ok(out)
+}
+
+fn write_values<W: Write>(
+  writer: &mut W,
+  values: &[f32],
+) -> std::io::Result<()> {
+  for v in values {
+    write!(writer, " {}", v)?;
+  }
+
+  Ok(())
+}
+
+fn write_vertex<W: Write>(
+  writer: &mut W,
+  record: &VertexRecord,
+) -> std::io::Result<()> {
+  let p = &record.position;
+
+  write!(writer, "v {} {} {} {}", record.vertex, p[0], p[1], p[2])?;
+  write_values(writer, &record.attributes)?;
+  writeln!(writer)
+}
+
+fn write_face<W: Write>(
+  writer: &mut W,
+  record: &FaceRecord,
+) -> std::io::Result<()> {
+  write!(writer, "f {} {}", record.face, record.vertices.len())?;
+
+  for vertex in &record.vertices {
+    write!(writer, " {}", vertex)?;
+  }
+
+  write_values(writer, &record.corners)?;
+  writeln!(writer)
+}
+
+pub fn write(path: &Path, mesh: &ProgressiveMesh) -> std::io::Result<()> {
+  let mut writer = BufWriter::new(File::create(path)?);
+
+  writeln!(&mut writer, "pm")?;
+
+  for (name, dimension) in &mesh.vertex_layout {
+    writeln!(&mut writer, "vertex_attribute {} {}", name, dimension)?;
+  }
+
+  for (name, dimension) in &mesh.corner_layout {
+    writeln!(&mut writer, "corner_attribute {} {}", name, dimension)?;
+  }
+
+  for record in &mesh.base_vertices {
+    write_vertex(&mut writer, record)?;
+  }
+
+  for record in &mesh.base_faces {
+    write_face(&mut writer, record)?;
+  }
+
+  for split in &mesh.splits {
+    writeln!(&mut writer, "split")?;
+
+    write_vertex(&mut writer, &split.vertex)?;
+    write_vertex(&mut writer, &split.parent)?;
+
+    for record in &split.faces {
+      write_face(&mut writer, record)?;
+    }
+  }
+
+  writer.flush()?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::file_format::test_path;
+
+  fn vertex(vertex: IndexType, x: f32, color: f32) -> VertexRecord {
+    VertexRecord {
+      vertex,
+      position: Vector3::new(x, 0.1 * x, -x),
+      attributes: vec![color],
+    }
+  }
+
+  fn face(face: IndexType, vertices: Vec<IndexType>) -> FaceRecord {
+    // uvs of each corner
+    let corners = vertices
+      .iter()
+      .flat_map(|v| vec![*v as f32 * 0.25, 1.0 / (*v as f32 + 3.0)])
+      .collect();
+
+    FaceRecord {
+      face,
+      vertices,
+      corners,
+    }
+  }
+
+  fn assert_vertices_eq(read: &[VertexRecord], written: &[VertexRecord]) {
+    assert_eq!(read.len(), written.len());
+
+    for (read, written) in read.iter().zip(written) {
+      assert_eq!(read.vertex, written.vertex);
+      assert_eq!(read.position, written.position);
+      assert_eq!(read.attributes, written.attributes);
+    }
+  }
+
+  fn assert_faces_eq(read: &[FaceRecord], written: &[FaceRecord]) {
+    assert_eq!(read.len(), written.len());
+
+    for (read, written) in read.iter().zip(written) {
+      assert_eq!(read.face, written.face);
+      assert_eq!(read.vertices, written.vertices);
+      assert_eq!(read.corners, written.corners);
+    }
+  }
+
+  #[test]
+  fn round_trip() {
+    let mesh = ProgressiveMesh {
+      vertex_layout: vec![("color".to_owned(), 1)],
+      corner_layout: vec![("uv".to_owned(), 2)],
+      base_vertices: vec![
+        vertex(0, 0.0, 0.5),
+        vertex(1, 1.0, 0.25),
+        vertex(3, -2.5, 1.0),
+      ],
+      base_faces: vec![face(0, vec![0, 1, 3])],
+      splits: vec![VertexSplit {
+        vertex: vertex(2, 0.3, 0.7),
+        parent: vertex(3, -2.0, 0.9),
+        faces: vec![face(0, vec![0, 1, 2]), face(1, vec![1, 3, 2])],
+      }],
+    };
+
+    let path = test_path("round_trip.pm");
+
+    write(&path, &mesh).unwrap();
+    let read_mesh = read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(read_mesh.vertex_layout, mesh.vertex_layout);
+    assert_eq!(read_mesh.corner_layout, mesh.corner_layout);
+    assert_vertices_eq(&read_mesh.base_vertices, &mesh.base_vertices);
+    assert_faces_eq(&read_mesh.base_faces, &mesh.base_faces);
+
+    assert_eq!(read_mesh.splits.len(), mesh.splits.len());
+
+    for (read, written) in read_mesh.splits.iter().zip(&mesh.splits) {
+      assert_vertices_eq(
+        &[read.vertex.clone(), read.parent.clone()],
+        &[written.vertex.clone(), written.parent.clone()],
+      );
+      assert_faces_eq(&read.faces, &written.faces);
+    }
+  }
+}
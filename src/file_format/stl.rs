@@ -0,0 +1,246 @@
+use crate::spatial::PointGrid;
+use crate::Face;
+use crate::IndexType;
+use crate::Vector3;
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{prelude::*, BufWriter};
+use std::path::Path;
+
+const HEADER_LEN: usize = 80;
+const TRIANGLE_LEN: usize = 50;
+
+fn invalid_data<E>(error: E) -> std::io::Error
+where
+  E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+  std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+}
+
+// stl files can't be told apart by their start ("solid" is a valid start of
+// a binary header), so a file is binary if its size matches the triangle count
+fn is_binary(contents: &[u8]) -> bool {
+  if contents.len() < HEADER_LEN + 4 {
+    return false;
+  }
+
+  let num_triangles = u32::from_le_bytes(
+    contents[HEADER_LEN..HEADER_LEN + 4].try_into().unwrap(),
+  ) as usize;
+
+  contents.len() == HEADER_LEN + 4 + num_triangles * TRIANGLE_LEN
+}
+
+fn read_binary(contents: &[u8]) -> Vec<[Vector3; 3]> {
+  let read_vector = |data: &[u8]| {
+    let mut vector = Vector3::zeros();
+    for i in 0..3 {
+      vector[i] =
+        f32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+    }
+    vector
+  };
+
+  contents[HEADER_LEN + 4..]
+    .chunks_exact(TRIANGLE_LEN)
+    .map(|data| {
+      // first 12 bytes are the normal and the last 2 the attribute count
+      [
+        read_vector(&data[12..24]),
+        read_vector(&data[24..36]),
+        read_vector(&data[36..48]),
+      ]
+    })
+    .collect()
+}
+
+fn read_ascii(contents: &str) -> std::io::Result<Vec<[Vector3; 3]>> {
+  let mut triangles = Vec::new();
+  let mut corners = Vec::new();
+
+  for (line_idx, line) in contents.lines().enumerate() {
+    let mut tokens = line.split_whitespace();
+
+    match tokens.next() {
+      Some("vertex") => {
+        let coords = tokens
+          .map(|v| v.parse::<f32>())
+          .collect::<Result<Vec<_>, _>>()
+          .ok()
+          .filter(|coords| coords.len() == 3)
+          .ok_or_else(|| {
+            invalid_data(format!("line {}: invalid vertex", line_idx + 1))
+          })?;
+
+        corners.push(Vector3::new(coords[0], coords[1], coords[2]));
+      }
+      Some("endloop") => {
+        if corners.len() != 3 {
+          return Err(invalid_data(format!(
+            "line {}: facet needs 3 vertices, found {}",
+            line_idx + 1,
+            corners.len()
+          )));
+        }
+
+        triangles.push([corners[0], corners[1], corners[2]]);
+        corners.clear();
+      }
+      // normals are recomputed from the positions
+      _ => {}
+    }
+  }
+
+  Ok(triangles)
+}
+
+// each triangle has its own three corners
+pub fn read(path: &Path) -> std::io::Result<Vec<[Vector3; 3]>> {
+  let mut contents = Vec::new();
+  File::open(path)?.read_to_end(&mut contents)?;
+
+  if is_binary(&contents) {
+    Ok(read_binary(&contents))
+  } else {
+    read_ascii(std::str::from_utf8(&contents).map_err(invalid_data)?)
+  }
+}
+
+// faces from the vertex of each corner, dropping triangles which lose a
+// corner
+fn welded_faces<F>(triangles: &[[Vector3; 3]], mut vertex: F) -> Vec<Face>
+where
+  F: FnMut(&Vector3) -> IndexType,
+{
+  let mut faces = Vec::with_capacity(triangles.len());
+
+  for triangle in triangles {
+    let face: Face = triangle.iter().map(&mut vertex).collect();
+
+    if face[0] != face[1] && face[1] != face[2] && face[2] != face[0] {
+      faces.push(face);
+    }
+  }
+
+  faces
+}
+
+// merges corners closer than epsilon (0 merges only identical positions) so
+// triangles share vertices; triangles which lose a corner are dropped
+pub fn weld(
+  triangles: &[[Vector3; 3]],
+  epsilon: f32,
+) -> (Vec<Vector3>, Vec<Face>) {
+  if epsilon > 0.0 {
+    let mut grid = PointGrid::new(epsilon);
+
+    let faces = welded_faces(triangles, |position| {
+      match grid.nearest_within(position, epsilon) {
+        Some(idx) => idx,
+        None => grid.insert(*position),
+      }
+    });
+
+    let vertices = (0..grid.len()).map(|idx| grid.position(idx as _)).collect();
+
+    (vertices, faces)
+  } else {
+    // identical positions have the same bits (after adding 0 so -0 is 0)
+    let mut vertices = Vec::new();
+    let mut idxs = HashMap::new();
+
+    let faces = welded_faces(triangles, |position| {
+      let bits = |v: f32| (v + 0.0).to_bits();
+      let key = [bits(position[0]), bits(position[1]), bits(position[2])];
+
+      *idxs.entry(key).or_insert_with(|| {
+        vertices.push(*position);
+        (vertices.len() - 1) as IndexType
+      })
+    });
+
+    (vertices, faces)
+  }
+}
+
+// binary stl, triangles are (normal, positions)
+pub fn write(
+  path: &Path,
+  triangles: &[(Vector3, [Vector3; 3])],
+) -> std::io::Result<()> {
+  let mut writer = BufWriter::new(File::create(path)?);
+
+  let mut header = [0u8; HEADER_LEN];
+  let name = b"binary stl";
+  header[..name.len()].copy_from_slice(name);
+
+  writer.write_all(&header)?;
+  writer.write_all(&(triangles.len() as u32).to_le_bytes())?;
+
+  for (normal, positions) in triangles {
+    for vector in std::iter::once(normal).chain(positions.iter()) {
+      for i in 0..3 {
+        writer.write_all(&vector[i].to_le_bytes())?;
+      }
+    }
+
+    // attribute byte count
+    writer.write_all(&0u16.to_le_bytes())?;
+  }
+
+  writer.flush()?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::file_format::test_path;
+
+  #[test]
+  fn write_read_weld() {
+    let normal = Vector3::new(0.0, 0.0, 1.0);
+    let quad = [
+      Vector3::new(0.0, 0.0, 0.0),
+      Vector3::new(1.0, 0.0, 0.0),
+      Vector3::new(1.0, 1.0, 0.0),
+      Vector3::new(-0.0, 1.0, 0.0),
+    ];
+    let triangles = vec![
+      (normal, [quad[0], quad[1], quad[2]]),
+      (normal, [quad[0], quad[2], quad[3]]),
+    ];
+
+    let path = test_path("write_read.stl");
+
+    write(&path, &triangles).unwrap();
+    let read_triangles = read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let corners: Vec<[Vector3; 3]> =
+      triangles.iter().map(|(_, corners)| *corners).collect();
+
+    assert_eq!(read_triangles, corners);
+
+    // shared corners become shared vertices (-0 is the same position as 0)
+    let (vertices, faces) = weld(&read_triangles, 0.0);
+
+    assert_eq!(vertices.len(), 4);
+    assert_eq!(faces, vec![vec![0, 1, 2], vec![0, 2, 3]]);
+
+    // corners closer than epsilon are merged, dropping the collapsed triangle
+    let mut near = corners.clone();
+    near[1][1] += Vector3::new(0.0, 0.0, 1e-3);
+    near.push([quad[0], quad[1], quad[1] + Vector3::new(1e-3, 0.0, 0.0)]);
+
+    assert_eq!(weld(&near, 0.0).0.len(), 6);
+
+    let (vertices, faces) = weld(&near, 1e-2);
+
+    assert_eq!(vertices.len(), 4);
+    assert_eq!(faces.len(), 2);
+  }
+}
@@ -1,18 +1,31 @@
 pub mod data_structure;
+pub mod error;
+pub mod file_format;
 pub mod mesh_operation;
+pub mod spatial;
 pub mod utils;
 
 pub use utils::get_normal;
 
+pub use data_structure::Attribute;
+pub use data_structure::Attributes;
 pub use data_structure::DataStructure;
+pub use data_structure::Face;
 pub use data_structure::HalfEdge;
+pub use data_structure::IndexRemap;
 pub use data_structure::IndexType;
 pub use data_structure::Vector3;
 
+pub use error::MeshError;
+
 pub use mesh_operation::Operation;
 
+pub use mesh_operation::CatmullClark;
 pub use mesh_operation::Denoise;
+pub use mesh_operation::Info;
 pub use mesh_operation::Noise;
 pub use mesh_operation::Remesh;
+pub use mesh_operation::Repair;
 pub use mesh_operation::Simplify;
+pub use mesh_operation::Smooth;
 pub use mesh_operation::Subdivide;
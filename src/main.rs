@@ -1,25 +1,75 @@
-use clap::Clap;
+use clap::{ArgSettings, Clap, IntoApp};
+use std::fs;
 use std::path::Path;
 
+use mesh::file_format::{obj, ply, pm, stl};
+use mesh::mesh_operation::{
+  validate, ClusterReport, RepairReport, SimplifyOutcome,
+};
+use mesh::Attributes;
+use mesh::CatmullClark;
 use mesh::DataStructure;
 use mesh::Denoise;
+use mesh::Face;
 use mesh::HalfEdge;
+use mesh::Info;
+use mesh::MeshError;
 use mesh::Noise;
 use mesh::Operation;
 use mesh::Remesh;
+use mesh::Repair;
 use mesh::Simplify;
+use mesh::Smooth;
 use mesh::Subdivide;
+use mesh::Vector3;
+
+// separates operations given on the command line
+const STEP_SEPARATOR: &str = "then";
+
+// problems listed when the input can't be loaded
+const MAX_REPORTED_ISSUES: usize = 10;
 
 #[derive(Clap)]
-#[clap(version = "0.1", author = "Ryan G.")]
+#[clap(
+  version = "0.1",
+  author = "Ryan G.",
+  after_help = "Multiple operations can be chained with \"then\", for \
+                example: mesh in.obj out.obj subdivide 2 then simplify 5000 \
+                (\"then\" is only a separator after the first operation)"
+)]
 struct Opts {
   /// Input mesh file
   infile: String,
-  /// Output mesh file
+  /// Output mesh file (- to not write the mesh, for instance with info)
   outfile: String,
+  /// File with one operation per line (applied after any operations given
+  /// on the command line)
+  #[clap(long = "pipeline")]
+  pipeline: Option<String>,
+  /// Write the mesh after each operation to
+  /// <prefix>_<step>_<operation>.<extension of outfile>
+  #[clap(long = "snapshot")]
+  snapshot: Option<String>,
+  /// Write ply files as ascii instead of binary
+  #[clap(long = "ascii")]
+  ascii: bool,
+  /// Merge vertices of stl input which are closer than this
+  #[clap(long = "weld", default_value = "0")]
+  weld: f32,
+  /// Load the finest level of a progressive mesh (.pm) input with at most
+  /// this many faces (the original mesh by default)
+  #[clap(long = "lod-faces")]
+  lod_faces: Option<usize>,
 
   #[clap(subcommand)]
   /// method
+  method: Option<Methods>,
+}
+
+// one operation after the first
+#[derive(Clap)]
+struct Step {
+  #[clap(subcommand)]
   method: Methods,
 }
 
@@ -28,29 +78,383 @@ enum Methods {
   #[clap(name = "subdivide")]
   /// subdivide the mesh using loop subdivision
   Subdivide(Subdivide),
+  #[clap(name = "catmull-clark")]
+  /// subdivide the mesh into quads using catmull clark subdivision
+  CatmullClark(CatmullClark),
   #[clap(name = "simplify")]
   Simplify(Simplify),
   #[clap(name = "remesh")]
   Remesh(Remesh),
   #[clap(name = "denoise")]
   Denoise(Denoise),
+  #[clap(name = "smooth")]
+  /// move vertices toward their neighbors (laplacian or taubin smoothing)
+  Smooth(Smooth),
   #[clap(name = "noise")]
   Noise(Noise),
+  #[clap(name = "info", alias = "stats")]
+  /// print statistics about the mesh (counts, topology, edge lengths,
+  /// triangle quality, ...)
+  Info(Info),
+  #[clap(name = "repair")]
+  /// fix non manifold input (applied before the mesh is built when it's the
+  /// first operation)
+  Repair(Repair),
+}
+
+impl Methods {
+  fn name(&self) -> &'static str {
+    match self {
+      Methods::Subdivide(_) => "subdivide",
+      Methods::CatmullClark(_) => "catmull-clark",
+      Methods::Simplify(_) => "simplify",
+      Methods::Remesh(_) => "remesh",
+      Methods::Denoise(_) => "denoise",
+      Methods::Smooth(_) => "smooth",
+      Methods::Noise(_) => "noise",
+      Methods::Info(_) => "info",
+      Methods::Repair(_) => "repair",
+    }
+  }
+
+  fn try_apply<D: DataStructure>(&self, mesh: &mut D) -> Result<(), MeshError> {
+    match self {
+      Methods::Subdivide(v) => v.try_apply(mesh),
+      Methods::CatmullClark(v) => v.try_apply(mesh),
+      Methods::Simplify(v) => {
+        let outcome = v.simplify_mesh(mesh)?;
+        print_simplify(&outcome);
+        Ok(())
+      }
+      Methods::Remesh(v) => v.try_apply(mesh),
+      Methods::Denoise(v) => v.try_apply(mesh),
+      Methods::Smooth(v) => v.try_apply(mesh),
+      Methods::Noise(v) => v.try_apply(mesh),
+      Methods::Info(v) => v.try_apply(mesh),
+      Methods::Repair(v) => {
+        let report = v.repair_mesh(mesh)?;
+        print_repair(&report);
+        Ok(())
+      }
+    }
+  }
+}
+
+fn print_repair(report: &RepairReport) {
+  if !report.is_empty() {
+    eprintln!("repair: {}", report);
+  }
+}
+
+fn print_cluster(report: &ClusterReport) {
+  eprintln!("simplify: {}", report);
+
+  if !report.repair.is_empty() {
+    eprintln!("simplify: cleanup {}", report.repair);
+  }
+}
+
+fn print_simplify(outcome: &SimplifyOutcome) {
+  match outcome {
+    SimplifyOutcome::Collapsed(report) => eprintln!("simplify: {}", report),
+    SimplifyOutcome::Progressive {
+      report,
+      levels,
+      path,
+    } => {
+      eprintln!("simplify: recorded {} levels to {}", levels, path.display());
+      eprintln!("simplify: {}", report);
+    }
+    SimplifyOutcome::Clustered(report) => print_cluster(report),
+  }
+}
+
+fn extension(path: &Path) -> String {
+  path
+    .extension()
+    .and_then(|ext| ext.to_str())
+    .unwrap_or("")
+    .to_lowercase()
+}
+
+type RawMesh = (Vec<Vector3>, Vec<Face>, Attributes);
+
+// format is chosen by extension
+fn read(
+  path: &Path,
+  weld: f32,
+  lod_faces: Option<usize>,
+) -> Result<RawMesh, Box<dyn std::error::Error>> {
+  let with_path = |e: std::io::Error| format!("{}: {}", path.display(), e);
+
+  match extension(path).as_str() {
+    "obj" => Ok(obj::read(path).map_err(with_path)?),
+    "ply" => {
+      let data = ply::read(path).map_err(with_path)?;
+      let attributes = data.attributes();
+      Ok((data.vertices, data.faces, attributes))
+    }
+    "stl" => {
+      let (vertices, faces) =
+        stl::weld(&stl::read(path).map_err(with_path)?, weld);
+      Ok((vertices, faces, Attributes::default()))
+    }
+    "pm" => {
+      let progressive = pm::read(path).map_err(with_path)?;
+      let level = match lod_faces {
+        Some(max_faces) => progressive.level_for_faces(max_faces),
+        None => progressive.num_levels() - 1,
+      };
+      Ok(progressive.level(level))
+    }
+    ext => Err(format!("unsupported input file extension \"{}\"", ext).into()),
+  }
+}
+
+// lists everything wrong with the input if it can't be built
+fn build(
+  path: &Path,
+  vertices: &[Vector3],
+  faces: &[Face],
+  attributes: Attributes,
+) -> Result<HalfEdge, Box<dyn std::error::Error>> {
+  let mut mesh =
+    HalfEdge::try_from_iters(vertices.iter().cloned(), faces.iter().cloned())
+      .map_err(|e: MeshError| {
+      let issues = validate(vertices, faces);
+
+      let mut message = format!("{}: {}", path.display(), e);
+
+      if issues.len() > 1 {
+        message += "\nall problems found:";
+
+        for issue in issues.iter().take(MAX_REPORTED_ISSUES) {
+          message += &format!("\n  {}", issue);
+        }
+
+        if issues.len() > MAX_REPORTED_ISSUES {
+          message +=
+            &format!("\n  ... and {} more", issues.len() - MAX_REPORTED_ISSUES);
+        }
+      }
+
+      message += "\nthe repair operation can fix most of these";
+
+      message
+    })?;
+
+  mesh
+    .set_attributes(attributes)
+    .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+  Ok(mesh)
+}
+
+fn save(
+  mesh: &HalfEdge,
+  path: &Path,
+  binary: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let with_path = |e: std::io::Error| format!("{}: {}", path.display(), e);
+
+  match extension(path).as_str() {
+    "obj" => Ok(mesh.save_obj(path).map_err(with_path)?),
+    "ply" => Ok(mesh.save_ply(path, binary).map_err(with_path)?),
+    "stl" => Ok(mesh.save_stl(path).map_err(with_path)?),
+    ext => Err(format!("unsupported output file extension \"{}\"", ext).into()),
+  }
+}
+
+fn parse_step<I: IntoIterator<Item = String>>(
+  args: I,
+) -> clap::Result<Methods> {
+  Step::try_parse_from(std::iter::once("mesh".to_owned()).chain(args))
+    .map(|step| step.method)
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-  let opts: Opts = Opts::parse();
-  let mut mesh = HalfEdge::from_obj(&Path::new(&opts.infile))?;
+fn read_pipeline(
+  path: &str,
+) -> Result<Vec<Methods>, Box<dyn std::error::Error>> {
+  let contents =
+    fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
 
-  match opts.method {
-    Methods::Subdivide(v) => v.apply(&mut mesh),
-    Methods::Simplify(v) => v.apply(&mut mesh),
-    Methods::Remesh(v) => v.apply(&mut mesh),
-    Methods::Denoise(v) => v.apply(&mut mesh),
-    Methods::Noise(v) => v.apply(&mut mesh),
+  let mut methods = Vec::new();
+
+  for (line_idx, line) in contents.lines().enumerate() {
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+
+    let method = parse_step(line.split_whitespace().map(|v| v.to_owned()))
+      .map_err(|e| format!("{} line {}: {}", path, line_idx + 1, e))?;
+
+    methods.push(method);
+  }
+
+  Ok(methods)
+}
+
+// index of the first operation, which is the first positional argument after
+// the input and output files (values of options are skipped), so files and
+// option values may still be called "then"
+fn first_step(args: &[String]) -> usize {
+  let app = Opts::into_app();
+  let takes_value = |arg: &str| {
+    app.get_arguments().any(|option| {
+      option.is_set(ArgSettings::TakesValue)
+        && option
+          .get_long()
+          .map(|long| format!("--{}", long))
+          .as_deref()
+          == Some(arg)
+    })
+  };
+
+  let mut positionals = 0;
+  let mut idx = 1;
+
+  while idx < args.len() {
+    let arg = &args[idx];
+
+    if arg.starts_with('-') && arg != "-" {
+      // the value is the next argument unless given as --option=value
+      if takes_value(arg) {
+        idx += 1;
+      }
+    } else if positionals < 2 {
+      positionals += 1;
+    } else {
+      return idx;
+    }
+
+    idx += 1;
+  }
+
+  args.len()
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+  let args: Vec<String> = std::env::args().collect();
+
+  let first = first_step(&args);
+  let first_end = args[first..]
+    .iter()
+    .position(|arg| arg == STEP_SEPARATOR)
+    .map_or(args.len(), |idx| first + idx);
+
+  let opts = Opts::parse_from(&args[..first_end]);
+
+  let mut methods: Vec<Methods> = opts.method.into_iter().collect();
+
+  if first_end < args.len() {
+    for segment in args[first_end + 1..].split(|arg| arg == STEP_SEPARATOR) {
+      methods
+        .push(parse_step(segment.iter().cloned()).unwrap_or_else(|e| e.exit()));
+    }
+  }
+
+  if let Some(path) = &opts.pipeline {
+    methods.extend(read_pipeline(path)?);
+  }
+
+  let outfile = Path::new(&opts.outfile);
+  let write_outfile = opts.outfile != "-";
+
+  // check before doing any work
+  if write_outfile
+    && !["obj", "ply", "stl"].contains(&extension(outfile).as_str())
+  {
+    return Err(
+      format!(
+        "unsupported output file extension \"{}\"",
+        extension(outfile)
+      )
+      .into(),
+    );
+  }
+
+  // snapshots are written in the format of the output file
+  if opts.snapshot.is_some() && !write_outfile {
+    return Err(
+      "--snapshot needs an output file (its extension is the format of the \
+       snapshots)"
+        .into(),
+    );
+  }
+
+  if !opts.weld.is_finite() || opts.weld < 0.0 {
+    return Err(
+      format!("invalid weld distance {} (must not be negative)", opts.weld)
+        .into(),
+    );
+  }
+
+  let infile = Path::new(&opts.infile);
+
+  let (mut vertices, mut faces, mut attributes) =
+    read(infile, opts.weld, opts.lod_faces)?;
+
+  // the input may not be a valid mesh yet, so repair the raw faces; vertex
+  // clustering also works on them, which avoids building the full mesh
+  let applied_first = match methods.first() {
+    Some(Methods::Repair(repair)) => {
+      let report = repair.repair(&mut vertices, &mut faces, &mut attributes);
+      print_repair(&report);
+
+      true
+    }
+    Some(Methods::Simplify(simplify)) if simplify.uses_clustering() => {
+      let report = simplify
+        .cluster(&mut vertices, &mut faces, &mut attributes)
+        .map_err(|e| format!("step 1 (simplify) failed: {}", e))?;
+
+      print_cluster(&report);
+
+      true
+    }
+    _ => false,
   };
 
-  mesh.save_obj(&Path::new(&opts.outfile))?;
+  let mut mesh = build(infile, &vertices, &faces, attributes)?;
+
+  for (i, method) in methods.iter().enumerate() {
+    if i != 0 {
+      // drop elements removed by earlier operations
+      mesh.compact();
+    }
+
+    if i != 0 || !applied_first {
+      method.try_apply(&mut mesh).map_err(|e| {
+        format!("step {} ({}) failed: {}", i + 1, method.name(), e)
+      })?;
+    }
+
+    if let Some(prefix) = &opts.snapshot {
+      let path = format!(
+        "{}_{}_{}.{}",
+        prefix,
+        i + 1,
+        method.name(),
+        extension(outfile)
+      );
+      save(&mesh, Path::new(&path), !opts.ascii)?;
+    }
+  }
+
+  if write_outfile {
+    save(&mesh, outfile, !opts.ascii)?;
+  }
 
   Ok(())
 }
+
+// errors are printed with Display rather than the Debug output of returning
+// them from main
+fn main() {
+  if let Err(e) = run() {
+    eprintln!("error: {}", e);
+    std::process::exit(1);
+  }
+}
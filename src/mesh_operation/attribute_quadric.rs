@@ -0,0 +1,154 @@
+use crate::Vector3;
+
+use nalgebra::{DMatrix, DVector};
+
+use std::ops::Add;
+
+// squared distance of a point in position + attribute space to a set of
+// planes (Garland and Heckbert 1998):
+// error(v) = v^T a v + 2 b^T v + c
+#[derive(Clone)]
+pub struct AttributeQuadric {
+  a: DMatrix<f64>,
+  b: DVector<f64>,
+  c: f64,
+}
+
+impl AttributeQuadric {
+  pub fn zeros(dimension: usize) -> Self {
+    AttributeQuadric {
+      a: DMatrix::zeros(dimension, dimension),
+      b: DVector::zeros(dimension),
+      c: 0.0,
+    }
+  }
+
+  // distance to the plane through the three points (zero for degenerate
+  // triangles)
+  pub fn from_triangle(points: [&DVector<f64>; 3], weight: f64) -> Self {
+    let dimension = points[0].len();
+    let p = points[0];
+
+    let mut out = AttributeQuadric::zeros(dimension);
+
+    let e_1 = points[1] - p;
+    let e_1_norm = e_1.norm();
+
+    if e_1_norm == 0.0 {
+      return out;
+    }
+
+    let e_1 = e_1 / e_1_norm;
+
+    let e_2 = points[2] - p;
+    let e_2 = &e_2 - e_1.dot(&e_2) * &e_1;
+    let e_2_norm = e_2.norm();
+
+    if e_2_norm == 0.0 {
+      return out;
+    }
+
+    let e_2 = e_2 / e_2_norm;
+
+    let p_e_1 = p.dot(&e_1);
+    let p_e_2 = p.dot(&e_2);
+
+    out.a = (DMatrix::identity(dimension, dimension)
+      - &e_1 * e_1.transpose()
+      - &e_2 * e_2.transpose())
+      * weight;
+    out.b = (p_e_1 * &e_1 + p_e_2 * &e_2 - p) * weight;
+    out.c = (p.dot(p) - p_e_1 * p_e_1 - p_e_2 * p_e_2) * weight;
+
+    out
+  }
+
+  // distance to the plane normal . x + d = 0 in position space (attributes
+  // don't contribute)
+  pub fn from_position_plane(
+    dimension: usize,
+    normal: &Vector3,
+    d: f32,
+    weight: f64,
+  ) -> Self {
+    let mut out = AttributeQuadric::zeros(dimension);
+
+    let d = d as f64;
+
+    for i in 0..3 {
+      for j in 0..3 {
+        out.a[(i, j)] = weight * normal[i] as f64 * normal[j] as f64;
+      }
+
+      out.b[i] = weight * d * normal[i] as f64;
+    }
+
+    out.c = weight * d * d;
+
+    out
+  }
+
+  pub fn error(&self, v: &DVector<f64>) -> f64 {
+    (v.transpose() * &self.a * v)[0] + 2.0 * self.b.dot(v) + self.c
+  }
+
+  // point with the least error (None if it isn't unique)
+  pub fn minimize(&self) -> Option<DVector<f64>> {
+    let solution = self.a.clone().cholesky()?.solve(&-&self.b);
+
+    if solution.iter().all(|v| v.is_finite()) {
+      Some(solution)
+    } else {
+      None
+    }
+  }
+
+  // point with the least error which has the given position
+  pub fn minimize_with_position(
+    &self,
+    position: &Vector3,
+  ) -> Option<DVector<f64>> {
+    let dimension = self.b.len();
+    let num_attributes = dimension - 3;
+
+    let mut out = DVector::zeros(dimension);
+
+    for i in 0..3 {
+      out[i] = position[i] as f64;
+    }
+
+    if num_attributes == 0 {
+      return Some(out);
+    }
+
+    // a_aa attributes = -(b_a + a_ap position)
+    let a_aa = self
+      .a
+      .slice((3, 3), (num_attributes, num_attributes))
+      .into_owned();
+    let a_ap = self.a.slice((3, 0), (num_attributes, 3));
+    let rhs = -(self.b.rows(3, num_attributes) + a_ap * out.rows(0, 3));
+
+    let attributes = a_aa.cholesky()?.solve(&rhs);
+
+    if !attributes.iter().all(|v| v.is_finite()) {
+      return None;
+    }
+
+    out.rows_mut(3, num_attributes).copy_from(&attributes);
+
+    Some(out)
+  }
+}
+
+impl Add for AttributeQuadric {
+  type Output = AttributeQuadric;
+
+  fn add(self, other: AttributeQuadric) -> AttributeQuadric {
+    AttributeQuadric {
+      a: self.a + other.a,
+      b: self.b + other.b,
+      c: self.c + other.c,
+    }
+  }
+}
@@ -0,0 +1,265 @@
+use crate::mesh_operation::Operation;
+use crate::Attribute;
+use crate::Attributes;
+use crate::DataStructure;
+use crate::IndexType;
+use crate::MeshError;
+use crate::Vector3;
+
+use clap::Clap;
+
+#[derive(Clap)]
+pub struct CatmullClark {
+  iterations: u32,
+}
+
+impl Operation for CatmullClark {
+  fn try_apply<D: DataStructure>(&self, mesh: &mut D) -> Result<(), MeshError> {
+    for _ in 0..self.iterations {
+      // attributes are looked up by dense indices
+      mesh.compact();
+      let (_, _, old_attributes) = mesh.to_vecs_with_attributes();
+
+      // new vertices are laid out as: old vertices (same indices), then one
+      // per edge, then one per face
+      let edge_point_offset = mesh.max_idx_vertices();
+      let face_point_offset = edge_point_offset + mesh.max_idx_edges();
+
+      let edge_point = |edge_idx: IndexType| {
+        (edge_point_offset + edge_idx as usize) as IndexType
+      };
+      let face_point = |face_idx: IndexType| {
+        (face_point_offset + face_idx as usize) as IndexType
+      };
+
+      let mut positions =
+        vec![Vector3::zeros(); face_point_offset + mesh.max_idx_faces()];
+      let mut faces = Vec::with_capacity(4 * mesh.num_faces());
+
+      let new_attribute = |attribute: &Attribute| {
+        Attribute::new(&attribute.name, attribute.dimension)
+      };
+      let mut attributes = Attributes {
+        vertex: old_attributes.vertex.iter().map(new_attribute).collect(),
+        corner: old_attributes.corner.iter().map(new_attribute).collect(),
+      };
+
+      for (new, old) in attributes.vertex.iter_mut().zip(&old_attributes.vertex)
+      {
+        new.values = old.values.clone();
+        new.values.resize(positions.len() * old.dimension, 0.0);
+      }
+
+      // start of the current face's corners in old_attributes
+      let mut corner_offset = 0;
+
+      let mut vertices = Vec::new();
+      let mut edges = Vec::new();
+
+      let mut face_op = mesh.initial_face();
+      while let Some(face) = face_op {
+        mesh.get_face_vertices(face, &mut vertices);
+        mesh.get_face_edges(face, &mut edges);
+
+        let n = vertices.len();
+
+        positions[face_point(face) as usize] = vertices
+          .iter()
+          .fold(Vector3::zeros(), |acc, v| acc + mesh.get_position(*v))
+          / n as f32;
+
+        let weight = 1.0 / n as f32;
+
+        let face_sources: Vec<_> =
+          vertices.iter().map(|v| (*v, weight)).collect();
+
+        for attribute in &mut attributes.vertex {
+          attribute.set_mix(face_point(face), &face_sources);
+        }
+
+        // one quad per corner
+        for i in 0..n {
+          faces.push(vec![
+            vertices[i],
+            edge_point(edges[i]),
+            face_point(face),
+            edge_point(edges[(i + n - 1) % n]),
+          ]);
+        }
+
+        let corner = |i: usize| (corner_offset + (i % n)) as IndexType;
+        let face_corners: Vec<_> =
+          (0..n).map(|i| (corner(i), weight)).collect();
+
+        for (new, old) in
+          attributes.corner.iter_mut().zip(&old_attributes.corner)
+        {
+          let face_value = old.mix(&face_corners);
+
+          // corners in the same order as the quads above
+          for i in 0..n {
+            new.push(old.get(corner(i)));
+            new.push(&old.mix(&[(corner(i), 0.5), (corner(i + 1), 0.5)]));
+            new.push(&face_value);
+            new.push(&old.mix(&[(corner(i + n - 1), 0.5), (corner(i), 0.5)]));
+          }
+        }
+
+        corner_offset += n;
+
+        face_op = mesh.next_face(face);
+      }
+
+      let mut edge_op = mesh.initial_edge();
+      while let Some(edge) = edge_op {
+        let [v_0, v_1] = mesh.get_endpoints(edge);
+
+        for attribute in &mut attributes.vertex {
+          attribute.set_mix(edge_point(edge), &[(v_0, 0.5), (v_1, 0.5)]);
+        }
+
+        let midpoint = 0.5 * (mesh.get_position(v_0) + mesh.get_position(v_1));
+
+        positions[edge_point(edge) as usize] = match mesh.get_edge_faces(edge) {
+          (f_0, Some(f_1)) => {
+            0.5 * midpoint
+              + 0.25
+                * (positions[face_point(f_0) as usize]
+                  + positions[face_point(f_1) as usize])
+          }
+          // boundary
+          (_, None) => midpoint,
+        };
+
+        edge_op = mesh.next_edge(edge);
+      }
+
+      let mut vertex_edges = Vec::new();
+      let mut adjacent_faces = Vec::new();
+
+      let mut vertex_op = mesh.initial_vertex();
+      while let Some(vertex) = vertex_op {
+        let boundary = mesh.get_vertex_edges(vertex, &mut vertex_edges);
+
+        let orig_pos = mesh.get_position(vertex);
+
+        positions[vertex as usize] = if boundary {
+          // first and last edges are on the boundary
+          let first = vertex_edges[0].1;
+          let last = vertex_edges[vertex_edges.len() - 1].1;
+
+          (3.0 / 4.0) * orig_pos
+            + (1.0 / 8.0) * (mesh.get_position(first) + mesh.get_position(last))
+        } else {
+          mesh.get_vertex_adjacent_faces(vertex, &mut adjacent_faces);
+
+          let n = vertex_edges.len() as f32;
+
+          let face_avg =
+            adjacent_faces.iter().fold(Vector3::zeros(), |acc, f| {
+              acc + positions[face_point(*f) as usize]
+            }) / adjacent_faces.len() as f32;
+
+          let edge_avg =
+            vertex_edges
+              .iter()
+              .fold(Vector3::zeros(), |acc, (_, other)| {
+                acc + 0.5 * (orig_pos + mesh.get_position(*other))
+              })
+              / n;
+
+          (face_avg + 2.0 * edge_avg + (n - 3.0) * orig_pos) / n
+        };
+
+        vertex_op = mesh.next_vertex(vertex);
+      }
+
+      // unused slots (removed vertices, edges or faces) aren't referenced by
+      // any face, so they are dropped
+      *mesh = D::try_from_iters(positions, faces)?;
+      mesh.set_attributes(attributes)?;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::mesh_operation::parallel::vertices;
+  use crate::HalfEdge;
+
+  // corner i has coordinates -1 or 1 from the bits of i (x is bit 0)
+  fn cube() -> HalfEdge {
+    let positions = (0..8).map(|i| {
+      let coord = |bit| if i & bit == 0 { -1.0 } else { 1.0 };
+      Vector3::new(coord(1), coord(2), coord(4))
+    });
+
+    let faces = vec![
+      vec![0, 2, 3, 1],
+      vec![4, 5, 7, 6],
+      vec![0, 1, 5, 4],
+      vec![2, 6, 7, 3],
+      vec![0, 4, 6, 2],
+      vec![1, 3, 7, 5],
+    ];
+
+    HalfEdge::from_iters(positions, faces)
+  }
+
+  fn has_position(mesh: &HalfEdge, position: &Vector3) -> bool {
+    vertices(mesh)
+      .iter()
+      .any(|v| (mesh.get_position(*v) - position).norm() < 1e-6)
+  }
+
+  #[test]
+  fn cube_to_quads() {
+    let mut mesh = cube();
+    let (positions, _) = mesh.to_vecs();
+
+    CatmullClark::try_parse_from(["catmull-clark", "1"])
+      .unwrap()
+      .try_apply(&mut mesh)
+      .unwrap();
+
+    // 8 corners, 12 edge points and 6 face points
+    assert_eq!(mesh.num_vertices(), 26);
+    assert_eq!(mesh.num_edges(), 48);
+    assert_eq!(mesh.num_faces(), 24);
+
+    let mut face_op = mesh.initial_face();
+    while let Some(face) = face_op {
+      assert_eq!(mesh.face_degree(face), 4);
+      face_op = mesh.next_face(face);
+    }
+
+    // (face average + 2 * edge midpoint average) / 3 for the corners
+    for (idx, position) in positions.iter().enumerate() {
+      let expected = (5.0 / 9.0) * position;
+
+      assert!((mesh.get_position(idx as IndexType) - expected).norm() < 1e-6);
+    }
+
+    // face points are the face centers and edge points are the average of
+    // the edge midpoint and the two face points
+    for axis in 0..3 {
+      for sign in &[-1.0, 1.0] {
+        let mut face_point = Vector3::zeros();
+        face_point[axis] = *sign;
+
+        assert!(has_position(&mesh, &face_point));
+
+        for other_sign in &[-1.0, 1.0] {
+          let mut edge_point = Vector3::zeros();
+          edge_point[axis] = 0.75 * sign;
+          edge_point[(axis + 1) % 3] = 0.75 * other_sign;
+
+          assert!(has_position(&mesh, &edge_point));
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,483 @@
+use crate::mesh_operation::repair::{Repair, RepairReport};
+use crate::utils::bounds;
+use crate::Attribute;
+use crate::Attributes;
+use crate::Face;
+use crate::IndexType;
+use crate::MeshError;
+use crate::Vector3;
+
+use nalgebra::{Matrix3, Vector3 as Vector3d, SVD};
+
+use std::collections::HashMap;
+use std::fmt;
+
+type Cell = [i64; 3];
+
+// finest grid resolution tried when searching for a face count
+const MAX_RESOLUTION: u32 = 1 << 16;
+
+// singular values of a cell's quadric smaller than this fraction of the
+// largest are ignored, so flat and straight regions fall back to the mean
+const SINGULAR_VALUE_RATIO: f64 = 1e-3;
+
+#[derive(Default)]
+pub struct ClusterReport {
+  pub initial_vertices: usize,
+  pub vertices: usize,
+  pub initial_faces: usize,
+  pub faces: usize,
+  pub cell_size: f32,
+  // cleanup of the faces which became non manifold
+  pub repair: RepairReport,
+}
+
+impl fmt::Display for ClusterReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{} to {} faces and {} to {} vertices with cell size {}",
+      self.initial_faces,
+      self.faces,
+      self.initial_vertices,
+      self.vertices,
+      self.cell_size
+    )
+  }
+}
+
+// vertices of the face's corners after clustering without repeats of the
+// previous corner (None if fewer than 3 are left)
+fn clustered_corners(
+  face: &Face,
+  clusters: &[IndexType],
+) -> Option<Vec<usize>> {
+  let corners: Vec<usize> = (0..face.len())
+    .filter(|i| {
+      let previous = (i + face.len() - 1) % face.len();
+
+      clusters[face[*i] as usize] != clusters[face[previous] as usize]
+    })
+    .collect();
+
+  if corners.len() >= 3 {
+    Some(corners)
+  } else {
+    None
+  }
+}
+
+pub(crate) fn check_input(
+  vertices: &[Vector3],
+  faces: &[Face],
+) -> Result<(), MeshError> {
+  if let Some(vertex) = vertices
+    .iter()
+    .position(|v| !v.iter().all(|v| v.is_finite()))
+  {
+    return Err(MeshError::InvalidPosition {
+      vertex: vertex as IndexType,
+    });
+  }
+
+  for (face_idx, face) in faces.iter().enumerate() {
+    if let Some(vertex) = face.iter().find(|v| **v as usize >= vertices.len()) {
+      return Err(MeshError::VertexIndexOutOfRange {
+        face: face_idx as IndexType,
+        vertex: *vertex,
+        num_vertices: vertices.len(),
+      });
+    }
+  }
+
+  Ok(())
+}
+
+// cluster of each vertex (numbered in order of first appearance) and the
+// cell of each cluster
+fn assign_clusters(
+  vertices: &[Vector3],
+  origin: &Vector3,
+  cell_size: f32,
+) -> (Vec<IndexType>, Vec<Cell>) {
+  let mut cluster_of_cell = HashMap::new();
+  let mut cells = Vec::new();
+
+  let clusters = vertices
+    .iter()
+    .map(|position| {
+      let offset = (position - origin) / cell_size;
+      let cell = [
+        offset[0].floor() as i64,
+        offset[1].floor() as i64,
+        offset[2].floor() as i64,
+      ];
+
+      *cluster_of_cell.entry(cell).or_insert_with(|| {
+        cells.push(cell);
+        (cells.len() - 1) as IndexType
+      })
+    })
+    .collect();
+
+  (clusters, cells)
+}
+
+// largest cell size whose clustering has at most max_faces faces (not
+// counting faces which end up duplicated)
+pub(crate) fn cell_size_for_faces(
+  vertices: &[Vector3],
+  faces: &[Face],
+  max_faces: usize,
+) -> f32 {
+  let (min, max) = bounds(vertices.iter().cloned());
+  let extent = (max - min).max().max(f32::MIN_POSITIVE);
+
+  let num_faces = |resolution: u32| {
+    let (clusters, _) =
+      assign_clusters(vertices, &min, extent / resolution as f32);
+
+    faces
+      .iter()
+      .filter(|face| clustered_corners(face, &clusters).is_some())
+      .count()
+  };
+
+  // num_faces(low) <= max_faces < num_faces(high)
+  let mut low = 1;
+  let mut high = 2;
+
+  while num_faces(high) <= max_faces {
+    if high >= MAX_RESOLUTION {
+      return extent / high as f32;
+    }
+
+    low = high;
+    high *= 2;
+  }
+
+  while high - low > 1 {
+    let middle = (low + high) / 2;
+
+    if num_faces(middle) <= max_faces {
+      low = middle;
+    } else {
+      high = middle;
+    }
+  }
+
+  extent / low as f32
+}
+
+// least squares point of the planes (a x = -b), restricted to the directions
+// the planes constrain and otherwise at the mean, clamped to the cell
+fn quadric_position(
+  a: &Matrix3<f64>,
+  b: &Vector3d<f64>,
+  mean: &Vector3,
+  cell_min: &Vector3,
+  cell_size: f32,
+) -> Vector3 {
+  let svd = SVD::new(*a, true, true);
+  let eps = svd.singular_values.max() * SINGULAR_VALUE_RATIO;
+
+  let inverse = match svd.pseudo_inverse(eps) {
+    Ok(inverse) => inverse,
+    Err(_) => return *mean,
+  };
+
+  let mean_d = mean.map(|v| v as f64);
+  let position = mean_d - inverse * (a * mean_d + b);
+
+  if !position.iter().all(|v| v.is_finite()) {
+    return *mean;
+  }
+
+  Vector3::from_fn(|i, _| {
+    (position[i] as f32).clamp(cell_min[i], cell_min[i] + cell_size)
+  })
+}
+
+// merges all vertices in each cell of a uniform grid into one and removes
+// the faces which collapse; vertex attributes are averaged, faces keep the
+// attributes of their remaining corners and the result is repaired to make
+// it manifold again
+pub(crate) fn cluster(
+  vertices: &mut Vec<Vector3>,
+  faces: &mut Vec<Face>,
+  attributes: &mut Attributes,
+  cell_size: f32,
+  use_quadrics: bool,
+) -> ClusterReport {
+  let mut report = ClusterReport {
+    initial_vertices: vertices.len(),
+    initial_faces: faces.len(),
+    cell_size,
+    ..ClusterReport::default()
+  };
+
+  let (min, _) = bounds(vertices.iter().cloned());
+  let (clusters, cells) = assign_clusters(vertices, &min, cell_size);
+
+  let mut sums = vec![Vector3::zeros(); cells.len()];
+  let mut counts = vec![0; cells.len()];
+
+  for (position, cluster) in vertices.iter().zip(&clusters) {
+    sums[*cluster as usize] += position;
+    counts[*cluster as usize] += 1;
+  }
+
+  let means: Vec<Vector3> = sums
+    .iter()
+    .zip(&counts)
+    .map(|(sum, count)| sum / *count as f32)
+    .collect();
+
+  let mut positions = means.clone();
+
+  if use_quadrics {
+    let mut a = vec![Matrix3::<f64>::zeros(); cells.len()];
+    let mut b = vec![Vector3d::<f64>::zeros(); cells.len()];
+
+    // area weighted planes of the triangles of each face's fan
+    for face in faces.iter() {
+      let p_0 = vertices[face[0] as usize].map(|v| v as f64);
+
+      for i in 1..face.len().saturating_sub(1) {
+        let p_1 = vertices[face[i] as usize].map(|v| v as f64);
+        let p_2 = vertices[face[i + 1] as usize].map(|v| v as f64);
+
+        let cross = (p_1 - p_0).cross(&(p_2 - p_0));
+        let double_area = cross.norm();
+
+        if double_area == 0.0 {
+          continue;
+        }
+
+        let normal = cross / double_area;
+        let d = -normal.dot(&p_0);
+        let weight = double_area / 2.0;
+
+        for vertex in [face[0], face[i], face[i + 1]].iter() {
+          let cluster = clusters[*vertex as usize] as usize;
+
+          a[cluster] += weight * normal * normal.transpose();
+          b[cluster] += weight * d * normal;
+        }
+      }
+    }
+
+    for (cluster, position) in positions.iter_mut().enumerate() {
+      let cell = cells[cluster];
+      let cell_min = min + Vector3::from_fn(|i, _| cell[i] as f32 * cell_size);
+
+      *position = quadric_position(
+        &a[cluster],
+        &b[cluster],
+        &means[cluster],
+        &cell_min,
+        cell_size,
+      );
+    }
+  }
+
+  // faces and their corner attributes
+  let mut new_faces = Vec::with_capacity(faces.len());
+  let mut new_corners: Vec<Attribute> = attributes
+    .corner
+    .iter()
+    .map(|attribute| Attribute::new(&attribute.name, attribute.dimension))
+    .collect();
+  let mut first_corner = 0;
+
+  for face in faces.iter() {
+    if let Some(corners) = clustered_corners(face, &clusters) {
+      new_faces.push(
+        corners
+          .iter()
+          .map(|i| clusters[face[*i] as usize])
+          .collect::<Face>(),
+      );
+
+      for (new, old) in new_corners.iter_mut().zip(&attributes.corner) {
+        for i in &corners {
+          new.push(old.get((first_corner + i) as IndexType));
+        }
+      }
+    }
+
+    first_corner += face.len();
+  }
+
+  // only clusters used by a face are kept
+  let mut new_idxs = vec![None; cells.len()];
+  let mut members = vec![Vec::new(); cells.len()];
+
+  for face in &new_faces {
+    for cluster in face {
+      new_idxs[*cluster as usize] = Some(0);
+    }
+  }
+
+  for (vertex, cluster) in clusters.iter().enumerate() {
+    members[*cluster as usize].push(vertex as IndexType);
+  }
+
+  let mut new_vertices = Vec::new();
+  let mut new_vertex_attributes: Vec<Attribute> = attributes
+    .vertex
+    .iter()
+    .map(|attribute| Attribute::new(&attribute.name, attribute.dimension))
+    .collect();
+
+  for (cluster, new_idx) in new_idxs.iter_mut().enumerate() {
+    if new_idx.is_none() {
+      continue;
+    }
+
+    *new_idx = Some(new_vertices.len() as IndexType);
+    new_vertices.push(positions[cluster]);
+
+    let weight = 1.0 / members[cluster].len() as f32;
+    let sources: Vec<_> = members[cluster]
+      .iter()
+      .map(|vertex| (*vertex, weight))
+      .collect();
+
+    for (new, old) in new_vertex_attributes.iter_mut().zip(&attributes.vertex) {
+      new.push(&old.mix(&sources));
+    }
+  }
+
+  for face in &mut new_faces {
+    for vertex in face.iter_mut() {
+      *vertex = new_idxs[*vertex as usize].unwrap();
+    }
+  }
+
+  *vertices = new_vertices;
+  *faces = new_faces;
+  attributes.vertex = new_vertex_attributes;
+  attributes.corner = new_corners;
+
+  report.repair = Repair::default().repair(vertices, faces, attributes);
+  report.vertices = vertices.len();
+  report.faces = faces.len();
+
+  report
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // surface of the cube [0, n]^3 split into unit quads
+  fn cube(n: i64) -> (Vec<Vector3>, Vec<Face>) {
+    let mut idxs = HashMap::new();
+    let mut vertices = Vec::new();
+    let mut faces = Vec::new();
+
+    let mut vertex = |p: Cell| {
+      *idxs.entry(p).or_insert_with(|| {
+        vertices.push(Vector3::new(p[0] as f32, p[1] as f32, p[2] as f32));
+        (vertices.len() - 1) as IndexType
+      })
+    };
+
+    for axis in 0..3 {
+      // the normal of a quad going along u and then v points along axis
+      let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+      for side in &[0, n] {
+        for i in 0..n {
+          for j in 0..n {
+            let mut face: Face =
+              [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)]
+                .iter()
+                .map(|(a, b)| {
+                  let mut p = [0; 3];
+                  p[axis] = *side;
+                  p[u] = *a;
+                  p[v] = *b;
+                  vertex(p)
+                })
+                .collect();
+
+            if *side == 0 {
+              face.reverse();
+            }
+
+            faces.push(face);
+          }
+        }
+      }
+    }
+
+    (vertices, faces)
+  }
+
+  #[test]
+  fn cell_size_gives_max_faces() {
+    let (vertices, faces) = cube(8);
+
+    for max_faces in &[6, 40, 100, 300] {
+      let cell_size = cell_size_for_faces(&vertices, &faces, *max_faces);
+
+      let mut vertices = vertices.clone();
+      let mut faces = faces.clone();
+      let report = cluster(
+        &mut vertices,
+        &mut faces,
+        &mut Attributes::default(),
+        cell_size,
+        false,
+      );
+
+      assert!(report.faces <= *max_faces);
+      assert!(report.faces > 0);
+    }
+  }
+
+  #[test]
+  fn quadric_position_is_clamped() {
+    // planes x = 0, y = 0 and z = 0 meet at the origin, outside the cell
+    let a = Matrix3::identity();
+    let b = Vector3d::zeros();
+    let cell_min = Vector3::new(0.5, -1.0, 0.25);
+
+    let position =
+      quadric_position(&a, &b, &Vector3::new(1.0, 0.0, 1.0), &cell_min, 1.0);
+
+    assert_eq!(position, Vector3::new(0.5, 0.0, 0.25));
+  }
+
+  #[test]
+  fn quadrics_keep_cube_corners() {
+    let n = 4;
+    let (mut vertices, mut faces) = cube(n);
+
+    cluster(
+      &mut vertices,
+      &mut faces,
+      &mut Attributes::default(),
+      1.5,
+      true,
+    );
+
+    // corners are where three planes meet, everything else stays on the
+    // surface of the cube
+    for corner in 0..8 {
+      let coord = |bit| if corner & bit == 0 { 0.0 } else { n as f32 };
+      let corner = Vector3::new(coord(1), coord(2), coord(4));
+
+      assert!(vertices.iter().any(|v| (v - corner).norm() < 1e-4));
+    }
+
+    for vertex in &vertices {
+      assert!(vertex.iter().all(|v| *v >= 0.0 && *v <= n as f32));
+      assert!(vertex
+        .iter()
+        .any(|v| v.abs() < 1e-4 || (v - n as f32).abs() < 1e-4));
+    }
+  }
+}
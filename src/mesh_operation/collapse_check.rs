@@ -0,0 +1,130 @@
+use crate::DataStructure;
+use crate::IndexType;
+use crate::MeshError;
+use crate::Vector3;
+
+use clap::Clap;
+
+// geometric checks applied before an edge collapse is committed (the
+// topological ones are done by collapse_edge)
+#[derive(Clap)]
+pub struct CollapseChecks {
+  /// allow collapses which flip the normal of a neighboring face
+  #[clap(long = "allow-flips")]
+  allow_flips: bool,
+  /// reject collapses which leave a face with a lower quality than this (0
+  /// is degenerate, 1 is equilateral) unless the face was already worse
+  #[clap(long = "min-quality", default_value = "0")]
+  min_quality: f32,
+  /// reject collapses which rotate a neighboring face by more than this many
+  /// degrees
+  #[clap(long = "max-dihedral-change", default_value = "180")]
+  max_dihedral_change: f32,
+}
+
+// 4 sqrt(3) area / sum of squared edge lengths, which is 1 for equilateral
+// triangles
+fn quality(normal: &Vector3, positions: &[Vector3; 3]) -> f32 {
+  let squared_lengths: f32 = (0..3)
+    .map(|i| (positions[(i + 1) % 3] - positions[i]).norm_squared())
+    .sum();
+
+  if squared_lengths == 0.0 {
+    return 0.0;
+  }
+
+  2.0 * 3.0f32.sqrt() * normal.norm() / squared_lengths
+}
+
+impl CollapseChecks {
+  pub fn validate(&self) -> Result<(), MeshError> {
+    if !(0.0..=1.0).contains(&self.min_quality) {
+      return Err(MeshError::InvalidArgument(format!(
+        "invalid minimum quality {} (must be between 0 and 1)",
+        self.min_quality
+      )));
+    }
+
+    if !(0.0..=180.0).contains(&self.max_dihedral_change) {
+      return Err(MeshError::InvalidArgument(format!(
+        "invalid maximum dihedral change {} (must be between 0 and 180)",
+        self.max_dihedral_change
+      )));
+    }
+
+    Ok(())
+  }
+
+  // errors with the reason if moving both endpoints of the edge to the
+  // position would break one of the checks (faces on the edge are removed by
+  // the collapse, so they aren't checked)
+  pub fn check<D: DataStructure>(
+    &self,
+    mesh: &D,
+    edge_idx: IndexType,
+    position: &Vector3,
+  ) -> Result<(), &'static str> {
+    let endpoints = mesh.get_endpoints(edge_idx);
+    let (f_0, f_1) = mesh.get_edge_faces(edge_idx);
+
+    let min_cos = self.max_dihedral_change.to_radians().cos();
+
+    let mut faces = Vec::new();
+
+    for vertex in &endpoints {
+      mesh.get_vertex_adjacent_faces(*vertex, &mut faces);
+
+      for face in &faces {
+        if *face == f_0 || Some(*face) == f_1 {
+          continue;
+        }
+
+        let vertices = mesh.get_face_neighbors(*face);
+
+        let old = [
+          mesh.get_position(vertices[0]),
+          mesh.get_position(vertices[1]),
+          mesh.get_position(vertices[2]),
+        ];
+
+        let mut new = old;
+
+        for (new, vertex) in new.iter_mut().zip(&vertices) {
+          if endpoints.contains(vertex) {
+            *new = *position;
+          }
+        }
+
+        let old_normal = (old[1] - old[0]).cross(&(old[2] - old[0]));
+        let new_normal = (new[1] - new[0]).cross(&(new[2] - new[0]));
+
+        let dot = old_normal.dot(&new_normal);
+
+        // a degenerate face has no orientation to flip (the quality check
+        // decides whether it may stay degenerate)
+        if !self.allow_flips && old_normal.norm() > 0.0 && dot <= 0.0 {
+          return Err("a neighboring face would flip");
+        }
+
+        let lengths = old_normal.norm() * new_normal.norm();
+
+        if self.max_dihedral_change < 180.0
+          && lengths > 0.0
+          && dot < min_cos * lengths
+        {
+          return Err("a neighboring face would rotate too far");
+        }
+
+        let new_quality = quality(&new_normal, &new);
+
+        if new_quality < self.min_quality
+          && new_quality < quality(&old_normal, &old)
+        {
+          return Err("a neighboring face would become a sliver");
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
@@ -1,95 +1,507 @@
+use crate::mesh_operation::parallel::{faces, map_slice, map_vertices};
 use crate::mesh_operation::Operation;
+use crate::spatial::PointGrid;
 use crate::DataStructure;
+use crate::IndexType;
+use crate::MeshError;
+use crate::Vector3;
 
-use std::collections::HashSet;
-use std::convert::TryInto;
-use std::iter::FromIterator;
+use clap::{ArgEnum, Clap};
 
-use clap::Clap;
+#[derive(ArgEnum, Clone, Copy, PartialEq)]
+pub enum DenoiseMethod {
+  // bilateral filtering of the vertices (Fleishman et al. 2003)
+  Vertex,
+  // bilateral filtering of the face normals and then moving the vertices to
+  // fit them (Zheng et al. 2011)
+  Normal,
+}
 
 #[derive(Clap)]
 pub struct Denoise {
   iterations: u32,
+  /// spatial standard deviation (distance between vertices, or between face
+  /// centroids for --method normal)
   sigma_c: f32,
+  /// range standard deviation (height over the tangent plane, or the
+  /// distance between unit normals for --method normal)
   sigma_s: f32,
+  /// rings of neighbors in the kernel (of vertices, or of faces sharing a
+  /// vertex for --method normal)
   kernel_size: u32,
+  /// use the neighbors within 2 sigma_c instead of kernel_size rings
+  #[clap(long = "euclidean")]
+  euclidean: bool,
+  /// use the neighbors within this distance instead of kernel_size rings
+  #[clap(long = "radius")]
+  radius: Option<f32>,
+  /// vertex (rounds sharp edges) or normal (keeps creases)
+  #[clap(arg_enum, long = "method", default_value = "vertex")]
+  method: DenoiseMethod,
+  /// vertex updates after filtering the normals (--method normal)
+  #[clap(long = "vertex-iterations", default_value = "20")]
+  vertex_iterations: u32,
 }
 
-impl Operation for Denoise {
-  fn apply<D: DataStructure>(&self, mesh: &mut D) {
-    let mut store = Vec::new();
-    let mut neighborhood = HashSet::new();
-    let mut new_vertices = HashSet::new();
-    let mut neighbors_this_round;
-
-    let mut new_positions = Vec::new();
+// elements reachable in up to kernel_size steps between adjacent elements
+// (including the first); buffers are kept between calls and visits are
+// marked with a stamp so the marks never need clearing
+#[derive(Default)]
+struct Rings {
+  marks: Vec<u32>,
+  stamp: u32,
+  adjacent: Vec<IndexType>,
+}
 
-    for _ in 0..self.iterations {
-      let double_var_c = 2.0 * self.sigma_c.powi(2);
-      let double_var_s = 2.0 * self.sigma_s.powi(2);
+impl Rings {
+  fn collect<F: FnMut(IndexType, &mut Vec<IndexType>)>(
+    &mut self,
+    first: IndexType,
+    kernel_size: u32,
+    max_idx: usize,
+    mut adjacent_to: F,
+    out: &mut Vec<IndexType>,
+  ) {
+    if self.stamp == u32::MAX {
+      self.marks.clear();
+      self.stamp = 0;
+    }
 
-      new_positions.resize(mesh.max_idx_vertices(), None);
+    self.stamp += 1;
+    self.marks.resize(max_idx, 0);
 
-      let mut vertex_op = mesh.initial_vertex();
+    out.clear();
+    out.push(first);
+    self.marks[first as usize] = self.stamp;
 
-      while let Some(vertex_idx) = vertex_op {
-        let normal = mesh.get_vertex_normal(vertex_idx, &mut store);
+    let mut ring_start = 0;
 
-        neighborhood.clear();
+    for _ in 0..kernel_size {
+      let ring_end = out.len();
 
-        neighborhood.insert(vertex_idx);
-        new_vertices.insert(vertex_idx);
+      for ring_idx in ring_start..ring_end {
+        adjacent_to(out[ring_idx], &mut self.adjacent);
 
-        for _ in 0..self.kernel_size {
-          store.clear();
+        for other_idx in &self.adjacent {
+          let mark = &mut self.marks[*other_idx as usize];
 
-          for other_vertex_idx in new_vertices.iter() {
-            mesh.get_vertex_neighbors_append(*other_vertex_idx, &mut store);
+          if *mark != self.stamp {
+            *mark = self.stamp;
+            out.push(*other_idx);
           }
+        }
+      }
 
-          neighbors_this_round = HashSet::from_iter(store.iter().cloned());
+      if ring_end == out.len() {
+        break;
+      }
 
-          new_vertices = HashSet::from_iter(
-            neighbors_this_round.difference(&neighborhood).cloned(),
-          );
+      ring_start = ring_end;
+    }
+  }
+}
+
+// vertex positions in a grid with cells the size of the query radius
+struct VertexGrid {
+  grid: PointGrid,
+  radius: f32,
+  // vertex of each point of the grid
+  vertices: Vec<IndexType>,
+}
+
+impl VertexGrid {
+  fn new<D: DataStructure>(mesh: &D, radius: f32) -> Self {
+    let mut grid = PointGrid::new(radius);
+    let mut vertices = Vec::new();
+
+    let mut vertex_op = mesh.initial_vertex();
+
+    while let Some(vertex_idx) = vertex_op {
+      grid.insert(mesh.get_position(vertex_idx));
+      vertices.push(vertex_idx);
+
+      vertex_op = mesh.next_vertex(vertex_idx);
+    }
+
+    VertexGrid {
+      grid,
+      radius,
+      vertices,
+    }
+  }
+
+  fn within(&self, position: &Vector3, out: &mut Vec<IndexType>) {
+    out.clear();
+
+    let vertices = &self.vertices;
+
+    self.grid.for_each_within(position, self.radius, |idx| {
+      out.push(vertices[idx as usize])
+    });
+  }
+}
+
+// unit normal (zero if degenerate), area and centroid of each face
+fn face_geometry<D: DataStructure>(
+  mesh: &D,
+) -> (Vec<Vector3>, Vec<f32>, Vec<Vector3>) {
+  let mut normals = vec![Vector3::zeros(); mesh.max_idx_faces()];
+  let mut areas = vec![0.0; mesh.max_idx_faces()];
+  let mut centroids = vec![Vector3::zeros(); mesh.max_idx_faces()];
+
+  let mut face_op = mesh.initial_face();
+
+  while let Some(face_idx) = face_op {
+    let (_, [p_0, p_1, p_2]) = mesh.get_face_normal(face_idx);
+    let cross = (p_1 - p_0).cross(&(p_2 - p_0));
+    let double_area = cross.norm();
+
+    if double_area > 0.0 {
+      normals[face_idx as usize] = cross / double_area;
+    }
 
-          neighborhood.extend(&new_vertices);
+    areas[face_idx as usize] = double_area / 2.0;
+    centroids[face_idx as usize] = (p_0 + p_1 + p_2) / 3.0;
+
+    face_op = mesh.next_face(face_idx);
+  }
+
+  (normals, areas, centroids)
+}
+
+impl Denoise {
+  fn validate(&self) -> Result<(), MeshError> {
+    for (name, sigma) in &[("sigma_c", self.sigma_c), ("sigma_s", self.sigma_s)]
+    {
+      if !sigma.is_finite() || *sigma <= 0.0 {
+        return Err(MeshError::InvalidArgument(format!(
+          "invalid {} {} (must be positive)",
+          name, sigma
+        )));
+      }
+    }
+
+    if let Some(radius) = self.radius {
+      if !radius.is_finite() || radius <= 0.0 {
+        return Err(MeshError::InvalidArgument(format!(
+          "invalid radius {} (must be positive)",
+          radius
+        )));
+      }
+    }
+
+    Ok(())
+  }
+
+  // radius of the neighborhoods (None for rings)
+  fn radius(&self) -> Option<f32> {
+    if self.radius.is_some() {
+      self.radius
+    } else if self.euclidean {
+      Some(2.0 * self.sigma_c)
+    } else {
+      None
+    }
+  }
+
+  // each face normal becomes the area and bilaterally weighted average of
+  // the normals around it, then each vertex moves toward the planes of its
+  // faces (through their centroids with the filtered normals)
+  fn filter_normals<D: DataStructure>(&self, mesh: &mut D) {
+    let double_var_c = 2.0 * self.sigma_c.powi(2);
+    let double_var_s = 2.0 * self.sigma_s.powi(2);
+
+    let (mut normals, areas, centroids) = face_geometry(mesh);
+
+    let faces = faces(mesh);
+
+    // faces within the radius of the centroid or kernel_size rings of faces
+    // sharing a vertex
+    let grid = self.radius().map(|radius| {
+      let mut points = PointGrid::new(radius);
+
+      for face_idx in &faces {
+        points.insert(centroids[*face_idx as usize]);
+      }
+
+      (points, radius)
+    });
+
+    let mut neighborhoods = vec![Vec::new(); mesh.max_idx_faces()];
+
+    let found = map_slice(
+      &faces,
+      |(rings, vertices, adjacent_faces): &mut (
+        Rings,
+        Vec<IndexType>,
+        Vec<IndexType>,
+      ),
+       face_idx| {
+        let mut neighborhood = Vec::new();
+
+        match &grid {
+          Some((points, radius)) => {
+            points.for_each_within(
+              &centroids[*face_idx as usize],
+              *radius,
+              |idx| neighborhood.push(faces[idx as usize]),
+            );
+          }
+          None => rings.collect(
+            *face_idx,
+            self.kernel_size,
+            mesh.max_idx_faces(),
+            |other_idx, adjacent| {
+              adjacent.clear();
+              mesh.get_face_vertices(other_idx, vertices);
+
+              for vertex_idx in vertices.iter() {
+                mesh.get_vertex_adjacent_faces(*vertex_idx, adjacent_faces);
+                adjacent.extend_from_slice(adjacent_faces);
+              }
+            },
+            &mut neighborhood,
+          ),
         }
 
-        let vertex_pos = mesh.get_position(vertex_idx);
+        neighborhood
+      },
+    );
 
-        let (sum, normalizer) = neighborhood.iter().fold(
-          (0.0, 0.0),
-          |(sum, normalizer), neighbor| {
-            let neighbor = *neighbor;
-            let diff = vertex_pos - mesh.get_position(neighbor);
+    for (face_idx, neighborhood) in faces.iter().zip(found) {
+      neighborhoods[*face_idx as usize] = neighborhood;
+    }
 
-            let diff_norm = diff.norm();
+    for _ in 0..self.iterations {
+      let filtered = map_slice(&faces, |_: &mut (), face_idx| {
+        let face_idx = *face_idx as usize;
+        let normal = &normals[face_idx];
 
-            let height = normal.dot(&diff);
+        let sum = neighborhoods[face_idx].iter().fold(
+          Vector3::zeros(),
+          |sum, other_idx| {
+            let other_idx = *other_idx as usize;
+            let other = &normals[other_idx];
 
-            let w_c = (-diff_norm.powi(2) / double_var_c).exp();
-            let w_s = (-height.powi(2) / double_var_s).exp();
+            let distance =
+              (centroids[face_idx] - centroids[other_idx]).norm_squared();
+            let difference = (normal - other).norm_squared();
 
-            let w = w_c * w_s;
+            let w_c = (-distance / double_var_c).exp();
+            let w_s = (-difference / double_var_s).exp();
 
-            (sum + w * height, normalizer + w)
+            sum + other * (areas[other_idx] * w_c * w_s)
           },
         );
 
-        new_positions[vertex_idx as usize] =
-          Some(vertex_pos - normal * (sum / normalizer));
+        let norm = sum.norm();
+
+        if norm > 0.0 {
+          sum / norm
+        } else {
+          *normal
+        }
+      });
 
-        vertex_op = mesh.next_vertex(vertex_idx);
+      for (face_idx, normal) in faces.iter().zip(filtered) {
+        normals[*face_idx as usize] = normal;
       }
+    }
+
+    for _ in 0..self.vertex_iterations {
+      let (_, _, centroids) = face_geometry(mesh);
+
+      let new_positions = map_vertices(mesh, |adjacent_faces, vertex_idx| {
+        let position = mesh.get_position(vertex_idx);
+
+        mesh.get_vertex_adjacent_faces(vertex_idx, adjacent_faces);
+
+        let offset =
+          adjacent_faces
+            .iter()
+            .fold(Vector3::zeros(), |sum, face_idx| {
+              let face_idx = *face_idx as usize;
+              let normal = &normals[face_idx];
+
+              sum + normal * normal.dot(&(centroids[face_idx] - position))
+            });
+
+        if adjacent_faces.is_empty() {
+          position
+        } else {
+          position + offset / adjacent_faces.len() as f32
+        }
+      });
+
+      for (vertex_idx, position) in &new_positions {
+        mesh.set_position(*vertex_idx, position);
+      }
+    }
+  }
+
+  fn filter_vertices<D: DataStructure>(&self, mesh: &mut D) {
+    let double_var_c = 2.0 * self.sigma_c.powi(2);
+    let double_var_s = 2.0 * self.sigma_s.powi(2);
+
+    for _ in 0..self.iterations {
+      // positions at the start of the iteration (for the radius queries)
+      let grid = self.radius().map(|radius| VertexGrid::new(mesh, radius));
+
+      let new_positions = map_vertices(
+        mesh,
+        |(rings, store, neighborhood): &mut (
+          Rings,
+          Vec<IndexType>,
+          Vec<IndexType>,
+        ),
+         vertex_idx| {
+          let normal = mesh.get_vertex_normal(vertex_idx, store);
+          let vertex_pos = mesh.get_position(vertex_idx);
+
+          match &grid {
+            Some(grid) => grid.within(&vertex_pos, neighborhood),
+            None => rings.collect(
+              vertex_idx,
+              self.kernel_size,
+              mesh.max_idx_vertices(),
+              |other_idx, adjacent| {
+                mesh.get_vertex_neighbors(other_idx, adjacent);
+              },
+              neighborhood,
+            ),
+          }
+
+          let (sum, normalizer) = neighborhood.iter().fold(
+            (0.0, 0.0),
+            |(sum, normalizer), neighbor| {
+              let neighbor = *neighbor;
+              let diff = vertex_pos - mesh.get_position(neighbor);
+
+              let diff_norm = diff.norm();
+
+              let height = normal.dot(&diff);
+
+              let w_c = (-diff_norm.powi(2) / double_var_c).exp();
+              let w_s = (-height.powi(2) / double_var_s).exp();
+
+              let w = w_c * w_s;
+
+              (sum + w * height, normalizer + w)
+            },
+          );
+
+          vertex_pos - normal * (sum / normalizer)
+        },
+      );
+
+      for (vertex_idx, pos) in &new_positions {
+        mesh.set_position(*vertex_idx, pos);
+      }
+    }
+  }
+}
+
+impl Operation for Denoise {
+  fn try_apply<D: DataStructure>(&self, mesh: &mut D) -> Result<(), MeshError> {
+    self.validate()?;
+
+    match self.method {
+      DenoiseMethod::Vertex => self.filter_vertices(mesh),
+      DenoiseMethod::Normal => {
+        mesh.check_triangle_mesh()?;
+        self.filter_normals(mesh);
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::mesh_operation::parallel::vertices;
+  use crate::HalfEdge;
+
+  fn denoise(args: &[&str]) -> Denoise {
+    Denoise::try_parse_from(
+      std::iter::once("denoise").chain(args.iter().cloned()),
+    )
+    .unwrap()
+  }
+
+  // n by n grid in the xy plane with z from height
+  fn grid<F: Fn(IndexType) -> f32>(n: IndexType, height: F) -> HalfEdge {
+    let idx = |x: IndexType, y: IndexType| x + y * (n + 1);
+
+    let positions = (0..(n + 1) * (n + 1)).map(|i| {
+      Vector3::new((i % (n + 1)) as f32, (i / (n + 1)) as f32, height(i))
+    });
+
+    let mut faces = Vec::new();
+
+    for y in 0..n {
+      for x in 0..n {
+        faces.push(vec![idx(x, y), idx(x + 1, y), idx(x + 1, y + 1)]);
+        faces.push(vec![idx(x, y), idx(x + 1, y + 1), idx(x, y + 1)]);
+      }
+    }
+
+    HalfEdge::from_iters(positions, faces)
+  }
+
+  // a fixed pseudo random height in [-0.1, 0.1]
+  fn noise(i: IndexType) -> f32 {
+    ((i * 7919 + 13) % 101) as f32 / 500.0 - 0.1
+  }
+
+  fn rms_height(mesh: &HalfEdge) -> f32 {
+    let vertices = vertices(mesh);
+
+    let sum: f32 = vertices
+      .iter()
+      .map(|v| mesh.get_position(*v)[2].powi(2))
+      .sum();
+
+    (sum / vertices.len() as f32).sqrt()
+  }
+
+  #[test]
+  fn noisy_plane_gets_flatter() {
+    let mut mesh = grid(10, noise);
+    let before = rms_height(&mesh);
+
+    denoise(&["5", "2", "0.5", "2", "--method", "normal"])
+      .try_apply(&mut mesh)
+      .unwrap();
+
+    let after = rms_height(&mesh);
+
+    assert!(after < 0.5 * before, "{} vs {}", after, before);
+  }
+
+  #[test]
+  fn radius_matches_brute_force() {
+    let mesh = grid(8, |i| 0.3 * noise(i));
+
+    for radius in &[0.5, 1.0, 1.5, 3.7] {
+      let grid = VertexGrid::new(&mesh, *radius);
+      let mut found = Vec::new();
+
+      for v in vertices(&mesh) {
+        let position = mesh.get_position(v);
+
+        grid.within(&position, &mut found);
+        found.sort_unstable();
 
-      let iter = new_positions
-        .iter()
-        .enumerate()
-        .filter_map(|(vertex_idx, pos_op)| pos_op.map(|x| (vertex_idx, x)));
+        let expected: Vec<_> = vertices(&mesh)
+          .into_iter()
+          .filter(|other| {
+            (mesh.get_position(*other) - position).norm() <= *radius
+          })
+          .collect();
 
-      for (vertex_idx, pos) in iter {
-        mesh.set_position(vertex_idx.try_into().unwrap(), &pos);
+        assert_eq!(found, expected);
       }
     }
   }
@@ -0,0 +1,433 @@
+use crate::mesh_operation::Operation;
+use crate::utils::DisjointSets;
+use crate::DataStructure;
+use crate::MeshError;
+use crate::Vector3;
+
+use clap::Clap;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+
+// longest bar of a histogram
+const BAR_WIDTH: usize = 40;
+
+// counts of values in equal width bins from start to end
+pub struct Histogram {
+  pub start: f32,
+  pub end: f32,
+  pub counts: Vec<usize>,
+  // of the values
+  pub min: f32,
+  pub max: f32,
+  pub mean: f32,
+}
+
+impl Histogram {
+  // the bins span the values unless a range is given
+  pub fn new(values: &[f32], range: Option<(f32, f32)>, bins: usize) -> Self {
+    let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let (start, end) = range.unwrap_or((min, max));
+
+    let bins = bins.max(1);
+    let mut counts = vec![0; bins];
+
+    for value in values {
+      let fraction = if end > start {
+        (value - start) / (end - start)
+      } else {
+        0.0
+      };
+
+      let bin = (fraction * bins as f32).max(0.0) as usize;
+
+      counts[bin.min(bins - 1)] += 1;
+    }
+
+    let mean = if values.is_empty() {
+      0.0
+    } else {
+      (values.iter().map(|v| *v as f64).sum::<f64>() / values.len() as f64)
+        as f32
+    };
+
+    Histogram {
+      start,
+      end,
+      counts,
+      min,
+      max,
+      mean,
+    }
+  }
+}
+
+impl fmt::Display for Histogram {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.counts.iter().all(|count| *count == 0) {
+      return write!(f, "none");
+    }
+
+    write!(f, "min {} mean {} max {}", self.min, self.mean, self.max)?;
+
+    let largest = *self.counts.iter().max().unwrap();
+    let width = (self.end - self.start) / self.counts.len() as f32;
+
+    for (bin, count) in self.counts.iter().enumerate() {
+      write!(
+        f,
+        "\n  {:>10.4} - {:<10.4} {:>8} {}",
+        self.start + width * bin as f32,
+        self.start + width * (bin + 1) as f32,
+        count,
+        "#".repeat((count * BAR_WIDTH + largest / 2) / largest)
+      )?;
+    }
+
+    Ok(())
+  }
+}
+
+pub struct MeshStats {
+  pub vertices: usize,
+  pub edges: usize,
+  pub faces: usize,
+  // faces which aren't triangles (skipped by the triangle quality)
+  pub polygons: usize,
+  pub euler_characteristic: i64,
+  // assuming an orientable surface, from the euler characteristic, components
+  // and boundary loops
+  pub genus: i64,
+  pub boundary_loops: usize,
+  pub components: usize,
+  pub bounds: Option<(Vector3, Vector3)>,
+  pub area: f64,
+  // signed volume enclosed (only meaningful without boundary loops)
+  pub volume: f64,
+  pub edge_lengths: Histogram,
+  // 4 sqrt(3) area / sum of squared edge lengths, 1 for equilateral and 0
+  // for degenerate triangles
+  pub triangle_quality: Histogram,
+  // number of vertices with each valence
+  pub valences: BTreeMap<usize, usize>,
+}
+
+impl fmt::Display for MeshStats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(
+      f,
+      "vertices {} edges {} faces {}",
+      self.vertices, self.edges, self.faces
+    )?;
+
+    if self.polygons > 0 {
+      writeln!(f, "faces which aren't triangles {}", self.polygons)?;
+    }
+
+    writeln!(
+      f,
+      "euler characteristic {} genus {}",
+      self.euler_characteristic, self.genus
+    )?;
+    writeln!(
+      f,
+      "connected components {} boundary loops {}",
+      self.components, self.boundary_loops
+    )?;
+
+    if let Some((min, max)) = &self.bounds {
+      let size = max - min;
+
+      writeln!(
+        f,
+        "bounding box ({}, {}, {}) to ({}, {}, {}) diagonal {}",
+        min[0],
+        min[1],
+        min[2],
+        max[0],
+        max[1],
+        max[2],
+        size.norm()
+      )?;
+    }
+
+    write!(f, "surface area {} volume {}", self.area, self.volume)?;
+
+    if self.boundary_loops > 0 {
+      write!(f, " (the mesh isn't closed)")?;
+    }
+
+    writeln!(f)?;
+    writeln!(f, "edge length {}", self.edge_lengths)?;
+    writeln!(f, "triangle quality {}", self.triangle_quality)?;
+    write!(f, "valence")?;
+
+    for (valence, count) in &self.valences {
+      write!(f, " {}:{}", valence, count)?;
+    }
+
+    Ok(())
+  }
+}
+
+// prints statistics about the mesh (the mesh isn't changed)
+#[derive(Clap)]
+pub struct Info {
+  /// number of bins of the histograms
+  #[clap(long = "bins", default_value = "10")]
+  bins: usize,
+}
+
+impl Info {
+  pub fn stats<D: DataStructure>(&self, mesh: &D) -> MeshStats {
+    let max_idx = mesh.max_idx_vertices();
+
+    // components connect through any edge, boundary loops through boundary
+    // edges
+    let mut components = DisjointSets::new(max_idx);
+    let mut loops = DisjointSets::new(max_idx);
+    let mut on_boundary = vec![false; max_idx];
+
+    let mut lengths = Vec::with_capacity(mesh.num_edges());
+
+    let mut edge_op = mesh.initial_edge();
+
+    while let Some(edge_idx) = edge_op {
+      let [first, second] = mesh.get_endpoints(edge_idx);
+
+      components.union(first as usize, second as usize);
+
+      if mesh.get_edge_faces(edge_idx).1.is_none() {
+        loops.union(first as usize, second as usize);
+        on_boundary[first as usize] = true;
+        on_boundary[second as usize] = true;
+      }
+
+      lengths
+        .push((mesh.get_position(first) - mesh.get_position(second)).norm());
+
+      edge_op = mesh.next_edge(edge_idx);
+    }
+
+    let mut num_components = 0;
+    let mut boundary_loops = 0;
+    let mut bounds: Option<(Vector3, Vector3)> = None;
+    let mut valences = BTreeMap::new();
+
+    let mut vertex_op = mesh.initial_vertex();
+
+    while let Some(vertex_idx) = vertex_op {
+      if components.find(vertex_idx as usize) == vertex_idx as usize {
+        num_components += 1;
+      }
+
+      if on_boundary[vertex_idx as usize]
+        && loops.find(vertex_idx as usize) == vertex_idx as usize
+      {
+        boundary_loops += 1;
+      }
+
+      let position = mesh.get_position(vertex_idx);
+
+      bounds = Some(match bounds {
+        Some((min, max)) => (
+          min.zip_map(&position, |a, b| a.min(b)),
+          max.zip_map(&position, |a, b| a.max(b)),
+        ),
+        None => (position, position),
+      });
+
+      *valences.entry(mesh.degree(vertex_idx)).or_insert(0) += 1;
+
+      vertex_op = mesh.next_vertex(vertex_idx);
+    }
+
+    let mut area = 0.0;
+    let mut volume = 0.0;
+    let mut polygons = 0;
+    let mut qualities = Vec::with_capacity(mesh.num_faces());
+    let mut vertices = Vec::new();
+
+    let mut face_op = mesh.initial_face();
+
+    while let Some(face_idx) = face_op {
+      mesh.get_face_vertices(face_idx, &mut vertices);
+
+      let positions: Vec<nalgebra::Vector3<f64>> = vertices
+        .iter()
+        .map(|vertex_idx| mesh.get_position(*vertex_idx).map(|v| v as f64))
+        .collect();
+
+      // fan of triangles from the first vertex
+      for i in 1..positions.len().saturating_sub(1) {
+        let [p_0, p_1, p_2] = [positions[0], positions[i], positions[i + 1]];
+        let double_area = (p_1 - p_0).cross(&(p_2 - p_0)).norm();
+
+        area += double_area / 2.0;
+        volume += p_0.dot(&p_1.cross(&p_2)) / 6.0;
+
+        if positions.len() == 3 {
+          let squared_lengths = (p_1 - p_0).norm_squared()
+            + (p_2 - p_1).norm_squared()
+            + (p_0 - p_2).norm_squared();
+
+          qualities.push(if squared_lengths > 0.0 {
+            (2.0 * 3.0f64.sqrt() * double_area / squared_lengths) as f32
+          } else {
+            0.0
+          });
+        }
+      }
+
+      if positions.len() != 3 {
+        polygons += 1;
+      }
+
+      face_op = mesh.next_face(face_idx);
+    }
+
+    let euler_characteristic = mesh.num_vertices() as i64
+      - mesh.num_edges() as i64
+      + mesh.num_faces() as i64;
+
+    MeshStats {
+      vertices: mesh.num_vertices(),
+      edges: mesh.num_edges(),
+      faces: mesh.num_faces(),
+      polygons,
+      euler_characteristic,
+      genus: (2 * num_components as i64
+        - boundary_loops as i64
+        - euler_characteristic)
+        / 2,
+      boundary_loops,
+      components: num_components,
+      bounds,
+      area,
+      volume,
+      edge_lengths: Histogram::new(&lengths, None, self.bins),
+      triangle_quality: Histogram::new(&qualities, Some((0.0, 1.0)), self.bins),
+      valences,
+    }
+  }
+}
+
+impl Operation for Info {
+  fn try_apply<D: DataStructure>(&self, mesh: &mut D) -> Result<(), MeshError> {
+    if self.bins == 0 {
+      return Err(MeshError::InvalidArgument(
+        "invalid bins 0 (must be positive)".to_owned(),
+      ));
+    }
+
+    // a closed pipe (for instance through head) isn't an error of the
+    // operation
+    let _ = writeln!(io::stdout(), "{}", self.stats(mesh));
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Face;
+  use crate::HalfEdge;
+  use crate::IndexType;
+
+  fn stats(vertices: Vec<Vector3>, faces: Vec<Face>) -> MeshStats {
+    let mesh = HalfEdge::from_iters(vertices, faces);
+
+    Info::try_parse_from(["info"]).unwrap().stats(&mesh)
+  }
+
+  // n by m quads (two triangles each), joined at opposite sides if wrap
+  fn grid(n: IndexType, m: IndexType, wrap: bool) -> MeshStats {
+    let (columns, rows) = if wrap { (n, m) } else { (n + 1, m + 1) };
+    let idx = |x: IndexType, y: IndexType| x % columns + (y % rows) * columns;
+
+    let vertices = (0..columns * rows)
+      .map(|i| {
+        let (x, y) = ((i % columns) as f32, (i / columns) as f32);
+
+        if wrap {
+          // torus
+          let (u, v) = (
+            x / n as f32 * std::f32::consts::PI * 2.0,
+            y / m as f32 * std::f32::consts::PI * 2.0,
+          );
+          let radius = 2.0 + v.cos();
+
+          Vector3::new(radius * u.cos(), radius * u.sin(), v.sin())
+        } else {
+          Vector3::new(x, y, 0.0)
+        }
+      })
+      .collect();
+
+    let mut faces = Vec::new();
+
+    for y in 0..m {
+      for x in 0..n {
+        faces.push(vec![idx(x, y), idx(x + 1, y), idx(x + 1, y + 1)]);
+        faces.push(vec![idx(x, y), idx(x + 1, y + 1), idx(x, y + 1)]);
+      }
+    }
+
+    stats(vertices, faces)
+  }
+
+  #[test]
+  fn closed_tetrahedron() {
+    let stats = stats(
+      vec![
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(1.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        Vector3::new(0.0, 0.0, 1.0),
+      ],
+      vec![vec![0, 2, 1], vec![0, 1, 3], vec![1, 2, 3], vec![0, 3, 2]],
+    );
+
+    assert_eq!(stats.euler_characteristic, 2);
+    assert_eq!(stats.genus, 0);
+    assert_eq!(stats.boundary_loops, 0);
+    assert_eq!(stats.components, 1);
+    assert!((stats.volume - 1.0 / 6.0).abs() < 1e-6);
+  }
+
+  #[test]
+  fn open_grid_and_torus() {
+    let open = grid(3, 2, false);
+
+    assert_eq!(open.euler_characteristic, 1);
+    assert_eq!(open.genus, 0);
+    assert_eq!(open.boundary_loops, 1);
+    assert_eq!(open.components, 1);
+
+    let torus = grid(6, 4, true);
+
+    assert_eq!(torus.euler_characteristic, 0);
+    assert_eq!(torus.genus, 1);
+    assert_eq!(torus.boundary_loops, 0);
+    assert_eq!(torus.components, 1);
+  }
+
+  #[test]
+  fn disjoint_triangles() {
+    let vertices = (0..6)
+      .map(|i| {
+        Vector3::new((i % 3) as f32, (i % 3 == 2) as u8 as f32, i as f32)
+      })
+      .collect();
+
+    let stats = stats(vertices, vec![vec![0, 1, 2], vec![3, 4, 5]]);
+
+    assert_eq!(stats.components, 2);
+    assert_eq!(stats.boundary_loops, 2);
+    assert_eq!(stats.euler_characteristic, 2);
+    assert_eq!(stats.genus, 0);
+  }
+}
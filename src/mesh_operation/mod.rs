@@ -1,17 +1,42 @@
 use crate::DataStructure;
+use crate::MeshError;
 
 pub trait Operation {
-  fn apply<D: DataStructure>(&self, mesh: &mut D);
+  // the mesh may be partially modified if this errors
+  fn try_apply<D: DataStructure>(&self, mesh: &mut D) -> Result<(), MeshError>;
+
+  // panics if the operation fails (see try_apply)
+  fn apply<D: DataStructure>(&self, mesh: &mut D) {
+    self.try_apply(mesh).unwrap_or_else(|e| panic!("{}", e))
+  }
 }
 
+mod attribute_quadric;
+mod catmull_clark;
+mod cluster;
+mod collapse_check;
 mod denoise;
+mod info;
 mod noise;
+mod parallel;
+mod progressive;
 mod remesh;
+mod repair;
+mod sharp_edges;
 mod simplify;
+mod smooth;
 mod subdivide;
 
-pub use denoise::Denoise;
-pub use noise::Noise;
+pub use catmull_clark::CatmullClark;
+pub use cluster::ClusterReport;
+pub use collapse_check::CollapseChecks;
+pub use denoise::{Denoise, DenoiseMethod};
+pub use info::{Histogram, Info, MeshStats};
+pub use noise::{Noise, NoiseDistribution, NoiseScale};
+pub use progressive::{FaceRecord, ProgressiveMesh, VertexRecord, VertexSplit};
 pub use remesh::Remesh;
-pub use simplify::Simplify;
+pub use repair::{validate, Repair, RepairReport};
+pub use sharp_edges::SharpEdges;
+pub use simplify::{Simplify, SimplifyOutcome, SimplifyReport, StopReason};
+pub use smooth::{Smooth, SmoothWeights};
 pub use subdivide::Subdivide;
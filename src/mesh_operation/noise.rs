@@ -1,32 +1,191 @@
+use crate::mesh_operation::parallel::map_vertices;
 use crate::mesh_operation::Operation;
+use crate::utils::{bounding_box_diagonal, mean_edge_length};
 use crate::DataStructure;
+use crate::IndexType;
+use crate::MeshError;
+use crate::Vector3;
 
-use rand_distr::{Distribution, Normal};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution, Normal, Uniform, UnitSphere};
 
-use clap::Clap;
+use clap::{ArgEnum, Clap};
+
+#[derive(ArgEnum, Clone, Copy, PartialEq)]
+pub enum NoiseDistribution {
+  // sigma is the standard deviation
+  Normal,
+  // offsets are between -sigma and sigma
+  Uniform,
+}
+
+#[derive(ArgEnum, Clone, Copy, PartialEq)]
+pub enum NoiseScale {
+  Absolute,
+  // sigma is a fraction of the mean edge length
+  MeanEdge,
+  // sigma is a fraction of the bounding box diagonal
+  Diagonal,
+}
 
 #[derive(Clap)]
 pub struct Noise {
+  /// standard deviation (normal) or largest offset (uniform)
   sigma: f32,
+  /// distribution of the offsets (normal or uniform)
+  #[clap(arg_enum, long = "distribution", default_value = "normal")]
+  distribution: NoiseDistribution,
+  /// move each vertex in a random direction instead of along its normal
+  #[clap(long = "isotropic")]
+  isotropic: bool,
+  /// sigma is absolute or a fraction of the mean edge length or the
+  /// bounding box diagonal
+  #[clap(arg_enum, long = "relative-to", default_value = "absolute")]
+  relative_to: NoiseScale,
+  /// seed of the random numbers (the same mesh and seed always give the same
+  /// noise)
+  #[clap(long = "seed")]
+  seed: Option<u64>,
+}
+
+enum Offset {
+  Normal(Normal<f32>),
+  Uniform(Uniform<f32>),
+}
+
+impl Distribution<f32> for Offset {
+  fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f32 {
+    match self {
+      Offset::Normal(dist) => dist.sample(rng),
+      Offset::Uniform(dist) => dist.sample(rng),
+    }
+  }
+}
+
+// seed of the generator of a vertex: the index is spread over the bits and
+// mixed with the seed by the splitmix64 finalizer, so neighboring vertices and
+// seeds don't get correlated generators
+fn vertex_seed(seed: u64, vertex_idx: IndexType) -> u64 {
+  let mut z = seed ^ (vertex_idx as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+
+  z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+  z ^ (z >> 31)
+}
+
+impl Noise {
+  fn offset<D: DataStructure>(&self, mesh: &D) -> Result<Offset, MeshError> {
+    let invalid = || {
+      MeshError::InvalidArgument(format!("invalid noise sigma {}", self.sigma))
+    };
+
+    if !self.sigma.is_finite() || self.sigma < 0.0 {
+      return Err(invalid());
+    }
+
+    let sigma = match self.relative_to {
+      NoiseScale::Absolute => self.sigma,
+      NoiseScale::MeanEdge => self.sigma * mean_edge_length(mesh),
+      NoiseScale::Diagonal => self.sigma * bounding_box_diagonal(mesh),
+    };
+
+    Ok(match self.distribution {
+      NoiseDistribution::Normal => {
+        Offset::Normal(Normal::new(0.0, sigma).map_err(|_| invalid())?)
+      }
+      NoiseDistribution::Uniform => {
+        Offset::Uniform(Uniform::new_inclusive(-sigma, sigma))
+      }
+    })
+  }
 }
 
 impl Operation for Noise {
-  fn apply<D: DataStructure>(&self, mesh: &mut D) {
-    let mut vertex_op = mesh.initial_vertex();
-    let mut store = Vec::new();
+  fn try_apply<D: DataStructure>(&self, mesh: &mut D) -> Result<(), MeshError> {
+    let offset = self.offset(mesh)?;
 
-    let dist =
-      Normal::new(0.0, self.sigma).expect("distribution should be valid");
+    // each vertex draws from its own generator (see vertex_seed) so the noise
+    // doesn't depend on the order vertices are visited in; the generator is
+    // named (unlike StdRng, whose algorithm may change between versions of
+    // rand) so a seed always gives the same noise
+    let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
 
-    while let Some(vertex_idx) = vertex_op {
-      let normal = mesh.get_vertex_normal(vertex_idx, &mut store);
+    // normals are all from the positions before any noise is added
+    let new_positions = map_vertices(mesh, |store, vertex_idx| {
+      let mut rng = ChaCha8Rng::seed_from_u64(vertex_seed(seed, vertex_idx));
 
-      let noise = normal * dist.sample(&mut rand::thread_rng());
+      let direction = if self.isotropic {
+        let [x, y, z]: [f32; 3] = UnitSphere.sample(&mut rng);
 
-      let new_position = mesh.get_position(vertex_idx) + noise;
+        Vector3::new(x, y, z)
+      } else {
+        mesh.get_vertex_normal(vertex_idx, store)
+      };
+
+      mesh.get_position(vertex_idx) + direction * offset.sample(&mut rng)
+    });
+
+    for (vertex_idx, new_position) in new_positions {
       mesh.set_position(vertex_idx, &new_position);
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::HalfEdge;
+
+  fn noisy_positions(args: &[&str]) -> Vec<Vector3> {
+    let n = 4;
+    let idx = |x: IndexType, y: IndexType| x + y * (n + 1);
+
+    let positions = (0..(n + 1) * (n + 1))
+      .map(|i| Vector3::new((i % (n + 1)) as f32, (i / (n + 1)) as f32, 0.0));
+
+    let mut faces = Vec::new();
+
+    for y in 0..n {
+      for x in 0..n {
+        faces.push(vec![idx(x, y), idx(x + 1, y), idx(x + 1, y + 1)]);
+        faces.push(vec![idx(x, y), idx(x + 1, y + 1), idx(x, y + 1)]);
+      }
+    }
+
+    let mut mesh = HalfEdge::from_iters(positions, faces);
+
+    Noise::try_parse_from(std::iter::once("noise").chain(args.iter().cloned()))
+      .unwrap()
+      .try_apply(&mut mesh)
+      .unwrap();
+
+    mesh.to_vecs().0
+  }
+
+  #[test]
+  fn seed_gives_same_noise() {
+    for extra_args in &[&[][..], &["--isotropic", "--distribution", "uniform"]]
+    {
+      let with_seed = |seed: &str| {
+        let mut args = vec!["0.1", "--seed", seed];
+        args.extend_from_slice(extra_args);
+
+        noisy_positions(&args)
+      };
+
+      let first = with_seed("3");
+
+      assert_eq!(first, with_seed("3"));
+      assert_ne!(first, with_seed("4"));
 
-      vertex_op = mesh.next_vertex(vertex_idx);
+      // every vertex gets its own offset
+      for (i, position) in first.iter().enumerate() {
+        assert!(first[..i].iter().all(|other| other[2] != position[2]));
+      }
     }
   }
 }
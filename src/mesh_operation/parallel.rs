@@ -0,0 +1,193 @@
+use crate::DataStructure;
+use crate::IndexType;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+// f applied to each item in order; with the parallel feature the items are
+// split between threads, each with its own buffers (from Default) which f
+// can reuse between items, and the results are the same as without it
+pub(crate) fn map_slice<I, B, T, F>(items: &[I], f: F) -> Vec<T>
+where
+  I: Sync,
+  B: Default,
+  T: Send,
+  F: Fn(&mut B, &I) -> T + Sync + Send,
+{
+  #[cfg(feature = "parallel")]
+  {
+    items.par_iter().map_init(B::default, f).collect()
+  }
+
+  #[cfg(not(feature = "parallel"))]
+  {
+    let mut buffers = B::default();
+
+    items.iter().map(|item| f(&mut buffers, item)).collect()
+  }
+}
+
+pub(crate) fn vertices<D: DataStructure>(mesh: &D) -> Vec<IndexType> {
+  let mut out = Vec::with_capacity(mesh.num_vertices());
+  let mut vertex_op = mesh.initial_vertex();
+
+  while let Some(vertex_idx) = vertex_op {
+    out.push(vertex_idx);
+    vertex_op = mesh.next_vertex(vertex_idx);
+  }
+
+  out
+}
+
+pub(crate) fn faces<D: DataStructure>(mesh: &D) -> Vec<IndexType> {
+  let mut out = Vec::with_capacity(mesh.num_faces());
+  let mut face_op = mesh.initial_face();
+
+  while let Some(face_idx) = face_op {
+    out.push(face_idx);
+    face_op = mesh.next_face(face_idx);
+  }
+
+  out
+}
+
+// f applied to each vertex (see map_slice), paired with the vertex in the
+// order of initial_vertex/next_vertex
+pub(crate) fn map_vertices<D, B, T, F>(mesh: &D, f: F) -> Vec<(IndexType, T)>
+where
+  D: DataStructure,
+  B: Default,
+  T: Send,
+  F: Fn(&mut B, IndexType) -> T + Sync + Send,
+{
+  let vertices = vertices(mesh);
+  let values =
+    map_slice(&vertices, |buffers, vertex_idx| f(buffers, *vertex_idx));
+
+  vertices.into_iter().zip(values).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::mesh_operation::Operation;
+  use crate::HalfEdge;
+  use crate::Subdivide;
+  use crate::Vector3;
+
+  use clap::Clap;
+
+  fn parse<T: Clap>(args: &[&str]) -> T {
+    T::try_parse_from(args.iter().cloned()).unwrap()
+  }
+
+  // subdivided octahedron with bumps, so every operation has work to do
+  fn bumpy_sphere() -> HalfEdge {
+    let positions = vec![
+      Vector3::new(0.0, 0.0, 1.0),
+      Vector3::new(0.0, 0.0, -1.0),
+      Vector3::new(1.0, 0.0, 0.0),
+      Vector3::new(0.0, 1.0, 0.0),
+      Vector3::new(-1.0, 0.0, 0.0),
+      Vector3::new(0.0, -1.0, 0.0),
+    ];
+    let faces = (0..4).flat_map(|i| {
+      let (a, b) = (2 + i, 2 + (i + 1) % 4);
+
+      vec![vec![0, a, b], vec![1, b, a]]
+    });
+
+    let mut mesh = HalfEdge::from_iters(positions, faces);
+
+    parse::<Subdivide>(&["subdivide", "2"])
+      .try_apply(&mut mesh)
+      .unwrap();
+
+    for v in vertices(&mesh) {
+      let scale = 1.0 + ((v * 7919 + 13) % 101) as f32 / 1000.0;
+      let position = mesh.get_position(v).normalize() * scale;
+
+      mesh.set_position(v, &position);
+    }
+
+    mesh
+  }
+
+  #[test]
+  fn map_vertices_matches_loop() {
+    let mesh = bumpy_sphere();
+
+    let mapped = map_vertices(&mesh, |neighbors, vertex_idx| {
+      mesh.get_vertex_neighbors(vertex_idx, neighbors);
+      neighbors.len() as f32 * mesh.get_position(vertex_idx)
+    });
+
+    let mut neighbors = Vec::new();
+    let mut expected = Vec::new();
+    let mut vertex_op = mesh.initial_vertex();
+
+    while let Some(vertex_idx) = vertex_op {
+      mesh.get_vertex_neighbors(vertex_idx, &mut neighbors);
+      expected.push((
+        vertex_idx,
+        neighbors.len() as f32 * mesh.get_position(vertex_idx),
+      ));
+
+      vertex_op = mesh.next_vertex(vertex_idx);
+    }
+
+    assert_eq!(mapped, expected);
+  }
+
+  // several threads give exactly the same meshes as one
+  #[cfg(feature = "parallel")]
+  #[test]
+  fn operations_match_one_thread() {
+    use crate::Denoise;
+    use crate::Noise;
+    use crate::Remesh;
+
+    type Apply<'a> = dyn Fn(&mut HalfEdge) + Sync + 'a;
+
+    let denoise_vertex: Denoise = parse(&["denoise", "2", "0.1", "0.05", "2"]);
+    let denoise_normal: Denoise = parse(&[
+      "denoise", "2", "0.2", "0.3", "1", "--method", "normal", "--radius",
+      "0.4",
+    ]);
+    let noise: Noise = parse(&["noise", "0.05", "--seed", "7"]);
+    let isotropic_noise: Noise =
+      parse(&["noise", "0.05", "--seed", "7", "--isotropic"]);
+    let remesh: Remesh = parse(&["remesh", "2", "0.5"]);
+    let subdivide: Subdivide = parse(&["subdivide", "1"]);
+
+    let operations: Vec<Box<Apply<'_>>> = vec![
+      Box::new(|mesh| denoise_vertex.try_apply(mesh).unwrap()),
+      Box::new(|mesh| denoise_normal.try_apply(mesh).unwrap()),
+      Box::new(|mesh| noise.try_apply(mesh).unwrap()),
+      Box::new(|mesh| isotropic_noise.try_apply(mesh).unwrap()),
+      Box::new(|mesh| remesh.try_apply(mesh).unwrap()),
+      Box::new(|mesh| subdivide.try_apply(mesh).unwrap()),
+    ];
+
+    let run = |threads: usize, operation: &Apply<'_>| {
+      let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+
+      pool.install(|| {
+        let mut mesh = bumpy_sphere();
+        operation(&mut mesh);
+        mesh.to_vecs()
+      })
+    };
+
+    for operation in &operations {
+      let (sequential_vertices, sequential_faces) = run(1, operation.as_ref());
+      let (vertices, faces) = run(4, operation.as_ref());
+
+      assert_eq!(vertices, sequential_vertices);
+      assert_eq!(faces, sequential_faces);
+    }
+  }
+}
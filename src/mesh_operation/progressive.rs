@@ -0,0 +1,412 @@
+use crate::Attribute;
+use crate::Attributes;
+use crate::DataStructure;
+use crate::Face;
+use crate::IndexType;
+use crate::Vector3;
+
+// position and all vertex attributes one after the other
+#[derive(Clone)]
+pub struct VertexRecord {
+  pub vertex: IndexType,
+  pub position: Vector3,
+  pub attributes: Vec<f32>,
+}
+
+// vertices and all corner attributes of each corner one after the other
+#[derive(Clone)]
+pub struct FaceRecord {
+  pub face: IndexType,
+  pub vertices: Face,
+  pub corners: Vec<f32>,
+}
+
+// undoes one edge collapse
+#[derive(Clone)]
+pub struct VertexSplit {
+  // the vertex removed by the collapse
+  pub vertex: VertexRecord,
+  // the vertex it was collapsed into, as it was before the collapse
+  pub parent: VertexRecord,
+  // faces around both vertices before the collapse (including the faces the
+  // collapse removed)
+  pub faces: Vec<FaceRecord>,
+}
+
+// coarsest mesh and the vertex splits which refine it back to the original;
+// indices are those of the mesh which was simplified (so there may be gaps)
+pub struct ProgressiveMesh {
+  // names and dimensions of the attributes
+  pub vertex_layout: Vec<(String, usize)>,
+  pub corner_layout: Vec<(String, usize)>,
+  pub base_vertices: Vec<VertexRecord>,
+  pub base_faces: Vec<FaceRecord>,
+  // coarsest first
+  pub splits: Vec<VertexSplit>,
+}
+
+pub(crate) fn record_vertex<D: DataStructure>(
+  mesh: &D,
+  vertex: IndexType,
+) -> VertexRecord {
+  let mut attributes = Vec::new();
+  mesh.get_vertex_attributes(vertex, &mut attributes);
+
+  VertexRecord {
+    vertex,
+    position: mesh.get_position(vertex),
+    attributes,
+  }
+}
+
+pub(crate) fn record_face<D: DataStructure>(
+  mesh: &D,
+  face: IndexType,
+) -> FaceRecord {
+  let mut vertices = Vec::new();
+  mesh.get_face_vertices(face, &mut vertices);
+
+  let mut corners = Vec::new();
+  let mut values = Vec::new();
+
+  for vertex in &vertices {
+    mesh.get_corner_attributes(*vertex, face, &mut values);
+    corners.extend_from_slice(&values);
+  }
+
+  FaceRecord {
+    face,
+    vertices,
+    corners,
+  }
+}
+
+// faces around both endpoints of an edge
+pub(crate) fn record_faces<D: DataStructure>(
+  mesh: &D,
+  vertices: [IndexType; 2],
+) -> Vec<FaceRecord> {
+  let mut faces = Vec::new();
+  let mut adjacent_faces = Vec::new();
+
+  for vertex in &vertices {
+    mesh.get_vertex_adjacent_faces(*vertex, &mut adjacent_faces);
+
+    for face in &adjacent_faces {
+      if !faces.contains(face) {
+        faces.push(*face);
+      }
+    }
+  }
+
+  faces
+    .into_iter()
+    .map(|face| record_face(mesh, face))
+    .collect()
+}
+
+fn layout(attributes: &[Attribute]) -> Vec<(String, usize)> {
+  attributes
+    .iter()
+    .map(|attribute| (attribute.name.clone(), attribute.dimension))
+    .collect()
+}
+
+impl ProgressiveMesh {
+  // the mesh is the result of the collapses which the splits (in the order
+  // of the collapses) undo
+  pub fn new<D: DataStructure>(
+    mesh: &D,
+    attributes: &Attributes,
+    mut splits: Vec<VertexSplit>,
+  ) -> Self {
+    let mut base_vertices = Vec::new();
+    let mut vertex_op = mesh.initial_vertex();
+
+    while let Some(vertex) = vertex_op {
+      base_vertices.push(record_vertex(mesh, vertex));
+      vertex_op = mesh.next_vertex(vertex);
+    }
+
+    let mut base_faces = Vec::new();
+    let mut face_op = mesh.initial_face();
+
+    while let Some(face) = face_op {
+      base_faces.push(record_face(mesh, face));
+      face_op = mesh.next_face(face);
+    }
+
+    splits.reverse();
+
+    ProgressiveMesh {
+      vertex_layout: layout(&attributes.vertex),
+      corner_layout: layout(&attributes.corner),
+      base_vertices,
+      base_faces,
+      splits,
+    }
+  }
+
+  pub fn num_levels(&self) -> usize {
+    self.splits.len() + 1
+  }
+
+  // number of faces at each level (splits only ever add faces)
+  pub fn face_counts(&self) -> Vec<usize> {
+    let mut alive = Vec::new();
+    let mut count = 0;
+
+    let mut add = |faces: &[FaceRecord]| {
+      for record in faces {
+        let face = record.face as usize;
+
+        if alive.len() <= face {
+          alive.resize(face + 1, false);
+        }
+
+        if !alive[face] {
+          alive[face] = true;
+          count += 1;
+        }
+      }
+
+      count
+    };
+
+    let mut counts = Vec::with_capacity(self.num_levels());
+
+    counts.push(add(&self.base_faces));
+
+    for split in &self.splits {
+      counts.push(add(&split.faces));
+    }
+
+    counts
+  }
+
+  // finest level with at most max_faces faces (the coarsest if even that has
+  // more)
+  pub fn level_for_faces(&self, max_faces: usize) -> usize {
+    self
+      .face_counts()
+      .iter()
+      .rposition(|count| *count <= max_faces)
+      .unwrap_or(0)
+  }
+
+  // mesh with the first `level` splits applied, with attributes in the same
+  // order as the vertices and the corners of the faces
+  pub fn level(&self, level: usize) -> (Vec<Vector3>, Vec<Face>, Attributes) {
+    let mut vertices: Vec<Option<&VertexRecord>> = Vec::new();
+    let mut faces: Vec<Option<&FaceRecord>> = Vec::new();
+
+    fn set<'a, T>(
+      slots: &mut Vec<Option<&'a T>>,
+      idx: IndexType,
+      value: &'a T,
+    ) {
+      let idx = idx as usize;
+
+      if slots.len() <= idx {
+        slots.resize(idx + 1, None);
+      }
+
+      slots[idx] = Some(value);
+    }
+
+    for record in &self.base_vertices {
+      set(&mut vertices, record.vertex, record);
+    }
+
+    for record in &self.base_faces {
+      set(&mut faces, record.face, record);
+    }
+
+    for split in &self.splits[..level.min(self.splits.len())] {
+      set(&mut vertices, split.vertex.vertex, &split.vertex);
+      set(&mut vertices, split.parent.vertex, &split.parent);
+
+      for record in &split.faces {
+        set(&mut faces, record.face, record);
+      }
+    }
+
+    let faces: Vec<&FaceRecord> = faces.into_iter().flatten().collect();
+
+    // only vertices used by a face are kept
+    let mut new_idxs = vec![None; vertices.len()];
+
+    for record in &faces {
+      for vertex in &record.vertices {
+        new_idxs[*vertex as usize] = Some(0);
+      }
+    }
+
+    let mut positions = Vec::new();
+    let mut vertex_values = Vec::new();
+
+    for (vertex, new_idx) in new_idxs.iter_mut().enumerate() {
+      if new_idx.is_some() {
+        let record = vertices[vertex].expect("face uses a missing vertex");
+
+        *new_idx = Some(positions.len() as IndexType);
+        positions.push(record.position);
+        vertex_values.push(&record.attributes[..]);
+      }
+    }
+
+    let out_faces = faces
+      .iter()
+      .map(|record| {
+        record
+          .vertices
+          .iter()
+          .map(|vertex| new_idxs[*vertex as usize].unwrap())
+          .collect()
+      })
+      .collect();
+
+    let corner_values: Vec<&[f32]> = faces
+      .iter()
+      .flat_map(|record| {
+        let stride = record.corners.len() / record.vertices.len().max(1);
+
+        (0..record.vertices.len())
+          .map(move |i| &record.corners[i * stride..(i + 1) * stride])
+      })
+      .collect();
+
+    let attributes = Attributes {
+      vertex: split_values(&self.vertex_layout, &vertex_values),
+      corner: split_values(&self.corner_layout, &corner_values),
+    };
+
+    (positions, out_faces, attributes)
+  }
+}
+
+// separates concatenated attribute values by layout
+fn split_values(
+  layout: &[(String, usize)],
+  elements: &[&[f32]],
+) -> Vec<Attribute> {
+  let mut start = 0;
+
+  layout
+    .iter()
+    .map(|(name, dimension)| {
+      let mut attribute = Attribute::new(name, *dimension);
+
+      for values in elements {
+        attribute.push(&values[start..start + dimension]);
+      }
+
+      start += dimension;
+
+      attribute
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::HalfEdge;
+  use crate::Simplify;
+
+  use clap::Clap;
+
+  // positions of the vertices of each face (starting at the smallest vertex
+  // index, so rotated faces compare equal), sorted
+  fn face_positions(vertices: &[Vector3], faces: &[Face]) -> Vec<Vec<Vector3>> {
+    let mut out: Vec<Vec<Vector3>> = faces
+      .iter()
+      .map(|face| {
+        let start = (0..face.len()).min_by_key(|i| face[*i]).unwrap();
+
+        (0..face.len())
+          .map(|i| vertices[face[(start + i) % face.len()] as usize])
+          .collect()
+      })
+      .collect();
+
+    out.sort_by(|a, b| {
+      let key = |face: &Vec<Vector3>| {
+        face
+          .iter()
+          .flat_map(|p| p.iter().cloned())
+          .collect::<Vec<_>>()
+      };
+
+      key(a).partial_cmp(&key(b)).unwrap()
+    });
+
+    out
+  }
+
+  #[test]
+  fn levels_rebuild_meshes() {
+    let n = 4;
+    let idx = |x: IndexType, y: IndexType| x + y * (n + 1);
+
+    let positions: Vec<_> = (0..(n + 1) * (n + 1))
+      .map(|i| {
+        let (x, y) = (i % (n + 1), i / (n + 1));
+        Vector3::new(x as f32, y as f32, 0.25 * ((x * y) % 3) as f32)
+      })
+      .collect();
+
+    let mut faces = Vec::new();
+
+    for y in 0..n {
+      for x in 0..n {
+        faces.push(vec![idx(x, y), idx(x + 1, y), idx(x + 1, y + 1)]);
+        faces.push(vec![idx(x, y), idx(x + 1, y + 1), idx(x, y + 1)]);
+      }
+    }
+
+    let mut color = Attribute::new("color", 1);
+    color.values = positions.iter().map(|p| p[0] + p[2]).collect();
+
+    let mut mesh = HalfEdge::from_iters(positions.clone(), faces.clone());
+    mesh
+      .set_attributes(Attributes {
+        vertex: vec![color.clone()],
+        corner: Vec::new(),
+      })
+      .unwrap();
+
+    let (report, progressive) =
+      Simplify::try_parse_from(["simplify", "--target-faces", "10"])
+        .unwrap()
+        .simplify_progressive(&mut mesh)
+        .unwrap();
+
+    let full = progressive.num_levels() - 1;
+
+    assert_eq!(full, report.collapses);
+    assert_eq!(progressive.level_for_faces(faces.len()), full);
+    assert_eq!(progressive.level_for_faces(report.faces), 0);
+
+    // all splits give back the original mesh
+    let (level_vertices, level_faces, level_attributes) =
+      progressive.level(full);
+
+    assert_eq!(level_vertices, positions);
+    assert_eq!(
+      face_positions(&level_vertices, &level_faces),
+      face_positions(&positions, &faces)
+    );
+    assert_eq!(level_attributes.vertex[0].values, color.values);
+
+    // and none the simplified one
+    let (simplified_vertices, simplified_faces) = mesh.to_vecs();
+    let (level_vertices, level_faces, _) = progressive.level(0);
+
+    assert_eq!(level_faces.len(), report.faces);
+    assert_eq!(
+      face_positions(&level_vertices, &level_faces),
+      face_positions(&simplified_vertices, &simplified_faces)
+    );
+  }
+}
@@ -1,10 +1,17 @@
+use crate::mesh_operation::parallel::map_vertices;
+use crate::mesh_operation::CollapseChecks;
 use crate::mesh_operation::Operation;
+use crate::mesh_operation::SharpEdges;
+use crate::spatial::TriangleBvh;
+use crate::utils::{bounding_box_diagonal, mean_edge_length};
 use crate::DataStructure;
+use crate::IndexType;
+use crate::MeshError;
 use crate::Vector3;
 
 use clap::Clap;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clap)]
 pub struct Remesh {
@@ -14,87 +21,495 @@ pub struct Remesh {
   no_collapse: bool,
   #[clap(long = "no-flip")]
   no_flip: bool,
+  /// don't move smoothed vertices back onto the closest point of the input
+  /// surface
+  #[clap(long = "no-reproject")]
+  no_reproject: bool,
+  /// edge length to aim for (the average edge length of the input by
+  /// default)
+  #[clap(long = "target-length")]
+  target_length: Option<f32>,
+  /// edge length to aim for as a fraction of the bounding box diagonal
+  #[clap(long = "relative-length")]
+  relative_length: Option<f32>,
+  /// shorten edges where the surface is curved so they stay within
+  /// --tolerance of it (the target length becomes the longest allowed)
+  #[clap(long = "adaptive")]
+  adaptive: bool,
+  /// distance edges may deviate from the surface with --adaptive (5% of the
+  /// target length by default)
+  #[clap(long = "tolerance")]
+  tolerance: Option<f32>,
+  /// shortest edge length with --adaptive as a fraction of the target length
+  #[clap(long = "min-length-ratio", default_value = "0.1")]
+  min_length_ratio: f32,
+  #[clap(flatten)]
+  checks: CollapseChecks,
+  #[clap(flatten)]
+  sharp_edges: SharpEdges,
 }
 
-impl Operation for Remesh {
-  #[allow(clippy::cognitive_complexity)]
-  fn apply<D: DataStructure>(&self, mesh: &mut D) {
-    for _ in 0..self.iterations {
-      let mut total_edge_len = 0.0;
-      let mut num_edges = 0;
+// how a vertex may move, from the feature edges (sharp or boundary) at it
+#[derive(Clone, Copy, PartialEq)]
+enum VertexKind {
+  Smooth,
+  // on a feature line, with the neighbors along the line
+  Feature([IndexType; 2]),
+  // end or junction of feature lines, which never moves
+  Corner,
+}
 
-      let get_edge_len = |mesh: &D, edge_idx| {
-        let [l, r] = mesh.get_endpoints(edge_idx);
+fn vertex_kind<D: DataStructure>(
+  mesh: &D,
+  features: &[bool],
+  vertex_idx: IndexType,
+  edges: &mut Vec<(IndexType, IndexType)>,
+) -> VertexKind {
+  mesh.get_vertex_edges(vertex_idx, edges);
+
+  let mut feature_neighbors = edges
+    .iter()
+    .filter(|(edge_idx, _)| features[*edge_idx as usize])
+    .map(|(_, other_idx)| *other_idx);
+
+  match (
+    feature_neighbors.next(),
+    feature_neighbors.next(),
+    feature_neighbors.next(),
+  ) {
+    (None, _, _) => VertexKind::Smooth,
+    (Some(first), Some(second), None) => VertexKind::Feature([first, second]),
+    _ => VertexKind::Corner,
+  }
+}
+
+// edges longer than this times their target are split and shorter than
+// SHORT_FACTOR times it are collapsed (Botsch and Kobbelt 2004)
+const LONG_FACTOR: f32 = 4.0 / 3.0;
+const SHORT_FACTOR: f32 = 4.0 / 5.0;
+
+// tolerance with --adaptive as a fraction of the target length
+const DEFAULT_TOLERANCE: f32 = 0.05;
+
+fn get_edge_len<D: DataStructure>(mesh: &D, edge_idx: IndexType) -> f32 {
+  let [l, r] = mesh.get_endpoints(edge_idx);
+
+  let val = (mesh.get_position(l) - mesh.get_position(r)).norm();
+
+  debug_assert!(!val.is_nan());
+
+  val
+}
+
+impl Remesh {
+  fn validate(&self) -> Result<(), MeshError> {
+    let positive = |value: Option<f32>| match value {
+      Some(value) => value.is_finite() && value > 0.0,
+      None => true,
+    };
+
+    if self.target_length.is_some() && self.relative_length.is_some() {
+      return Err(MeshError::InvalidArgument(
+        "only one of --target-length and --relative-length can be given"
+          .to_owned(),
+      ));
+    }
+
+    if !positive(self.target_length)
+      || !positive(self.relative_length)
+      || !positive(self.tolerance)
+    {
+      return Err(MeshError::InvalidArgument(
+        "edge lengths and the tolerance must be positive".to_owned(),
+      ));
+    }
 
-        let val = (mesh.get_position(l) - mesh.get_position(r)).norm();
+    if !(self.min_length_ratio > 0.0 && self.min_length_ratio <= 1.0) {
+      return Err(MeshError::InvalidArgument(format!(
+        "invalid minimum length ratio {} (must be above 0 and at most 1)",
+        self.min_length_ratio
+      )));
+    }
 
-        debug_assert!(!val.is_nan());
+    Ok(())
+  }
+
+  // fixed for all iterations so the resolution doesn't drift
+  fn target_length<D: DataStructure>(&self, mesh: &D) -> f32 {
+    if let Some(target_length) = self.target_length {
+      return target_length;
+    }
+
+    if let Some(relative_length) = self.relative_length {
+      return relative_length * bounding_box_diagonal(mesh);
+    }
+
+    mean_edge_length(mesh)
+  }
 
-        val
+  // target edge length around each vertex: the length of a chord within the
+  // tolerance of a circle with the largest curvature along the vertex's
+  // edges, sqrt(6 tolerance / curvature - 3 tolerance^2), clamped to the
+  // allowed lengths
+  fn vertex_targets<D: DataStructure>(
+    &self,
+    mesh: &D,
+    features: &[bool],
+    target_length: f32,
+  ) -> Vec<f32> {
+    let mut out = vec![target_length; mesh.max_idx_vertices()];
+
+    let tolerance = self.tolerance.unwrap_or(DEFAULT_TOLERANCE * target_length);
+    let min_length = self.min_length_ratio * target_length;
+
+    let mut neighbors = Vec::new();
+    let mut edges = Vec::new();
+    let mut store = Vec::new();
+
+    let mut vertex_op = mesh.initial_vertex();
+
+    while let Some(vertex_idx) = vertex_op {
+      let position = mesh.get_position(vertex_idx);
+
+      let curvature = match vertex_kind(mesh, features, vertex_idx, &mut edges)
+      {
+        // edges to corners get the target of their other end
+        VertexKind::Corner => {
+          vertex_op = mesh.next_vertex(vertex_idx);
+          continue;
+        }
+        // curvature of the circle through the vertex and its neighbors
+        // along the line, 2 sin(angle at the vertex) / |chord|
+        VertexKind::Feature([first, second]) => {
+          let to_first = mesh.get_position(first) - position;
+          let to_second = mesh.get_position(second) - position;
+          let chord = (to_second - to_first).norm();
+
+          let curvature = 2.0 * to_first.cross(&to_second).norm()
+            / (to_first.norm() * to_second.norm() * chord);
+
+          if curvature.is_finite() {
+            curvature
+          } else {
+            0.0
+          }
+        }
+        VertexKind::Smooth => {
+          mesh.get_vertex_neighbors(vertex_idx, &mut neighbors);
+
+          let normal = mesh.get_vertex_normal(vertex_idx, &mut store);
+
+          // curvature of the circle through both endpoints which is tangent
+          // to the surface at this vertex
+          neighbors
+            .iter()
+            .map(|other_idx| {
+              let diff = position - mesh.get_position(*other_idx);
+              let len_squared = diff.norm_squared();
+
+              if len_squared > 0.0 {
+                2.0 * normal.dot(&diff).abs() / len_squared
+              } else {
+                0.0
+              }
+            })
+            .fold(0.0f32, |acc, curvature| {
+              if curvature.is_finite() {
+                acc.max(curvature)
+              } else {
+                acc
+              }
+            })
+        }
       };
 
-      let mut edge_op = mesh.initial_edge();
+      let length_squared =
+        6.0 * tolerance / curvature - 3.0 * tolerance * tolerance;
 
-      while let Some(edge_idx) = edge_op {
-        num_edges += 1;
+      out[vertex_idx as usize] = if length_squared > 0.0 {
+        length_squared.sqrt().clamp(min_length, target_length)
+      } else {
+        min_length
+      };
 
-        total_edge_len += get_edge_len(mesh, edge_idx);
+      vertex_op = mesh.next_vertex(vertex_idx);
+    }
 
-        edge_op = mesh.next_edge(edge_idx);
+    out
+  }
+
+  // position the edge collapses to (None if it would move a feature):
+  // smooth vertices merge into feature vertices, feature vertices only along
+  // their line and corners stay where they are
+  fn collapse_position<D: DataStructure>(
+    mesh: &D,
+    features: &[bool],
+    edge_idx: IndexType,
+    edges: &mut Vec<(IndexType, IndexType)>,
+  ) -> Option<Vector3> {
+    let [l, r] = mesh.get_endpoints(edge_idx);
+
+    let l_kind = vertex_kind(mesh, features, l, edges);
+    let r_kind = vertex_kind(mesh, features, r, edges);
+
+    let midpoint = (mesh.get_position(l) + mesh.get_position(r)) * 0.5;
+
+    match (l_kind, r_kind) {
+      (VertexKind::Smooth, VertexKind::Smooth) => Some(midpoint),
+      (VertexKind::Smooth, _) => Some(mesh.get_position(r)),
+      (_, VertexKind::Smooth) => Some(mesh.get_position(l)),
+      _ if !features[edge_idx as usize] => None,
+      (VertexKind::Feature(_), VertexKind::Feature(_)) => Some(midpoint),
+      (VertexKind::Corner, VertexKind::Feature(_)) => {
+        Some(mesh.get_position(l))
+      }
+      (VertexKind::Feature(_), VertexKind::Corner) => {
+        Some(mesh.get_position(r))
       }
+      (VertexKind::Corner, VertexKind::Corner) => None,
+    }
+  }
 
-      let avg_edge_len = total_edge_len / (num_edges as f32);
+  // if flipping the edge from l to r (with top and bottom the opposite
+  // vertices of its faces) would turn one of the new faces over
+  fn flip_folds<D: DataStructure>(
+    mesh: &D,
+    [l, r, top, bottom]: [IndexType; 4],
+  ) -> bool {
+    let [l, r, top, bottom] = [
+      mesh.get_position(l),
+      mesh.get_position(r),
+      mesh.get_position(top),
+      mesh.get_position(bottom),
+    ];
+
+    let normal = |a: Vector3, b: Vector3, c: Vector3| (b - a).cross(&(c - a));
+
+    let old_normal = normal(l, r, top) + normal(r, l, bottom);
+
+    normal(top, l, bottom).dot(&old_normal) <= 0.0
+      || normal(bottom, r, top).dot(&old_normal) <= 0.0
+  }
 
-      let mut to_split = Vec::new();
-      let mut to_collapse = Vec::new();
+  // if collapsing the edge to the position leaves an edge which would be
+  // split again
+  fn collapse_too_long<D: DataStructure>(
+    mesh: &D,
+    edge_idx: IndexType,
+    position: &Vector3,
+    max_len: &dyn Fn(IndexType, IndexType) -> f32,
+    neighbors: &mut Vec<IndexType>,
+  ) -> bool {
+    let endpoints = mesh.get_endpoints(edge_idx);
+
+    for vertex_idx in &endpoints {
+      mesh.get_vertex_neighbors(*vertex_idx, neighbors);
+
+      for other_idx in neighbors.iter() {
+        if !endpoints.contains(other_idx)
+          && (mesh.get_position(*other_idx) - position).norm()
+            > max_len(*vertex_idx, *other_idx)
+        {
+          return true;
+        }
+      }
+    }
 
-      let midpoint = |mesh: &D, edge_idx| {
-        let [l, r] = mesh.get_endpoints(edge_idx);
+    false
+  }
+}
+
+// segments of the feature edges for reprojecting feature vertices (as
+// triangles with a repeated vertex)
+fn bvh_of_edges<D: DataStructure>(mesh: &D, features: &[bool]) -> TriangleBvh {
+  let mut segments = Vec::new();
+  let mut edge_op = mesh.initial_edge();
+
+  while let Some(edge_idx) = edge_op {
+    if features[edge_idx as usize] {
+      let [l, r] = mesh.get_endpoints(edge_idx);
+      let r_position = mesh.get_position(r);
+
+      segments.push([mesh.get_position(l), r_position, r_position]);
+    }
+
+    edge_op = mesh.next_edge(edge_idx);
+  }
+
+  TriangleBvh::new(segments)
+}
+
+fn bvh_of_faces<D: DataStructure>(mesh: &D) -> TriangleBvh {
+  let mut triangles = Vec::with_capacity(mesh.num_faces());
+  let mut face_op = mesh.initial_face();
+
+  while let Some(face_idx) = face_op {
+    triangles.push(mesh.get_face_normal(face_idx).1);
+    face_op = mesh.next_face(face_idx);
+  }
+
+  TriangleBvh::new(triangles)
+}
+
+fn closest_point(bvh: &Option<TriangleBvh>, position: Vector3) -> Vector3 {
+  match bvh {
+    Some(bvh) => bvh
+      .closest_point(&position)
+      .map_or(position, |(closest, _)| closest),
+    None => position,
+  }
+}
+
+impl Operation for Remesh {
+  #[allow(clippy::cognitive_complexity)]
+  fn try_apply<D: DataStructure>(&self, mesh: &mut D) -> Result<(), MeshError> {
+    mesh.check_triangle_mesh()?;
+    self.checks.validate()?;
+    self.validate()?;
+
+    let target_length = self.target_length(mesh);
+
+    // sharp and boundary edges
+    let mut features = self.sharp_edges.find(mesh)?;
+
+    let mut edge_op = mesh.initial_edge();
+
+    while let Some(edge_idx) = edge_op {
+      if mesh.get_edge_faces(edge_idx).1.is_none() {
+        features[edge_idx as usize] = true;
+      }
+
+      edge_op = mesh.next_edge(edge_idx);
+    }
+
+    let (surface, feature_lines) = if self.no_reproject {
+      (None, None)
+    } else {
+      (
+        Some(bvh_of_faces(mesh)),
+        Some(bvh_of_edges(mesh, &features)),
+      )
+    };
 
-        (mesh.get_position(l) + mesh.get_position(r)) * 0.5
+    let mut edges = Vec::new();
+
+    for _ in 0..self.iterations {
+      let vertex_targets = if self.adaptive {
+        Some(self.vertex_targets(mesh, &features, target_length))
+      } else {
+        None
+      };
+
+      // target length of the edge between two vertices (vertices added by
+      // splits in this iteration don't have a target of their own)
+      let edge_target = |l: IndexType, r: IndexType| match &vertex_targets {
+        Some(targets) => {
+          let get = |v: IndexType| {
+            targets.get(v as usize).cloned().unwrap_or(target_length)
+          };
+
+          get(l).min(get(r))
+        }
+        None => target_length,
       };
 
+      let mut to_split = Vec::new();
+      let mut to_collapse = Vec::new();
+
       let mut edge_op = mesh.initial_edge();
 
       while let Some(edge_idx) = edge_op {
         let edge_len = get_edge_len(mesh, edge_idx);
-
-        if edge_len > (4.0 / 3.0) * avg_edge_len {
-          to_split.push((edge_idx, midpoint(mesh, edge_idx)));
-        } else if edge_len < (4.0 / 5.0) * avg_edge_len {
-          to_collapse.push((edge_idx, midpoint(mesh, edge_idx)));
+        let [l, r] = mesh.get_endpoints(edge_idx);
+        let target = edge_target(l, r);
+
+        if edge_len > LONG_FACTOR * target {
+          to_split.push((
+            edge_idx,
+            (mesh.get_position(l) + mesh.get_position(r)) * 0.5,
+          ));
+        } else if edge_len < SHORT_FACTOR * target {
+          to_collapse.push(edge_idx);
         }
 
         edge_op = mesh.next_edge(edge_idx);
       }
 
       for (edge_idx, new_pos) in to_split {
-        let (new_vertex, _) = mesh.split_edge(edge_idx);
+        let (new_vertex, [_, _, new_half], _) = mesh.split_edge(edge_idx);
 
         debug_assert!(!new_pos[0].is_nan());
         debug_assert!(!new_pos[1].is_nan());
         debug_assert!(!new_pos[2].is_nan());
         mesh.set_position(new_vertex, &new_pos);
+
+        // both halves of a feature edge are features
+        features.resize(mesh.max_idx_edges(), false);
+        features[new_half as usize] = features[edge_idx as usize];
       }
 
       if !self.no_collapse {
         let mut removed = HashSet::new();
         let mut store_removed = Vec::new();
         let mut store_modified = Vec::new();
+        let mut neighbors = Vec::new();
+        let mut merged_features = HashMap::new();
 
-        for (edge_idx, new_pos) in to_collapse {
-          if !removed.contains(&edge_idx) {
-            if let Some(vertex_idx) = mesh.collapse_edge(
-              edge_idx,
-              &mut store_modified,
-              &mut store_removed,
-            ) {
-              mesh.set_position(vertex_idx, &new_pos);
+        let max_len = |l, r| LONG_FACTOR * edge_target(l, r);
+
+        for edge_idx in to_collapse {
+          if removed.contains(&edge_idx) {
+            continue;
+          }
+
+          let new_pos = match Remesh::collapse_position(
+            mesh, &features, edge_idx, &mut edges,
+          ) {
+            Some(new_pos) => new_pos,
+            None => continue,
+          };
+
+          if Remesh::collapse_too_long(
+            mesh,
+            edge_idx,
+            &new_pos,
+            &max_len,
+            &mut neighbors,
+          ) || self.checks.check(mesh, edge_idx, &new_pos).is_err()
+          {
+            continue;
+          }
+
+          // edges to the same vertex are merged, the result is a feature if
+          // either was
+          merged_features.clear();
+
+          for vertex_idx in &mesh.get_endpoints(edge_idx) {
+            mesh.get_vertex_edges(*vertex_idx, &mut edges);
+
+            for (other_edge_idx, other_idx) in &edges {
+              *merged_features.entry(*other_idx).or_insert(false) |=
+                features[*other_edge_idx as usize];
             }
+          }
+
+          if let Some(vertex_idx) = mesh.collapse_edge(
+            edge_idx,
+            &mut store_modified,
+            &mut store_removed,
+          ) {
+            mesh.set_position(vertex_idx, &new_pos);
 
-            removed.extend(store_removed.iter().cloned());
-            removed.extend(store_modified.iter().map(|x| x.0));
+            mesh.get_vertex_edges(vertex_idx, &mut edges);
+
+            for (other_edge_idx, other_idx) in &edges {
+              features[*other_edge_idx as usize] =
+                merged_features.get(other_idx).cloned().unwrap_or(false);
+            }
           }
+
+          removed.extend(store_removed.iter().cloned());
+          removed.extend(store_modified.iter().map(|x| x.0));
         }
       }
 
@@ -118,7 +533,12 @@ impl Operation for Remesh {
               + (top_degree - 6).abs()
               + (bottom_degree - 6).abs();
 
-            if flip_dev < no_flip_dev && l_degree > 3 && r_degree > 3 {
+            if flip_dev < no_flip_dev
+              && l_degree > 3
+              && r_degree > 3
+              && !features[edge_idx as usize]
+              && !Remesh::flip_folds(mesh, [l, r, top, bottom])
+            {
               mesh.flip_edge(edge_idx);
             }
           }
@@ -127,45 +547,194 @@ impl Operation for Remesh {
         }
       }
 
-      let mut vertex_op = mesh.initial_vertex();
+      let new_positions = map_vertices(
+        mesh,
+        |(edges, neighbors, store): &mut (
+          Vec<(IndexType, IndexType)>,
+          Vec<IndexType>,
+          Vec<IndexType>,
+        ),
+         vertex_idx| {
+          let orig_position = mesh.get_position(vertex_idx);
+
+          // feature vertices move along their line and corners stay put
+          match vertex_kind(mesh, &features, vertex_idx, edges) {
+            VertexKind::Smooth => {
+              mesh.get_vertex_neighbors(vertex_idx, neighbors);
+
+              let centroid = neighbors.iter().fold(
+                Vector3::zeros(),
+                |acc, other_vertex_idx| {
+                  acc + mesh.get_position(*other_vertex_idx)
+                },
+              ) / neighbors.len() as f32;
+
+              let diff = centroid - orig_position;
+
+              let normal = mesh.get_vertex_normal(vertex_idx, store);
+
+              let delta = diff - (normal.dot(&diff)) * normal;
+
+              if delta[0].is_nan() || delta[1].is_nan() || delta[2].is_nan() {
+                orig_position
+              } else {
+                closest_point(
+                  &surface,
+                  orig_position + self.smoothing_weight * delta,
+                )
+              }
+            }
+            VertexKind::Feature([first, second]) => {
+              let first = mesh.get_position(first);
+              let second = mesh.get_position(second);
+
+              let diff = (first + second) * 0.5 - orig_position;
+              let tangent = (second - first).normalize();
+
+              let delta = tangent.dot(&diff) * tangent;
+
+              if delta[0].is_nan() || delta[1].is_nan() || delta[2].is_nan() {
+                orig_position
+              } else {
+                closest_point(
+                  &feature_lines,
+                  orig_position + self.smoothing_weight * delta,
+                )
+              }
+            }
+            VertexKind::Corner => orig_position,
+          }
+        },
+      );
 
-      let mut new_positions = Vec::new();
-      let mut neighbors = Vec::new();
-      let mut store = Vec::new();
+      for (vertex_idx, new_position) in new_positions {
+        mesh.set_position(vertex_idx, &new_position);
+      }
+    }
 
-      while let Some(vertex_idx) = vertex_op {
-        mesh.get_vertex_neighbors(vertex_idx, &mut neighbors);
+    Ok(())
+  }
+}
 
-        let centroid = neighbors
-          .iter()
-          .fold(Vector3::zeros(), |acc, other_vertex_idx| {
-            acc + mesh.get_position(*other_vertex_idx)
-          })
-          / neighbors.len() as f32;
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::mesh_operation::parallel::vertices;
+  use crate::utils::mean_edge_length;
+  use crate::HalfEdge;
+
+  fn remesh(args: &[&str]) -> Remesh {
+    Remesh::try_parse_from(
+      std::iter::once("remesh").chain(args.iter().cloned()),
+    )
+    .unwrap()
+  }
 
-        let orig_position = mesh.get_position(vertex_idx);
+  // cube [-1, 1]^3 with two triangles per side, corner i has coordinates
+  // from the bits of i (x is bit 0)
+  fn cube() -> HalfEdge {
+    let positions = (0..8).map(|i| {
+      let coord = |bit| if i & bit == 0 { -1.0 } else { 1.0 };
+      Vector3::new(coord(1), coord(2), coord(4))
+    });
+
+    let quads = [
+      [0, 2, 3, 1],
+      [4, 5, 7, 6],
+      [0, 1, 5, 4],
+      [2, 6, 7, 3],
+      [0, 4, 6, 2],
+      [1, 3, 7, 5],
+    ];
+
+    let faces = quads
+      .iter()
+      .flat_map(|[a, b, c, d]| vec![vec![*a, *b, *c], vec![*a, *c, *d]]);
+
+    HalfEdge::from_iters(positions, faces)
+  }
 
-        let diff = centroid - orig_position;
+  fn on_cube(position: &Vector3) -> bool {
+    (position.amax() - 1.0).abs() < 1e-4
+  }
 
-        let normal = mesh.get_vertex_normal(vertex_idx, &mut store);
+  #[test]
+  fn cube_creases_are_kept() {
+    let mut mesh = cube();
 
-        let delta = diff - (normal.dot(&diff)) * normal;
+    remesh(&["5", "0.5", "--target-length", "0.4", "--crease-angle", "45"])
+      .try_apply(&mut mesh)
+      .unwrap();
 
-        let new_position =
-          if delta[0].is_nan() || delta[1].is_nan() || delta[2].is_nan() {
-            orig_position
-          } else {
-            orig_position + self.smoothing_weight * delta
-          };
+    assert!(mesh.num_faces() > 12);
 
-        new_positions.push((vertex_idx, new_position));
+    for v in vertices(&mesh) {
+      assert!(on_cube(&mesh.get_position(v)));
+    }
 
-        vertex_op = mesh.next_vertex(vertex_idx);
-      }
+    for corner in 0..8 {
+      let coord = |bit| if corner & bit == 0 { -1.0 } else { 1.0 };
+      let corner = Vector3::new(coord(1), coord(2), coord(4));
 
-      for (vertex_idx, new_position) in new_positions {
-        mesh.set_position(vertex_idx, &new_position);
+      assert!(vertices(&mesh)
+        .iter()
+        .any(|v| mesh.get_position(*v) == corner));
+    }
+
+    // the sharp edges cover exactly the 12 edges of the cube
+    let mut sharp_length = 0.0;
+
+    let mut edge_op = mesh.initial_edge();
+    while let Some(edge_idx) = edge_op {
+      let (f_0, f_1) = mesh.get_edge_faces(edge_idx);
+      let (n_0, _) = mesh.get_face_normal(f_0);
+      let (n_1, _) = mesh.get_face_normal(f_1.unwrap());
+
+      if n_0.dot(&n_1) < 0.5 {
+        let [l, r] = mesh.get_endpoints(edge_idx);
+        let (p_l, p_r) = (mesh.get_position(l), mesh.get_position(r));
+
+        // two coordinates of both endpoints are on the same sides
+        let shared = (0..3)
+          .filter(|i| p_l[*i].abs() == 1.0 && p_l[*i] == p_r[*i])
+          .count();
+
+        assert_eq!(shared, 2);
+
+        sharp_length += (p_l - p_r).norm();
       }
+
+      edge_op = mesh.next_edge(edge_idx);
+    }
+
+    assert!((sharp_length - 24.0).abs() < 1e-3);
+  }
+
+  #[test]
+  fn target_length_is_reached() {
+    for target in &[0.2, 0.35] {
+      let mut mesh = cube();
+
+      let target_arg = target.to_string();
+      remesh(&[
+        "8",
+        "0.5",
+        "--target-length",
+        &target_arg,
+        "--crease-angle",
+        "45",
+      ])
+      .try_apply(&mut mesh)
+      .unwrap();
+
+      let mean = mean_edge_length(&mesh);
+
+      assert!(
+        (mean - target).abs() < 0.2 * target,
+        "{} vs {}",
+        mean,
+        target
+      );
     }
   }
 }
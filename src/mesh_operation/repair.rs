@@ -0,0 +1,597 @@
+use crate::mesh_operation::Operation;
+use crate::utils::DisjointSets;
+use crate::Attribute;
+use crate::Attributes;
+use crate::DataStructure;
+use crate::Face;
+use crate::IndexType;
+use crate::MeshError;
+use crate::Vector3;
+
+use clap::Clap;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// faces (and the index of the corner the edge starts at) on each edge, keyed
+// by the sorted endpoints
+type EdgeFaces = HashMap<[IndexType; 2], Vec<(usize, usize)>>;
+
+fn edge_faces(faces: &[Face], skip: &[bool]) -> EdgeFaces {
+  let mut out = EdgeFaces::new();
+
+  for (face_idx, face) in faces.iter().enumerate() {
+    if skip[face_idx] {
+      continue;
+    }
+
+    for i in 0..face.len() {
+      let (v_0, v_1) = (face[i], face[(i + 1) % face.len()]);
+
+      out
+        .entry([v_0.min(v_1), v_0.max(v_1)])
+        .or_default()
+        .push((face_idx, i));
+    }
+  }
+
+  out
+}
+
+// if the faces go along the edge in opposite directions
+fn opposite(
+  faces: &[Face],
+  first: (usize, usize),
+  second: (usize, usize),
+) -> bool {
+  faces[first.0][first.1] != faces[second.0][second.1]
+}
+
+// groups the corners of faces into fans around each vertex, corners are
+// joined across edges which connect accepts; returns the start of each
+// face's corners
+fn corner_fans<F>(
+  faces: &[Face],
+  edges: &EdgeFaces,
+  connect: F,
+) -> (Vec<usize>, DisjointSets)
+where
+  F: Fn(&[(usize, usize)]) -> bool,
+{
+  let mut offsets = Vec::with_capacity(faces.len());
+  let mut num_corners = 0;
+
+  for face in faces {
+    offsets.push(num_corners);
+    num_corners += face.len();
+  }
+
+  let mut fans = DisjointSets::new(num_corners);
+
+  for on_edge in edges.values() {
+    if !connect(on_edge) {
+      continue;
+    }
+
+    let corner = |(face_idx, i): (usize, usize)| {
+      offsets[face_idx] + i % faces[face_idx].len()
+    };
+
+    let [(f_0, i_0), (f_1, i_1)] = [on_edge[0], on_edge[1]];
+
+    if opposite(faces, on_edge[0], on_edge[1]) {
+      // f_0 goes v -> w and f_1 goes w -> v
+      fans.union(corner((f_0, i_0)), corner((f_1, i_1 + 1)));
+      fans.union(corner((f_0, i_0 + 1)), corner((f_1, i_1)));
+    } else {
+      fans.union(corner((f_0, i_0)), corner((f_1, i_1)));
+      fans.union(corner((f_0, i_0 + 1)), corner((f_1, i_1 + 1)));
+    }
+  }
+
+  (offsets, fans)
+}
+
+// original corner indices of each face, kept in step with the faces so
+// corner attributes can be rebuilt at the end
+type FaceCorners = Vec<Vec<usize>>;
+
+fn retain_faces(
+  faces: &mut Vec<Face>,
+  corners: &mut FaceCorners,
+  keep: &[bool],
+) {
+  let mut idx = 0;
+  faces.retain(|_| {
+    idx += 1;
+    keep[idx - 1]
+  });
+
+  let mut idx = 0;
+  corners.retain(|_| {
+    idx += 1;
+    keep[idx - 1]
+  });
+}
+
+fn face_key(face: &Face) -> Vec<IndexType> {
+  let mut key = face.clone();
+  key.sort_unstable();
+  key
+}
+
+// all problems which would stop the faces from forming a manifold half edge
+// mesh (faces with invalid indices or positions aren't checked further)
+pub fn validate(vertices: &[Vector3], faces: &[Face]) -> Vec<MeshError> {
+  let mut issues = Vec::new();
+
+  let mut skip = vec![false; faces.len()];
+  let mut invalid_positions = HashSet::new();
+  let mut seen_faces = HashMap::new();
+
+  for (face_idx, face) in faces.iter().enumerate() {
+    let face_idx_out = face_idx as IndexType;
+
+    if let Some(vertex) = face.iter().find(|v| **v as usize >= vertices.len()) {
+      issues.push(MeshError::VertexIndexOutOfRange {
+        face: face_idx_out,
+        vertex: *vertex,
+        num_vertices: vertices.len(),
+      });
+      skip[face_idx] = true;
+      continue;
+    }
+
+    for vertex in face {
+      if !vertices[*vertex as usize].iter().all(|v| v.is_finite()) {
+        if invalid_positions.insert(*vertex) {
+          issues.push(MeshError::InvalidPosition { vertex: *vertex });
+        }
+        skip[face_idx] = true;
+      }
+    }
+
+    let key = face_key(face);
+
+    if face.len() < 3 || key.windows(2).any(|w| w[0] == w[1]) {
+      issues.push(MeshError::DegenerateFace { face: face_idx_out });
+      skip[face_idx] = true;
+    } else if let Some(original) = seen_faces.get(&key) {
+      issues.push(MeshError::DuplicateFace {
+        face: face_idx_out,
+        original: *original,
+      });
+      skip[face_idx] = true;
+    } else {
+      seen_faces.insert(key, face_idx_out);
+    }
+  }
+
+  let edges = edge_faces(faces, &skip);
+
+  let mut sorted_edges: Vec<_> = edges.iter().collect();
+  sorted_edges.sort_unstable_by_key(|(_, on_edge)| on_edge[0]);
+
+  for (_, on_edge) in sorted_edges {
+    let (face_idx, i) = on_edge[0];
+    let vertices = [
+      faces[face_idx][i],
+      faces[face_idx][(i + 1) % faces[face_idx].len()],
+    ];
+
+    if on_edge.len() > 2 {
+      issues.push(MeshError::NonManifoldEdge {
+        face: on_edge[2].0 as IndexType,
+        vertices,
+      });
+    } else if on_edge.len() == 2 && !opposite(faces, on_edge[0], on_edge[1]) {
+      issues.push(MeshError::InconsistentOrientation {
+        faces: [on_edge[0].0 as IndexType, on_edge[1].0 as IndexType],
+        vertices,
+      });
+    }
+  }
+
+  // orientation is checked above, so fans are joined across any edge with
+  // two faces
+  let (offsets, mut fans) =
+    corner_fans(faces, &edges, |on_edge| on_edge.len() == 2);
+
+  let mut vertex_fans: HashMap<IndexType, usize> = HashMap::new();
+  let mut reported = HashSet::new();
+
+  for (face_idx, face) in faces.iter().enumerate() {
+    if skip[face_idx] {
+      continue;
+    }
+
+    for (i, vertex) in face.iter().enumerate() {
+      let fan = fans.find(offsets[face_idx] + i);
+
+      match vertex_fans.get(vertex) {
+        None => {
+          vertex_fans.insert(*vertex, fan);
+        }
+        Some(first_fan) => {
+          if *first_fan != fan && reported.insert(*vertex) {
+            issues.push(MeshError::NonManifoldVertex { vertex: *vertex });
+          }
+        }
+      }
+    }
+  }
+
+  issues
+}
+
+#[derive(Default)]
+pub struct RepairReport {
+  pub removed_invalid: usize,
+  pub removed_degenerate: usize,
+  pub removed_duplicate: usize,
+  pub flipped: usize,
+  pub split_vertices: usize,
+  // faces which couldn't be kept without leaving a non manifold edge
+  pub removed_non_manifold: usize,
+}
+
+impl RepairReport {
+  pub fn is_empty(&self) -> bool {
+    self.removed_invalid
+      + self.removed_degenerate
+      + self.removed_duplicate
+      + self.flipped
+      + self.split_vertices
+      + self.removed_non_manifold
+      == 0
+  }
+}
+
+impl fmt::Display for RepairReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "removed {} invalid, {} degenerate and {} duplicate faces, flipped {} \
+       faces, split {} vertices, removed {} faces on non manifold edges",
+      self.removed_invalid,
+      self.removed_degenerate,
+      self.removed_duplicate,
+      self.flipped,
+      self.split_vertices,
+      self.removed_non_manifold
+    )
+  }
+}
+
+#[derive(Clap, Default)]
+pub struct Repair {
+  /// don't change the orientation of faces
+  #[clap(long = "no-orient")]
+  no_orient: bool,
+}
+
+impl Repair {
+  // removes invalid, degenerate and duplicate faces, makes the orientation
+  // of neighboring faces consistent and gives each fan around a non manifold
+  // vertex its own copy of the vertex; corner attributes follow their faces
+  // and split vertices copy the vertex attributes
+  pub fn repair(
+    &self,
+    vertices: &mut Vec<Vector3>,
+    faces: &mut Vec<Face>,
+    attributes: &mut Attributes,
+  ) -> RepairReport {
+    let mut report = RepairReport::default();
+
+    let mut corners = FaceCorners::with_capacity(faces.len());
+    let mut num_corners = 0;
+
+    for face in faces.iter() {
+      corners.push((num_corners..num_corners + face.len()).collect());
+      num_corners += face.len();
+    }
+
+    let num_vertices = vertices.len();
+    let valid_position =
+      |v: IndexType| vertices[v as usize].iter().all(|v| v.is_finite());
+
+    let mut seen_faces = HashSet::new();
+
+    let keep: Vec<_> = faces
+      .iter()
+      .map(|face| {
+        if face
+          .iter()
+          .any(|v| *v as usize >= num_vertices || !valid_position(*v))
+        {
+          report.removed_invalid += 1;
+          return false;
+        }
+
+        let key = face_key(face);
+
+        if face.len() < 3 || key.windows(2).any(|w| w[0] == w[1]) {
+          report.removed_degenerate += 1;
+          false
+        } else if !seen_faces.insert(key) {
+          report.removed_duplicate += 1;
+          false
+        } else {
+          true
+        }
+      })
+      .collect();
+
+    retain_faces(faces, &mut corners, &keep);
+
+    if !self.no_orient {
+      report.flipped = orient(faces, &mut corners);
+    }
+
+    loop {
+      report.split_vertices +=
+        split_vertices(vertices, faces, &mut attributes.vertex);
+
+      let removed = remove_conflicting_faces(faces, &mut corners);
+
+      if removed == 0 {
+        break;
+      }
+
+      // removing faces can leave new non manifold vertices
+      report.removed_non_manifold += removed;
+    }
+
+    for attribute in &mut attributes.corner {
+      let mut values = Vec::with_capacity(attribute.values.len());
+
+      for corner in corners.iter().flatten() {
+        values.extend_from_slice(attribute.get(*corner as IndexType));
+      }
+
+      attribute.values = values;
+    }
+
+    report
+  }
+
+  // repairs the faces of a mesh and rebuilds it
+  pub fn repair_mesh<D: DataStructure>(
+    &self,
+    mesh: &mut D,
+  ) -> Result<RepairReport, MeshError> {
+    let (mut vertices, mut faces, mut attributes) =
+      mesh.to_vecs_with_attributes();
+
+    let report = self.repair(&mut vertices, &mut faces, &mut attributes);
+
+    *mesh = D::try_from_iters(vertices, faces)?;
+    mesh.set_attributes(attributes)?;
+
+    Ok(report)
+  }
+}
+
+// flips faces so neighbors agree with the orientation of most of the faces
+// in their component, returns the number of flipped faces
+fn orient(faces: &mut [Face], corners: &mut FaceCorners) -> usize {
+  let edges = edge_faces(faces, &vec![false; faces.len()]);
+
+  // neighbor and if it goes along the shared edge in the same direction
+  let mut neighbors = vec![Vec::new(); faces.len()];
+
+  for on_edge in edges.values() {
+    if let [first, second] = on_edge[..] {
+      let same = !opposite(faces, first, second);
+
+      neighbors[first.0].push((second.0, same));
+      neighbors[second.0].push((first.0, same));
+    }
+  }
+
+  let mut flip: Vec<Option<bool>> = vec![None; faces.len()];
+  let mut component = Vec::new();
+
+  for start in 0..faces.len() {
+    if flip[start].is_some() {
+      continue;
+    }
+
+    component.clear();
+    component.push(start);
+    flip[start] = Some(false);
+
+    let mut i = 0;
+    while i < component.len() {
+      let face_idx = component[i];
+      let face_flip = flip[face_idx].unwrap();
+
+      for (neighbor, same) in &neighbors[face_idx] {
+        if flip[*neighbor].is_none() {
+          flip[*neighbor] = Some(face_flip ^ same);
+          component.push(*neighbor);
+        }
+      }
+
+      i += 1;
+    }
+
+    let num_flipped = component.iter().filter(|f| flip[**f].unwrap()).count();
+
+    if 2 * num_flipped > component.len() {
+      for face_idx in &component {
+        flip[*face_idx] = flip[*face_idx].map(|v| !v);
+      }
+    }
+  }
+
+  let mut num_flipped = 0;
+
+  for ((face, face_corners), face_flip) in
+    faces.iter_mut().zip(corners.iter_mut()).zip(flip)
+  {
+    if face_flip == Some(true) {
+      face.reverse();
+      face_corners.reverse();
+      num_flipped += 1;
+    }
+  }
+
+  num_flipped
+}
+
+// every fan around a vertex after the first gets a new copy of the vertex,
+// returns the number of new vertices
+fn split_vertices(
+  vertices: &mut Vec<Vector3>,
+  faces: &mut [Face],
+  vertex_attributes: &mut [Attribute],
+) -> usize {
+  let edges = edge_faces(faces, &vec![false; faces.len()]);
+
+  // only edges which will get twins connect fans
+  let (offsets, mut fans) = corner_fans(faces, &edges, |on_edge| {
+    on_edge.len() == 2 && opposite(faces, on_edge[0], on_edge[1])
+  });
+
+  let mut fan_vertex: HashMap<usize, IndexType> = HashMap::new();
+  let mut has_fan = vec![false; vertices.len()];
+  let num_vertices = vertices.len();
+
+  for (face_idx, face) in faces.iter_mut().enumerate() {
+    for (i, vertex) in face.iter_mut().enumerate() {
+      let fan = fans.find(offsets[face_idx] + i);
+      let original = *vertex;
+
+      *vertex = *fan_vertex.entry(fan).or_insert_with(|| {
+        if has_fan[original as usize] {
+          vertices.push(vertices[original as usize]);
+
+          for attribute in vertex_attributes.iter_mut() {
+            let value = attribute.get(original).to_vec();
+            attribute.push(&value);
+          }
+
+          (vertices.len() - 1) as IndexType
+        } else {
+          has_fan[original as usize] = true;
+          original
+        }
+      });
+    }
+  }
+
+  vertices.len() - num_vertices
+}
+
+// drops faces which use a directed edge already used by an earlier face
+fn remove_conflicting_faces(
+  faces: &mut Vec<Face>,
+  corners: &mut FaceCorners,
+) -> usize {
+  let mut used = HashSet::new();
+  let num_faces = faces.len();
+
+  let keep: Vec<_> = faces
+    .iter()
+    .map(|face| {
+      let pairs: Vec<_> = (0..face.len())
+        .map(|i| (face[i], face[(i + 1) % face.len()]))
+        .collect();
+
+      if pairs.iter().any(|pair| used.contains(pair)) {
+        false
+      } else {
+        used.extend(pairs);
+        true
+      }
+    })
+    .collect();
+
+  retain_faces(faces, corners, &keep);
+
+  num_faces - faces.len()
+}
+
+impl Operation for Repair {
+  fn try_apply<D: DataStructure>(&self, mesh: &mut D) -> Result<(), MeshError> {
+    self.repair_mesh(mesh).map(|_| ())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::HalfEdge;
+
+  // repairs the faces, which must have been invalid before and valid after
+  fn repair(
+    vertices: &mut Vec<Vector3>,
+    faces: &mut Vec<Face>,
+  ) -> RepairReport {
+    assert!(!validate(vertices, faces).is_empty());
+
+    let report =
+      Repair::default().repair(vertices, faces, &mut Attributes::default());
+
+    assert!(validate(vertices, faces).is_empty());
+    assert!(HalfEdge::try_from_iters(
+      vertices.iter().cloned(),
+      faces.iter().cloned()
+    )
+    .is_ok());
+
+    report
+  }
+
+  fn positions(n: usize) -> Vec<Vector3> {
+    (0..n)
+      .map(|i| Vector3::new(i as f32, (i * i) as f32, (i % 2) as f32))
+      .collect()
+  }
+
+  #[test]
+  fn inconsistent_winding() {
+    let mut vertices = positions(4);
+    // both faces go along 2 -> 0
+    let mut faces = vec![vec![0, 1, 2], vec![0, 3, 2]];
+
+    let report = repair(&mut vertices, &mut faces);
+
+    assert_eq!(report.flipped, 1);
+    assert_eq!(report.split_vertices, 0);
+    assert_eq!(report.removed_non_manifold, 0);
+    assert_eq!(faces, vec![vec![0, 1, 2], vec![2, 3, 0]]);
+  }
+
+  #[test]
+  fn bow_tie_vertex() {
+    let mut vertices = positions(5);
+    // only vertex 0 is shared
+    let mut faces = vec![vec![0, 1, 2], vec![0, 3, 4]];
+
+    let report = repair(&mut vertices, &mut faces);
+
+    assert_eq!(report.flipped, 0);
+    assert_eq!(report.split_vertices, 1);
+    assert_eq!(report.removed_non_manifold, 0);
+    assert_eq!(vertices.len(), 6);
+    assert_eq!(vertices[5], vertices[0]);
+    assert_eq!(faces, vec![vec![0, 1, 2], vec![5, 3, 4]]);
+  }
+
+  #[test]
+  fn three_faces_on_edge() {
+    let mut vertices = positions(5);
+    let mut faces = vec![vec![0, 1, 2], vec![1, 0, 3], vec![1, 0, 4]];
+
+    let report = repair(&mut vertices, &mut faces);
+
+    // each face becomes its own component (both endpoints get two copies)
+    assert_eq!(report.flipped, 0);
+    assert_eq!(report.split_vertices, 4);
+    assert_eq!(report.removed_non_manifold, 0);
+    assert_eq!(faces.len(), 3);
+    assert_eq!(vertices.len(), 9);
+  }
+}
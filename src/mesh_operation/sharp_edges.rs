@@ -0,0 +1,80 @@
+use crate::get_normal;
+use crate::utils::read_edge_list;
+use crate::DataStructure;
+use crate::MeshError;
+
+use clap::Clap;
+
+use std::path::Path;
+
+// edges marked by the user or by their dihedral angle, which operations keep
+// sharp
+#[derive(Clap)]
+pub struct SharpEdges {
+  /// file of vertex index pairs (1 based) marking sharp crease edges
+  #[clap(long = "creases")]
+  creases: Option<String>,
+  /// edges with a dihedral angle (in degrees) above this are sharp creases
+  #[clap(long = "crease-angle")]
+  crease_angle: Option<f32>,
+}
+
+impl SharpEdges {
+  // sharp flag of each edge (boundary edges aren't marked)
+  pub fn find<D: DataStructure>(
+    &self,
+    mesh: &D,
+  ) -> Result<Vec<bool>, MeshError> {
+    let mut sharp = vec![false; mesh.max_idx_edges()];
+
+    if let Some(path) = &self.creases {
+      let edges = read_edge_list(Path::new(path)).map_err(|e| {
+        MeshError::InvalidArgument(format!(
+          "couldn't read crease file {}: {}",
+          path, e
+        ))
+      })?;
+
+      for [first, second] in edges {
+        let edge_idx = mesh.find_edge(first, second).ok_or_else(|| {
+          MeshError::InvalidArgument(format!(
+            "crease file {}: vertices {} and {} aren't connected",
+            path,
+            first + 1,
+            second + 1
+          ))
+        })?;
+
+        sharp[edge_idx as usize] = true;
+      }
+    }
+
+    if let Some(crease_angle) = self.crease_angle {
+      let min_cos = crease_angle.to_radians().cos();
+
+      let mut edge_op = mesh.initial_edge();
+      while let Some(edge) = edge_op {
+        let ([near_0, near_1, far_0], far_op) = mesh.get_edge_neighbors(edge);
+
+        if let Some(far_1) = far_op {
+          let face_normal = |far| {
+            get_normal([
+              mesh.get_position(near_0),
+              mesh.get_position(near_1),
+              mesh.get_position(far),
+            ])
+          };
+
+          // the second face has the opposite winding
+          if face_normal(far_0).dot(&-face_normal(far_1)) < min_cos {
+            sharp[edge as usize] = true;
+          }
+        }
+
+        edge_op = mesh.next_edge(edge);
+      }
+    }
+
+    Ok(sharp)
+  }
+}
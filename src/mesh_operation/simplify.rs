@@ -1,20 +1,139 @@
+use crate::file_format::pm;
 use crate::get_normal;
+use crate::mesh_operation::attribute_quadric::AttributeQuadric;
+use crate::mesh_operation::cluster::{
+  cell_size_for_faces, check_input, cluster, ClusterReport,
+};
+use crate::mesh_operation::collapse_check::CollapseChecks;
+use crate::mesh_operation::progressive::{
+  record_faces, record_vertex, ProgressiveMesh, VertexSplit,
+};
 use crate::mesh_operation::Operation;
+use crate::Attributes;
 use crate::DataStructure;
+use crate::Face;
 use crate::IndexType;
+use crate::MeshError;
 use crate::Vector3;
 
 use clap::Clap;
 use nalgebra::base::{dimension::U1, Matrix4, Vector4};
+use nalgebra::DVector;
 use ordered_float::NotNan;
 
 use std::cmp::Reverse;
 use std::collections::{BinaryHeap, HashSet};
+use std::fmt;
 use std::iter::FromIterator;
+use std::ops::Add;
+use std::path::PathBuf;
 
+// simplification stops at the first criterion which is met
 #[derive(Clap)]
 pub struct Simplify {
-  faces_to_remove: u32,
+  faces_to_remove: Option<u32>,
+  /// stop once the mesh has at most this many faces
+  #[clap(long = "target-faces")]
+  target_faces: Option<u32>,
+  /// stop once the mesh has at most this fraction of its original faces
+  #[clap(long = "ratio")]
+  ratio: Option<f32>,
+  /// don't collapse edges whose quadric error is larger than this
+  #[clap(long = "max-error")]
+  max_error: Option<f32>,
+  /// include vertex and corner attributes (uvs, normals, colors) in the
+  /// error metric and keep attribute seams and boundaries in place
+  #[clap(long = "attributes")]
+  attributes: bool,
+  /// scale of attribute values relative to positions in the error metric
+  #[clap(long = "attribute-weight", default_value = "1")]
+  attribute_weight: f32,
+  /// weight of the planes which stop attribute seams from moving
+  #[clap(long = "seam-weight", default_value = "10")]
+  seam_weight: f32,
+  /// weight (relative to the faces) of the planes which stop the boundary
+  /// from moving
+  #[clap(long = "boundary-weight", default_value = "10")]
+  boundary_weight: f32,
+  /// never move or remove boundary vertices
+  #[clap(long = "lock-boundary")]
+  lock_boundary: bool,
+  /// also write the collapses to this progressive mesh (.pm) file, from
+  /// which any level of detail can be loaded
+  #[clap(long = "progressive")]
+  progressive: Option<PathBuf>,
+  /// merge all vertices in each cell of a uniform grid instead of collapsing
+  /// edges one at a time (much faster on huge meshes, but lower quality)
+  #[clap(long = "cluster")]
+  cluster: bool,
+  /// size of the grid cells for --cluster (chosen from the face count if not
+  /// given)
+  #[clap(long = "cell-size")]
+  cell_size: Option<f32>,
+  /// with --cluster, place each merged vertex at the point closest to the
+  /// planes of its faces instead of the mean of the cell
+  #[clap(long = "cluster-quadrics")]
+  cluster_quadrics: bool,
+  #[clap(flatten)]
+  checks: CollapseChecks,
+}
+
+// collapses rejected by the checks go back on the queue with their cost
+// multiplied by this, up to MAX_REJECTIONS times (until a neighboring
+// collapse changes the edge)
+const REJECTION_PENALTY: f32 = 10.0;
+const MAX_REJECTIONS: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+  TargetReached,
+  // the cheapest remaining collapse was above the maximum error
+  MaxError,
+  // no edge could be collapsed any more
+  QueueEmpty,
+}
+
+pub struct SimplifyReport {
+  pub initial_faces: usize,
+  pub faces: usize,
+  pub collapses: usize,
+  // collapses rejected by the geometric checks (an edge may be counted more
+  // than once)
+  pub rejected: usize,
+  // largest quadric error of a collapse which was applied
+  pub max_error: f32,
+  pub stop: StopReason,
+}
+
+impl fmt::Display for SimplifyReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{} to {} faces with {} collapses ({} rejected), max error {} ({})",
+      self.initial_faces,
+      self.faces,
+      self.collapses,
+      self.rejected,
+      self.max_error,
+      match self.stop {
+        StopReason::TargetReached => "target reached",
+        StopReason::MaxError => "stopped at the maximum error",
+        StopReason::QueueEmpty => "no more edges can be collapsed",
+      }
+    )
+  }
+}
+
+// what simplify_mesh did
+pub enum SimplifyOutcome {
+  Collapsed(SimplifyReport),
+  // the collapses were also written to a progressive mesh file
+  Progressive {
+    report: SimplifyReport,
+    levels: usize,
+    path: PathBuf,
+  },
+  Clustered(ClusterReport),
 }
 
 #[derive(Ord, Eq, PartialEq, PartialOrd)]
@@ -22,6 +141,64 @@ struct EdgeCost {
   cost: Reverse<NotNan<f32>>,
   edge_idx: IndexType,
   count: u32,
+  rejections: u32,
+}
+
+// the current plan for an edge, heap entries with a lower count are stale
+#[derive(Clone)]
+struct PlannedCollapse<C> {
+  collapse: C,
+  vertices: [IndexType; 2],
+  count: u32,
+  // cost without rejection penalties
+  cost: NotNan<f32>,
+}
+
+// error metric and placement of the new vertex
+trait Metric {
+  type Quadric: Clone + Add<Output = Self::Quadric>;
+  // where (and with which attributes) an edge collapses to
+  type Collapse: Clone;
+
+  fn zero_quadric(&self) -> Self::Quadric;
+
+  // where the remaining vertex is placed
+  fn position(&self, collapse: &Self::Collapse) -> Vector3;
+
+  fn face_quadric<D: DataStructure>(
+    &self,
+    mesh: &D,
+    face_idx: IndexType,
+  ) -> Self::Quadric;
+
+  // added to the quadric of each endpoint of the edge
+  fn edge_quadric<D: DataStructure>(
+    &self,
+    mesh: &D,
+    edge_idx: IndexType,
+  ) -> Option<Self::Quadric>;
+
+  // None if the edge must not be collapsed (edges is a buffer for the edges
+  // around a vertex)
+  fn plan<D: DataStructure>(
+    &self,
+    mesh: &D,
+    edge_idx: IndexType,
+    vertices: [IndexType; 2],
+    quadrics: [&Self::Quadric; 2],
+    edges: &mut Vec<(IndexType, IndexType)>,
+  ) -> Option<(Self::Collapse, NotNan<f32>)>;
+
+  // collapses the edge and moves the remaining vertex
+  fn collapse<D: DataStructure>(
+    &self,
+    mesh: &mut D,
+    edge_idx: IndexType,
+    vertices: [IndexType; 2],
+    collapse: &Self::Collapse,
+    modified_edges: &mut Vec<(IndexType, IndexType)>,
+    removed_edges: &mut Vec<IndexType>,
+  ) -> Option<IndexType>;
 }
 
 fn get_quadric<D: DataStructure>(
@@ -46,13 +223,33 @@ fn get_quadric<D: DataStructure>(
   v * v.transpose()
 }
 
+// plane through the edge perpendicular to its first face (normal, offset and
+// squared length of the edge)
+fn edge_plane<D: DataStructure>(
+  mesh: &D,
+  edge_idx: IndexType,
+) -> Option<(Vector3, f32, f32)> {
+  let [v_0, v_1] = mesh.get_endpoints(edge_idx);
+  let p_0 = mesh.get_position(v_0);
+  let edge = mesh.get_position(v_1) - p_0;
+
+  let (face_normal, _) = mesh.get_face_normal(mesh.get_edge_faces(edge_idx).0);
+  let normal = edge.cross(&face_normal).normalize();
+
+  if normal.iter().all(|v| v.is_finite()) {
+    Some((normal, -normal.dot(&p_0), edge.norm_squared()))
+  } else {
+    None
+  }
+}
+
 fn get_best_position_cost<D: DataStructure>(
   mesh: &D,
   vertex_first: IndexType,
   vertex_second: IndexType,
   quadric_first: &Matrix4<f32>,
   quadric_second: &Matrix4<f32>,
-) -> (Vector3, NotNan<f32>) {
+) -> Option<(Vector3, NotNan<f32>)> {
   let combined_quadric = quadric_first + quadric_second;
   let mut partials_quadric = combined_quadric;
 
@@ -87,55 +284,581 @@ fn get_best_position_cost<D: DataStructure>(
     debug_assert!(neighbors.contains(&vertex_first));
   }
 
-  (optimal_position, NotNan::new(cost).unwrap())
+  // a NaN cost (e.g. from degenerate faces) means the edge is skipped
+  Some((optimal_position, NotNan::new(cost).ok()?))
 }
 
-impl Operation for Simplify {
-  fn apply<D: DataStructure>(&self, mesh: &mut D) {
-    let mut face_quadrics = Vec::new();
+// plain quadric error metric on positions
+struct PositionMetric {
+  boundary_weight: f32,
+  lock_boundary: bool,
+}
+
+impl Metric for PositionMetric {
+  type Quadric = Matrix4<f32>;
+  type Collapse = Vector3;
+
+  fn zero_quadric(&self) -> Matrix4<f32> {
+    Matrix4::zeros()
+  }
+
+  fn position(&self, collapse: &Vector3) -> Vector3 {
+    *collapse
+  }
+
+  fn face_quadric<D: DataStructure>(
+    &self,
+    mesh: &D,
+    face_idx: IndexType,
+  ) -> Matrix4<f32> {
+    get_quadric(mesh, face_idx)
+  }
+
+  // boundary constraint plane, scaled by the weight alone because the face
+  // quadrics aren't area weighted here (AttributeMetric scales both by size)
+  fn edge_quadric<D: DataStructure>(
+    &self,
+    mesh: &D,
+    edge_idx: IndexType,
+  ) -> Option<Matrix4<f32>> {
+    if self.boundary_weight == 0.0 || mesh.get_edge_faces(edge_idx).1.is_some()
+    {
+      return None;
+    }
+
+    let (normal, d, _) = edge_plane(mesh, edge_idx)?;
+
+    let v = Vector4::new(normal[0], normal[1], normal[2], d);
+
+    Some(self.boundary_weight * v * v.transpose())
+  }
+
+  fn plan<D: DataStructure>(
+    &self,
+    mesh: &D,
+    _: IndexType,
+    [first, second]: [IndexType; 2],
+    [first_quadric, second_quadric]: [&Matrix4<f32>; 2],
+    edges: &mut Vec<(IndexType, IndexType)>,
+  ) -> Option<(Vector3, NotNan<f32>)> {
+    if self.lock_boundary {
+      let locked = [
+        mesh.get_vertex_edges(first, edges),
+        mesh.get_vertex_edges(second, edges),
+      ];
+
+      // the other endpoint moves onto the boundary vertex
+      let kept = match locked {
+        [true, true] => return None,
+        [true, false] => Some(first),
+        [false, true] => Some(second),
+        [false, false] => None,
+      };
+
+      if let Some(kept) = kept {
+        let position = mesh.get_position(kept);
+        let v = Vector4::new(position[0], position[1], position[2], 1.0);
+        let cost =
+          (v.transpose() * (first_quadric + second_quadric) * v)[0].max(0.0);
+
+        return Some((position, NotNan::new(cost).ok()?));
+      }
+    }
+
+    get_best_position_cost(mesh, first, second, first_quadric, second_quadric)
+  }
+
+  fn collapse<D: DataStructure>(
+    &self,
+    mesh: &mut D,
+    edge_idx: IndexType,
+    [first, second]: [IndexType; 2],
+    best_position: &Vector3,
+    modified_edges: &mut Vec<(IndexType, IndexType)>,
+    removed_edges: &mut Vec<IndexType>,
+  ) -> Option<IndexType> {
+    let first_position = mesh.get_position(first);
+    let second_position = mesh.get_position(second);
+
+    // interpolate attributes by where the new position projects onto the edge
+    let edge = second_position - first_position;
+    let length_squared = edge.norm_squared();
+    let t = if length_squared > 0.0 {
+      ((best_position - first_position).dot(&edge) / length_squared)
+        .clamp(0.0, 1.0)
+    } else {
+      0.5
+    };
+
+    // t is from the first endpoint of the edge
+    let t = if mesh.get_endpoints(edge_idx)[0] == first {
+      t
+    } else {
+      1.0 - t
+    };
+
+    let new_vertex =
+      mesh.collapse_edge_at(edge_idx, t, modified_edges, removed_edges)?;
+
+    mesh.set_position(new_vertex, best_position);
+
+    Some(new_vertex)
+  }
+}
+
+#[derive(PartialEq)]
+enum VertexKind {
+  Interior,
+  // on exactly two seam or boundary edges, may only move along them
+  Seam,
+  // where seams meet or end
+  Locked,
+}
+
+// quadric error metric on positions and attributes (Garland and Heckbert
+// 1998); collapses which touch a seam or the boundary move the removed
+// vertex onto the other endpoint so the seam stays in place
+struct AttributeMetric {
+  attribute_weight: f64,
+  seam_weight: f64,
+  boundary_weight: f64,
+  lock_boundary: bool,
+  // total components of vertex and corner attributes
+  dimensions: [usize; 2],
+}
+
+#[derive(Clone)]
+struct AttributeCollapse {
+  // position and weighted attributes of the new vertex
+  point: DVector<f64>,
+  // endpoint whose attributes are kept (the new vertex is placed on it)
+  kept: Option<IndexType>,
+}
+
+impl AttributeMetric {
+  fn dimension(&self) -> usize {
+    3 + self.dimensions[0] + self.dimensions[1]
+  }
+
+  // position and weighted attributes of the corner of the face at the vertex
+  fn point<D: DataStructure>(
+    &self,
+    mesh: &D,
+    vertex: IndexType,
+    face: IndexType,
+  ) -> DVector<f64> {
+    let mut values = Vec::new();
+    let mut out = DVector::zeros(self.dimension());
+
+    let position = mesh.get_position(vertex);
+
+    for i in 0..3 {
+      out[i] = position[i] as f64;
+    }
+
+    mesh.get_vertex_attributes(vertex, &mut values);
+    let mut idx = 3;
+
+    for value in &values {
+      out[idx] = self.attribute_weight * *value as f64;
+      idx += 1;
+    }
+
+    mesh.get_corner_attributes(vertex, face, &mut values);
+
+    for value in &values {
+      out[idx] = self.attribute_weight * *value as f64;
+      idx += 1;
+    }
+
+    out
+  }
+
+  // vertex and corner attributes from a point
+  fn attributes(&self, point: &DVector<f64>) -> (Vec<f32>, Vec<f32>) {
+    let unweight = |v: &f64| (v / self.attribute_weight) as f32;
+
+    let vertex_end = 3 + self.dimensions[0];
+
+    (
+      point
+        .iter()
+        .take(vertex_end)
+        .skip(3)
+        .map(unweight)
+        .collect(),
+      point.iter().skip(vertex_end).map(unweight).collect(),
+    )
+  }
+
+  // corner attributes differ across the edge at one of the endpoints
+  fn is_seam<D: DataStructure>(&self, mesh: &D, edge_idx: IndexType) -> bool {
+    if self.dimensions[1] == 0 {
+      return false;
+    }
+
+    let (f_0, f_1) = match mesh.get_edge_faces(edge_idx) {
+      (f_0, Some(f_1)) => (f_0, f_1),
+      (_, None) => return false,
+    };
+
+    let mut first = Vec::new();
+    let mut second = Vec::new();
+
+    mesh.get_endpoints(edge_idx).iter().any(|vertex| {
+      mesh.get_corner_attributes(*vertex, f_0, &mut first);
+      mesh.get_corner_attributes(*vertex, f_1, &mut second);
+
+      first != second
+    })
+  }
+
+  fn is_feature<D: DataStructure>(
+    &self,
+    mesh: &D,
+    edge_idx: IndexType,
+  ) -> bool {
+    mesh.get_edge_faces(edge_idx).1.is_none() || self.is_seam(mesh, edge_idx)
+  }
+
+  fn vertex_kind<D: DataStructure>(
+    &self,
+    mesh: &D,
+    vertex: IndexType,
+    edges: &mut Vec<(IndexType, IndexType)>,
+  ) -> VertexKind {
+    mesh.get_vertex_edges(vertex, edges);
+
+    let num_features = edges
+      .iter()
+      .filter(|(edge_idx, _)| self.is_feature(mesh, *edge_idx))
+      .count();
+
+    let boundary = edges
+      .iter()
+      .any(|(edge_idx, _)| mesh.get_edge_faces(*edge_idx).1.is_none());
+
+    if boundary && self.lock_boundary {
+      return VertexKind::Locked;
+    }
+
+    match num_features {
+      0 => VertexKind::Interior,
+      2 => VertexKind::Seam,
+      _ => VertexKind::Locked,
+    }
+  }
+
+  fn cost(quadric: &AttributeQuadric, point: &DVector<f64>) -> Option<f64> {
+    let cost = quadric.error(point);
+
+    if cost.is_finite() {
+      Some(cost.max(0.0))
+    } else {
+      None
+    }
+  }
+}
+
+impl Metric for AttributeMetric {
+  type Quadric = AttributeQuadric;
+  type Collapse = AttributeCollapse;
+
+  fn zero_quadric(&self) -> AttributeQuadric {
+    AttributeQuadric::zeros(self.dimension())
+  }
+
+  fn position(&self, collapse: &AttributeCollapse) -> Vector3 {
+    let point = &collapse.point;
+
+    Vector3::new(point[0] as f32, point[1] as f32, point[2] as f32)
+  }
+
+  fn face_quadric<D: DataStructure>(
+    &self,
+    mesh: &D,
+    face_idx: IndexType,
+  ) -> AttributeQuadric {
+    let [v_0, v_1, v_2] = mesh.get_face_neighbors(face_idx);
+
+    let p_0 = self.point(mesh, v_0, face_idx);
+    let p_1 = self.point(mesh, v_1, face_idx);
+    let p_2 = self.point(mesh, v_2, face_idx);
+
+    // weighted by area so small faces don't dominate
+    let (normal, [q_0, q_1, q_2]) = mesh.get_face_normal(face_idx);
+    let area = 0.5 * (q_1 - q_0).cross(&(q_2 - q_0)).dot(&normal);
+
+    AttributeQuadric::from_triangle([&p_0, &p_1, &p_2], area.abs() as f64)
+  }
+
+  // plane through a seam or boundary edge perpendicular to the face
+  // (weighted like the area weighted faces)
+  fn edge_quadric<D: DataStructure>(
+    &self,
+    mesh: &D,
+    edge_idx: IndexType,
+  ) -> Option<AttributeQuadric> {
+    let weight = if mesh.get_edge_faces(edge_idx).1.is_none() {
+      self.boundary_weight
+    } else if self.is_seam(mesh, edge_idx) {
+      self.seam_weight
+    } else {
+      return None;
+    };
+
+    if weight == 0.0 {
+      return None;
+    }
+
+    let (normal, d, length_squared) = edge_plane(mesh, edge_idx)?;
+
+    Some(AttributeQuadric::from_position_plane(
+      self.dimension(),
+      &normal,
+      d,
+      weight * length_squared as f64,
+    ))
+  }
+
+  fn plan<D: DataStructure>(
+    &self,
+    mesh: &D,
+    edge_idx: IndexType,
+    vertices: [IndexType; 2],
+    [first_quadric, second_quadric]: [&AttributeQuadric; 2],
+    edges: &mut Vec<(IndexType, IndexType)>,
+  ) -> Option<(AttributeCollapse, NotNan<f32>)> {
+    let quadric = first_quadric.clone() + second_quadric.clone();
+
+    let kinds = [
+      self.vertex_kind(mesh, vertices[0], edges),
+      self.vertex_kind(mesh, vertices[1], edges),
+    ];
+
+    let best = if kinds.iter().all(|kind| *kind == VertexKind::Interior) {
+      // fall back to the endpoints and the midpoint if there isn't a unique
+      // optimum
+      let midpoint =
+        0.5 * (mesh.get_position(vertices[0]) + mesh.get_position(vertices[1]));
+
+      let point = quadric.minimize().or_else(|| {
+        let candidates = [
+          mesh.get_position(vertices[0]),
+          mesh.get_position(vertices[1]),
+          midpoint,
+        ];
+
+        candidates
+          .iter()
+          .filter_map(|position| quadric.minimize_with_position(position))
+          .filter_map(|point| Some((Self::cost(&quadric, &point)?, point)))
+          .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+          .map(|(_, point)| point)
+      })?;
+
+      let cost = Self::cost(&quadric, &point)?;
+
+      (AttributeCollapse { point, kept: None }, cost)
+    } else {
+      let along_feature = self.is_feature(mesh, edge_idx);
+
+      // the removed vertex moves onto the kept one
+      let can_remove = |removed: usize| match kinds[removed] {
+        VertexKind::Interior => true,
+        VertexKind::Seam => {
+          along_feature && kinds[1 - removed] != VertexKind::Interior
+        }
+        VertexKind::Locked => false,
+      };
+
+      (0..2)
+        .filter(|removed| can_remove(*removed))
+        .filter_map(|removed| {
+          let kept = vertices[1 - removed];
+          let point =
+            quadric.minimize_with_position(&mesh.get_position(kept))?;
+          let cost = Self::cost(&quadric, &point)?;
+
+          Some((
+            AttributeCollapse {
+              point,
+              kept: Some(kept),
+            },
+            cost,
+          ))
+        })
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())?
+    };
+
+    let (collapse, cost) = best;
+
+    Some((collapse, NotNan::new(cost as f32).ok()?))
+  }
+
+  fn collapse<D: DataStructure>(
+    &self,
+    mesh: &mut D,
+    edge_idx: IndexType,
+    vertices: [IndexType; 2],
+    collapse: &AttributeCollapse,
+    modified_edges: &mut Vec<(IndexType, IndexType)>,
+    removed_edges: &mut Vec<IndexType>,
+  ) -> Option<IndexType> {
+    let kept = match collapse.kept {
+      Some(kept) => kept,
+      None => {
+        let new_vertex =
+          mesh.collapse_edge(edge_idx, modified_edges, removed_edges)?;
+
+        let (vertex_values, corner_values) = self.attributes(&collapse.point);
+
+        mesh.set_position(new_vertex, &self.position(collapse));
+        mesh.set_vertex_attributes(new_vertex, &vertex_values);
+
+        if !corner_values.is_empty() {
+          let mut faces = Vec::new();
+          mesh.get_vertex_adjacent_faces(new_vertex, &mut faces);
+
+          for face in faces {
+            mesh.set_corner_attributes(new_vertex, face, &corner_values);
+          }
+        }
+
+        return Some(new_vertex);
+      }
+    };
+
+    let removed = if vertices[0] == kept {
+      vertices[1]
+    } else {
+      vertices[0]
+    };
+
+    let position = mesh.get_position(kept);
+    let mut vertex_values = Vec::new();
+    mesh.get_vertex_attributes(kept, &mut vertex_values);
+
+    // corners of the removed vertex take the value of the kept vertex on the
+    // same side of the seam (one of the faces on the edge)
+    let mut new_corners = Vec::new();
+
+    if self.dimensions[1] != 0 {
+      let (f_0, f_1) = mesh.get_edge_faces(edge_idx);
+
+      let mut sides = Vec::new();
+
+      for face in std::iter::once(f_0).chain(f_1) {
+        let mut removed_values = Vec::new();
+        let mut kept_values = Vec::new();
+
+        mesh.get_corner_attributes(removed, face, &mut removed_values);
+        mesh.get_corner_attributes(kept, face, &mut kept_values);
+
+        sides.push((face, removed_values, kept_values));
+      }
+
+      let mut faces = Vec::new();
+      mesh.get_vertex_adjacent_faces(removed, &mut faces);
+
+      let mut values = Vec::new();
+
+      for face in faces {
+        if sides.iter().any(|(side_face, _, _)| *side_face == face) {
+          continue;
+        }
+
+        mesh.get_corner_attributes(removed, face, &mut values);
+
+        if let Some((_, _, kept_values)) = sides
+          .iter()
+          .find(|(_, removed_values, _)| *removed_values == values)
+        {
+          new_corners.push((face, kept_values.clone()));
+        }
+      }
+    }
+
+    // corners of the kept vertex keep their values
+    let t = if mesh.get_endpoints(edge_idx)[0] == kept {
+      0.0
+    } else {
+      1.0
+    };
+
+    let new_vertex =
+      mesh.collapse_edge_at(edge_idx, t, modified_edges, removed_edges)?;
+
+    mesh.set_position(new_vertex, &position);
+    mesh.set_vertex_attributes(new_vertex, &vertex_values);
+
+    for (face, values) in new_corners {
+      mesh.set_corner_attributes(new_vertex, face, &values);
+    }
+
+    Some(new_vertex)
+  }
+}
+
+impl Simplify {
+  fn simplify_with_metric<D: DataStructure, M: Metric>(
+    &self,
+    mesh: &mut D,
+    metric: &M,
+    mut splits: Option<&mut Vec<VertexSplit>>,
+  ) -> SimplifyReport {
+    let mut face_quadrics: Vec<Option<M::Quadric>> = Vec::new();
     face_quadrics.resize(mesh.max_idx_faces(), None);
-    let mut vertex_quadrics = Vec::new();
+    let mut vertex_quadrics: Vec<Option<M::Quadric>> = Vec::new();
     vertex_quadrics.resize(mesh.max_idx_vertices(), None);
 
     let mut adjacent_faces = Vec::new();
+    let mut vertex_edges = Vec::new();
 
     let mut edge_op = mesh.initial_edge();
 
-    let mut get_face_quadric = |face_idx| {
-      if let Some(quadric) = face_quadrics[face_idx as usize] {
-        quadric
+    let mut get_face_quadric = |face_idx: IndexType| {
+      if let Some(quadric) = &face_quadrics[face_idx as usize] {
+        quadric.clone()
       } else {
-        let quadric = get_quadric(mesh, face_idx);
+        let quadric = metric.face_quadric(mesh, face_idx);
 
-        face_quadrics[face_idx as usize] = Some(quadric);
+        face_quadrics[face_idx as usize] = Some(quadric.clone());
 
         quadric
       }
     };
 
-    let mut get_vertex_quadric = |vertex_idx| {
-      if let Some(quadric) = vertex_quadrics[vertex_idx as usize] {
-        quadric
+    let mut get_vertex_quadric = |vertex_idx: IndexType| {
+      if let Some(quadric) = &vertex_quadrics[vertex_idx as usize] {
+        quadric.clone()
       } else {
         mesh.get_vertex_adjacent_faces(vertex_idx, &mut adjacent_faces);
+        mesh.get_vertex_edges(vertex_idx, &mut vertex_edges);
 
         let quadric = adjacent_faces
           .iter()
-          .fold(Matrix4::zeros(), |acc, face_idx| {
+          .fold(metric.zero_quadric(), |acc, face_idx| {
             acc + get_face_quadric(*face_idx)
           });
 
-        vertex_quadrics[vertex_idx as usize] = Some(quadric);
+        let quadric = vertex_edges
+          .iter()
+          .filter_map(|(edge_idx, _)| metric.edge_quadric(mesh, *edge_idx))
+          .fold(quadric, |acc, edge_quadric| acc + edge_quadric);
+
+        vertex_quadrics[vertex_idx as usize] = Some(quadric.clone());
 
         quadric
       }
     };
 
     let mut edge_heap = BinaryHeap::new();
-    let mut edge_info = Vec::new();
+    let mut edge_info: Vec<Option<PlannedCollapse<M::Collapse>>> = Vec::new();
 
     let mut removed_edges = Vec::new();
     let mut modified_edges = Vec::new();
+    let mut plan_edges = Vec::new();
 
     edge_info.resize(mesh.max_idx_edges(), None);
 
@@ -145,40 +868,58 @@ impl Operation for Simplify {
       let first_quadric = get_vertex_quadric(first);
       let second_quadric = get_vertex_quadric(second);
 
-      let (best_position, cost) = get_best_position_cost(
+      if let Some((collapse, cost)) = metric.plan(
         mesh,
-        first,
-        second,
-        &first_quadric,
-        &second_quadric,
-      );
-
-      edge_heap.push(EdgeCost {
-        cost: Reverse(cost),
         edge_idx,
-        count: 0,
-      });
+        [first, second],
+        [&first_quadric, &second_quadric],
+        &mut plan_edges,
+      ) {
+        edge_heap.push(EdgeCost {
+          cost: Reverse(cost),
+          edge_idx,
+          count: 0,
+          rejections: 0,
+        });
 
-      edge_info[edge_idx as usize] = Some((best_position, first, second, 0));
+        edge_info[edge_idx as usize] = Some(PlannedCollapse {
+          collapse,
+          vertices: [first, second],
+          count: 0,
+          cost,
+        });
+      }
 
       edge_op = mesh.next_edge(edge_idx);
     }
 
     let initial_num_faces = mesh.num_faces();
 
-    let final_num_faces =
-      initial_num_faces.saturating_sub(self.faces_to_remove as usize);
+    let final_num_faces = self.target_num_faces(initial_num_faces);
+    let max_error = self.max_error.unwrap_or(f32::INFINITY);
+
+    let mut report = SimplifyReport {
+      initial_faces: initial_num_faces,
+      faces: initial_num_faces,
+      collapses: 0,
+      rejected: 0,
+      max_error: 0.0,
+      stop: StopReason::TargetReached,
+    };
 
     while mesh.num_faces() > final_num_faces {
       let op = edge_heap.pop();
 
       if op.is_none() {
-        eprintln!("couldn't remove requested number of faces (queue empty)");
-        return;
+        report.stop = StopReason::QueueEmpty;
+        break;
       }
 
       let EdgeCost {
-        edge_idx, count, ..
+        edge_idx,
+        count,
+        cost: Reverse(queued_cost),
+        rejections,
       } = op.unwrap();
 
       // TODO: when will this occur
@@ -186,8 +927,12 @@ impl Operation for Simplify {
         continue;
       }
 
-      let (best_position, first_vertex_idx, second_vertex_idx, true_count) =
-        edge_info[edge_idx as usize].unwrap();
+      let PlannedCollapse {
+        collapse,
+        vertices: [first_vertex_idx, second_vertex_idx],
+        count: true_count,
+        cost,
+      } = edge_info[edge_idx as usize].clone().unwrap();
 
       if count < true_count {
         continue;
@@ -195,6 +940,39 @@ impl Operation for Simplify {
 
       debug_assert_eq!(true_count, count);
 
+      if cost.into_inner() > max_error {
+        // the heap is ordered by cost, so every other collapse is worse
+        if queued_cost == cost {
+          report.stop = StopReason::MaxError;
+          break;
+        }
+
+        // penalized by earlier rejections
+        continue;
+      }
+
+      if self
+        .checks
+        .check(mesh, edge_idx, &metric.position(&collapse))
+        .is_err()
+      {
+        report.rejected += 1;
+
+        if rejections < MAX_REJECTIONS {
+          let penalized =
+            queued_cost.into_inner().max(f32::EPSILON) * REJECTION_PENALTY;
+
+          edge_heap.push(EdgeCost {
+            cost: Reverse(NotNan::new(penalized).unwrap()),
+            edge_idx,
+            count,
+            rejections: rejections + 1,
+          });
+        }
+
+        continue;
+      }
+
       debug_assert_ne!(first_vertex_idx, second_vertex_idx);
       debug_assert_eq!(
         HashSet::<u32>::from_iter(
@@ -203,54 +981,514 @@ impl Operation for Simplify {
         HashSet::<u32>::from_iter(mesh.get_endpoints(edge_idx).iter().cloned())
       );
 
-      if let Some(new_vertex) =
-        mesh.collapse_edge(edge_idx, &mut modified_edges, &mut removed_edges)
-      {
+      let before = splits.as_ref().map(|_| {
+        (
+          record_vertex(mesh, first_vertex_idx),
+          record_vertex(mesh, second_vertex_idx),
+          record_faces(mesh, [first_vertex_idx, second_vertex_idx]),
+        )
+      });
+
+      if let Some(new_vertex) = metric.collapse(
+        mesh,
+        edge_idx,
+        [first_vertex_idx, second_vertex_idx],
+        &collapse,
+        &mut modified_edges,
+        &mut removed_edges,
+      ) {
         debug_assert!(
           (new_vertex == first_vertex_idx) ^ (new_vertex == second_vertex_idx)
         );
 
-        mesh.set_position(new_vertex, &best_position);
+        report.collapses += 1;
+
+        if let (Some(splits), Some((first, second, faces))) =
+          (splits.as_mut(), before)
+        {
+          let (vertex, parent) = if new_vertex == first_vertex_idx {
+            (second, first)
+          } else {
+            (first, second)
+          };
+
+          splits.push(VertexSplit {
+            vertex,
+            parent,
+            faces,
+          });
+        }
+        report.max_error = report.max_error.max(cost.into_inner());
 
         for removed_edge in &removed_edges {
           edge_info[*removed_edge as usize] = None;
         }
 
-        let new_vertex_quadric = vertex_quadrics[first_vertex_idx as usize]
-          .unwrap()
-          + vertex_quadrics[second_vertex_idx as usize].unwrap();
+        let new_vertex_quadric =
+          vertex_quadrics[first_vertex_idx as usize].clone().unwrap()
+            + vertex_quadrics[second_vertex_idx as usize].clone().unwrap();
 
-        vertex_quadrics[new_vertex as usize] = Some(new_vertex_quadric);
+        vertex_quadrics[new_vertex as usize] = Some(new_vertex_quadric.clone());
 
         for (edge_idx, vertex_idx) in &modified_edges {
           let vertex_idx = *vertex_idx;
           let edge_idx = *edge_idx;
-          let (best_position, cost) = get_best_position_cost(
-            mesh,
-            new_vertex,
-            vertex_idx, // verify order unimportant
-            &new_vertex_quadric,
-            &vertex_quadrics[vertex_idx as usize].unwrap(),
-          );
 
           let mut old_count = 0;
 
-          if let Some((_, _, _, count)) = edge_info[edge_idx as usize] {
-            old_count = count;
+          if let Some(planned) = &edge_info[edge_idx as usize] {
+            old_count = planned.count;
           }
 
           let count = old_count + 1;
 
-          edge_info[edge_idx as usize] =
-            Some((best_position, new_vertex, vertex_idx, count));
-
-          edge_heap.push(EdgeCost {
+          let planned = metric.plan(
+            mesh,
             edge_idx,
-            cost: Reverse(cost),
-            count,
-          });
+            [new_vertex, vertex_idx], // verify order unimportant
+            [
+              &new_vertex_quadric,
+              vertex_quadrics[vertex_idx as usize].as_ref().unwrap(),
+            ],
+            &mut plan_edges,
+          );
+
+          match planned {
+            Some((collapse, cost)) => {
+              edge_info[edge_idx as usize] = Some(PlannedCollapse {
+                collapse,
+                vertices: [new_vertex, vertex_idx],
+                count,
+                cost,
+              });
+
+              edge_heap.push(EdgeCost {
+                edge_idx,
+                cost: Reverse(cost),
+                count,
+                rejections: 0,
+              });
+            }
+            // entries already on the heap are stale now
+            None => {
+              if let Some(planned) = &mut edge_info[edge_idx as usize] {
+                planned.count = count;
+              }
+            }
+          }
         }
       }
     }
+
+    report.faces = mesh.num_faces();
+
+    report
+  }
+
+  // the largest of the face counts asked for (0 if only the error is
+  // limited)
+  fn target_num_faces(&self, initial_num_faces: usize) -> usize {
+    let by_count = self
+      .faces_to_remove
+      .map(|n| initial_num_faces.saturating_sub(n as usize));
+    let by_target = self.target_faces.map(|n| n as usize);
+    let by_ratio = self
+      .ratio
+      .map(|ratio| (ratio as f64 * initial_num_faces as f64).ceil() as usize);
+
+    by_count
+      .into_iter()
+      .chain(by_target)
+      .chain(by_ratio)
+      .max()
+      .unwrap_or(0)
+  }
+
+  fn check_ratio(&self) -> Result<(), MeshError> {
+    if let Some(ratio) = self.ratio {
+      if !(0.0..=1.0).contains(&ratio) {
+        return Err(MeshError::InvalidArgument(format!(
+          "invalid ratio {} (must be between 0 and 1)",
+          ratio
+        )));
+      }
+    }
+
+    Ok(())
+  }
+
+  // if the vertex clustering strategy is used (which works on the raw
+  // vertices and faces rather than a mesh)
+  pub fn uses_clustering(&self) -> bool {
+    self.cluster
+  }
+
+  // vertex clustering with the cell size given or the largest one which
+  // reaches the face count; the faces don't need to be manifold and the
+  // result is repaired so it can be built into a mesh
+  pub fn cluster(
+    &self,
+    vertices: &mut Vec<Vector3>,
+    faces: &mut Vec<Face>,
+    attributes: &mut Attributes,
+  ) -> Result<ClusterReport, MeshError> {
+    check_input(vertices, faces)?;
+    self.check_ratio()?;
+
+    if self.max_error.is_some() || self.progressive.is_some() {
+      return Err(MeshError::InvalidArgument(
+        "--max-error and --progressive can't be used with --cluster".to_owned(),
+      ));
+    }
+
+    let has_target = self.faces_to_remove.is_some()
+      || self.target_faces.is_some()
+      || self.ratio.is_some();
+
+    let cell_size = match self.cell_size {
+      Some(cell_size) if cell_size.is_finite() && cell_size > 0.0 => cell_size,
+      Some(cell_size) => {
+        return Err(MeshError::InvalidArgument(format!(
+          "invalid cell size {} (must be positive)",
+          cell_size
+        )))
+      }
+      None if has_target => {
+        cell_size_for_faces(vertices, faces, self.target_num_faces(faces.len()))
+      }
+      None => {
+        return Err(MeshError::InvalidArgument(
+          "simplify --cluster needs --cell-size, a number of faces to \
+           remove, --target-faces or --ratio"
+            .to_owned(),
+        ))
+      }
+    };
+
+    Ok(cluster(
+      vertices,
+      faces,
+      attributes,
+      cell_size,
+      self.cluster_quadrics,
+    ))
+  }
+
+  // simplifies until one of the stopping criteria is met
+  pub fn simplify<D: DataStructure>(
+    &self,
+    mesh: &mut D,
+  ) -> Result<SimplifyReport, MeshError> {
+    self.run(mesh, None)
+  }
+
+  // clusters, simplifies or simplifies and records the collapses depending on
+  // the options
+  pub fn simplify_mesh<D: DataStructure>(
+    &self,
+    mesh: &mut D,
+  ) -> Result<SimplifyOutcome, MeshError> {
+    if self.cluster {
+      let (mut vertices, mut faces, mut attributes) =
+        mesh.to_vecs_with_attributes();
+
+      let report = self.cluster(&mut vertices, &mut faces, &mut attributes)?;
+
+      *mesh = D::try_from_iters(vertices, faces)?;
+      mesh.set_attributes(attributes)?;
+
+      return Ok(SimplifyOutcome::Clustered(report));
+    }
+
+    match &self.progressive {
+      Some(path) => {
+        let (report, progressive) = self.simplify_progressive(mesh)?;
+
+        pm::write(path, &progressive)?;
+
+        Ok(SimplifyOutcome::Progressive {
+          report,
+          levels: progressive.num_levels(),
+          path: path.clone(),
+        })
+      }
+      None => Ok(SimplifyOutcome::Collapsed(self.simplify(mesh)?)),
+    }
+  }
+
+  // like simplify, but also records the collapses so the original mesh (and
+  // every level in between) can be rebuilt
+  pub fn simplify_progressive<D: DataStructure>(
+    &self,
+    mesh: &mut D,
+  ) -> Result<(SimplifyReport, ProgressiveMesh), MeshError> {
+    let (_, _, attributes) = mesh.to_vecs_with_attributes();
+    let mut splits = Vec::new();
+
+    let report = self.run(mesh, Some(&mut splits))?;
+
+    Ok((report, ProgressiveMesh::new(mesh, &attributes, splits)))
+  }
+
+  fn run<D: DataStructure>(
+    &self,
+    mesh: &mut D,
+    splits: Option<&mut Vec<VertexSplit>>,
+  ) -> Result<SimplifyReport, MeshError> {
+    mesh.check_triangle_mesh()?;
+    self.checks.validate()?;
+
+    let valid = |value: f32, min: f32| value.is_finite() && value >= min;
+
+    if self.faces_to_remove.is_none()
+      && self.target_faces.is_none()
+      && self.ratio.is_none()
+      && self.max_error.is_none()
+    {
+      return Err(MeshError::InvalidArgument(
+        "simplify needs a number of faces to remove, --target-faces, \
+         --ratio or --max-error"
+          .to_owned(),
+      ));
+    }
+
+    self.check_ratio()?;
+
+    if let Some(max_error) = self.max_error {
+      if !valid(max_error, 0.0) {
+        return Err(MeshError::InvalidArgument(format!(
+          "invalid max error {}",
+          max_error
+        )));
+      }
+    }
+
+    if !valid(self.attribute_weight, f32::MIN_POSITIVE)
+      || !valid(self.seam_weight, 0.0)
+      || !valid(self.boundary_weight, 0.0)
+    {
+      return Err(MeshError::InvalidArgument(format!(
+        "invalid attribute weight {}, seam weight {} or boundary weight {} \
+         (the attribute weight must be positive and the others can't be \
+         negative)",
+        self.attribute_weight, self.seam_weight, self.boundary_weight
+      )));
+    }
+
+    if !self.attributes {
+      let metric = PositionMetric {
+        boundary_weight: self.boundary_weight,
+        lock_boundary: self.lock_boundary,
+      };
+
+      return Ok(self.simplify_with_metric(mesh, &metric, splits));
+    }
+
+    let metric = AttributeMetric {
+      attribute_weight: self.attribute_weight as f64,
+      seam_weight: self.seam_weight as f64,
+      boundary_weight: self.boundary_weight as f64,
+      lock_boundary: self.lock_boundary,
+      dimensions: mesh.attribute_dimensions(),
+    };
+
+    Ok(self.simplify_with_metric(mesh, &metric, splits))
+  }
+}
+
+impl Operation for Simplify {
+  fn try_apply<D: DataStructure>(&self, mesh: &mut D) -> Result<(), MeshError> {
+    self.simplify_mesh(mesh).map(|_| ())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::mesh_operation::parallel::vertices;
+  use crate::Attribute;
+  use crate::HalfEdge;
+
+  fn simplify(args: &[&str]) -> Simplify {
+    Simplify::try_parse_from(
+      std::iter::once("simplify").chain(args.iter().cloned()),
+    )
+    .unwrap()
+  }
+
+  // flat grid of n by n quads (two triangles each) in the xy plane
+  fn grid(n: IndexType) -> (Vec<Vector3>, Vec<Face>) {
+    let idx = |x: IndexType, y: IndexType| x + y * (n + 1);
+
+    let positions = (0..(n + 1) * (n + 1))
+      .map(|i| Vector3::new((i % (n + 1)) as f32, (i / (n + 1)) as f32, 0.0))
+      .collect();
+
+    let mut faces = Vec::new();
+
+    for y in 0..n {
+      for x in 0..n {
+        faces.push(vec![idx(x, y), idx(x + 1, y), idx(x + 1, y + 1)]);
+        faces.push(vec![idx(x, y), idx(x + 1, y + 1), idx(x, y + 1)]);
+      }
+    }
+
+    (positions, faces)
+  }
+
+  // grid with every other vertex raised, so every collapse has an error
+  fn bumpy_grid() -> HalfEdge {
+    let (mut positions, faces) = grid(6);
+
+    for (i, position) in positions.iter_mut().enumerate() {
+      position[2] = 0.5 * (i % 2) as f32;
+    }
+
+    HalfEdge::from_iters(positions, faces)
+  }
+
+  fn simplify_bumpy_grid(args: &[&str]) -> SimplifyReport {
+    let mut mesh = bumpy_grid();
+    let report = simplify(args).simplify(&mut mesh).unwrap();
+
+    assert_eq!(report.initial_faces, 72);
+    assert_eq!(report.faces, mesh.num_faces());
+
+    report
+  }
+
+  #[test]
+  fn target_faces_stop() {
+    let report = simplify_bumpy_grid(&["--target-faces", "30"]);
+
+    assert_eq!(report.stop, StopReason::TargetReached);
+    assert!(report.faces <= 30);
+  }
+
+  #[test]
+  fn ratio_stop() {
+    let report = simplify_bumpy_grid(&["--ratio", "0.5"]);
+
+    assert_eq!(report.stop, StopReason::TargetReached);
+    assert!(report.faces <= 36);
+  }
+
+  #[test]
+  fn max_error_stop() {
+    let report = simplify_bumpy_grid(&["--max-error", "1e-6"]);
+
+    assert_eq!(report.stop, StopReason::MaxError);
+    assert!(report.max_error <= 1e-6);
+  }
+
+  #[test]
+  fn lock_boundary() {
+    let runs: [&[&str]; 2] = [&[], &["--attributes"]];
+
+    for extra_args in &runs {
+      let mut mesh = bumpy_grid();
+
+      let mut edges = Vec::new();
+      let boundary: Vec<_> = vertices(&mesh)
+        .into_iter()
+        .filter(|v| mesh.get_vertex_edges(*v, &mut edges))
+        .map(|v| mesh.get_position(v))
+        .collect();
+
+      let mut args = vec!["--target-faces", "20", "--lock-boundary"];
+      args.extend_from_slice(extra_args);
+      simplify(&args).simplify(&mut mesh).unwrap();
+
+      assert!(mesh.num_faces() < 72);
+
+      // the collapsed vertex may take the index of either endpoint, so the
+      // boundary is compared by position
+      let positions: Vec<_> = vertices(&mesh)
+        .into_iter()
+        .filter(|v| mesh.get_vertex_edges(*v, &mut edges))
+        .map(|v| mesh.get_position(v))
+        .collect();
+
+      assert_eq!(positions.len(), boundary.len());
+
+      for position in &boundary {
+        assert!(positions.contains(position));
+      }
+    }
+  }
+
+  #[test]
+  fn uv_seam_is_kept() {
+    let seam = 3.0;
+    let (positions, faces) = grid(6);
+
+    // the right half of the grid is shifted in u, so the corners on the
+    // column x = seam have two uv values
+    let uv_at = |position: &Vector3, right: bool| {
+      vec![
+        0.1 * position[0] + if right { 0.5 } else { 0.0 },
+        0.1 * position[1],
+      ]
+    };
+    let is_right = |face: &Face| {
+      face.iter().map(|v| positions[*v as usize][0]).sum::<f32>() > 3.0 * seam
+    };
+
+    let mut uv = Attribute::new("uv", 2);
+
+    for face in &faces {
+      for v in face {
+        uv.push(&uv_at(&positions[*v as usize], is_right(face)));
+      }
+    }
+
+    let mut mesh = HalfEdge::from_iters(positions.clone(), faces);
+    mesh
+      .set_attributes(Attributes {
+        vertex: Vec::new(),
+        corner: vec![uv],
+      })
+      .unwrap();
+
+    simplify(&["--attributes", "--target-faces", "24"])
+      .simplify(&mut mesh)
+      .unwrap();
+
+    assert!(mesh.num_faces() < 72);
+
+    let mut face_vertices = Vec::new();
+    let mut values = Vec::new();
+    let mut seam_corners = [0, 0];
+
+    let mut face_op = mesh.initial_face();
+    while let Some(face) = face_op {
+      mesh.get_face_vertices(face, &mut face_vertices);
+
+      let right = face_vertices
+        .iter()
+        .map(|v| mesh.get_position(*v)[0])
+        .sum::<f32>()
+        > 3.0 * seam;
+
+      for vertex in &face_vertices {
+        let position = mesh.get_position(*vertex);
+
+        if (position[0] - seam).abs() < 1e-4 {
+          mesh.get_corner_attributes(*vertex, face, &mut values);
+
+          let expected = uv_at(&position, right);
+
+          assert!((values[0] - expected[0]).abs() < 1e-4);
+          assert!((values[1] - expected[1]).abs() < 1e-4);
+
+          seam_corners[right as usize] += 1;
+        }
+      }
+
+      face_op = mesh.next_face(face);
+    }
+
+    // the seam is still there with both sides
+    assert!(seam_corners[0] > 0 && seam_corners[1] > 0);
   }
 }
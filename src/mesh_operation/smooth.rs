@@ -0,0 +1,494 @@
+use crate::mesh_operation::Operation;
+use crate::DataStructure;
+use crate::MeshError;
+use crate::Vector3;
+
+use clap::{ArgEnum, Clap};
+
+// conjugate gradient stops once the residual is this much smaller than the
+// right hand side (or after MAX_CG_ITERATIONS)
+const CG_TOLERANCE: f64 = 1e-8;
+const MAX_CG_ITERATIONS: usize = 1000;
+
+#[derive(ArgEnum, Clone, Copy, PartialEq)]
+pub enum SmoothWeights {
+  Uniform,
+  Cotangent,
+}
+
+// laplacian smoothing (Desbrun et al. 1999) or taubin's lambda/mu smoothing
+// (Taubin 1995) which doesn't shrink the mesh; boundary vertices don't move
+#[derive(Clap)]
+pub struct Smooth {
+  iterations: u32,
+  /// weights of the neighbors in the laplacian (uniform or cotangent)
+  #[clap(arg_enum, long = "weights", default_value = "uniform")]
+  weights: SmoothWeights,
+  /// step size (how far each vertex moves toward the weighted average of its
+  /// neighbors)
+  #[clap(long = "lambda", default_value = "0.5")]
+  lambda: f32,
+  /// alternate each step with a step of --mu (taubin smoothing)
+  #[clap(long = "taubin")]
+  taubin: bool,
+  /// size of the inflating second step of taubin smoothing (negative and
+  /// larger in magnitude than lambda)
+  #[clap(long = "mu", default_value = "-0.53", allow_hyphen_values = true)]
+  mu: f32,
+  /// solve for each step (backward euler with conjugate gradient) instead of
+  /// moving explicitly, which is stable for any lambda
+  #[clap(long = "implicit")]
+  implicit: bool,
+}
+
+// symmetric system matrix row by row, off diagonal entries as (column, value)
+struct SparseMatrix {
+  diagonal: Vec<f64>,
+  off_diagonal: Vec<Vec<(usize, f64)>>,
+}
+
+impl SparseMatrix {
+  fn multiply(&self, x: &[f64], out: &mut [f64]) {
+    for (row, out) in out.iter_mut().enumerate() {
+      *out = self.diagonal[row] * x[row]
+        + self.off_diagonal[row]
+          .iter()
+          .map(|(column, value)| value * x[*column])
+          .sum::<f64>();
+    }
+  }
+
+  // jacobi preconditioned conjugate gradient starting from x, returns the
+  // number of iterations and the relative residual
+  fn solve(&self, b: &[f64], x: &mut [f64]) -> (usize, f64) {
+    let n = b.len();
+    let dot = |a: &[f64], b: &[f64]| -> f64 {
+      a.iter().zip(b).map(|(a, b)| a * b).sum()
+    };
+
+    let b_norm = dot(b, b).sqrt();
+
+    if b_norm == 0.0 {
+      x.iter_mut().for_each(|x| *x = 0.0);
+      return (0, 0.0);
+    }
+
+    let mut r = vec![0.0; n];
+    self.multiply(x, &mut r);
+    r.iter_mut().zip(b).for_each(|(r, b)| *r = b - *r);
+
+    let mut z: Vec<f64> =
+      r.iter().zip(&self.diagonal).map(|(r, d)| r / d).collect();
+    let mut p = z.clone();
+    let mut a_p = vec![0.0; n];
+    let mut r_z = dot(&r, &z);
+
+    for iteration in 0..MAX_CG_ITERATIONS {
+      let residual = dot(&r, &r).sqrt() / b_norm;
+
+      if residual < CG_TOLERANCE {
+        return (iteration, residual);
+      }
+
+      self.multiply(&p, &mut a_p);
+
+      let alpha = r_z / dot(&p, &a_p);
+
+      for i in 0..n {
+        x[i] += alpha * p[i];
+        r[i] -= alpha * a_p[i];
+        z[i] = r[i] / self.diagonal[i];
+      }
+
+      let new_r_z = dot(&r, &z);
+      let beta = new_r_z / r_z;
+      r_z = new_r_z;
+
+      for i in 0..n {
+        p[i] = z[i] + beta * p[i];
+      }
+    }
+
+    (MAX_CG_ITERATIONS, dot(&r, &r).sqrt() / b_norm)
+  }
+}
+
+// cotangent of the angle at the vertex between the edges to the others
+fn cotangent(at: &Vector3, first: &Vector3, second: &Vector3) -> f64 {
+  let a = (first - at).map(|v| v as f64);
+  let b = (second - at).map(|v| v as f64);
+
+  let cross = a.cross(&b).norm();
+
+  if cross == 0.0 {
+    0.0
+  } else {
+    a.dot(&b) / cross
+  }
+}
+
+impl Smooth {
+  fn validate(&self) -> Result<(), MeshError> {
+    if !self.lambda.is_finite() || self.lambda <= 0.0 {
+      return Err(MeshError::InvalidArgument(format!(
+        "invalid lambda {} (must be positive)",
+        self.lambda
+      )));
+    }
+
+    if self.taubin {
+      if self.implicit {
+        return Err(MeshError::InvalidArgument(
+          "taubin smoothing can't be implicit (the mu step isn't stable)"
+            .to_owned(),
+        ));
+      }
+
+      if !self.mu.is_finite() || self.mu >= -self.lambda {
+        return Err(MeshError::InvalidArgument(format!(
+          "invalid mu {} (must be negative with a larger magnitude than \
+           lambda)",
+          self.mu
+        )));
+      }
+    }
+
+    Ok(())
+  }
+
+  // weight of each edge (cotangent weights are clamped to be non negative
+  // so vertices always move toward their neighbors)
+  fn edge_weights<D: DataStructure>(&self, mesh: &D) -> Vec<f64> {
+    let mut out = vec![0.0; mesh.max_idx_edges()];
+
+    let mut edge_op = mesh.initial_edge();
+
+    while let Some(edge_idx) = edge_op {
+      out[edge_idx as usize] = match self.weights {
+        SmoothWeights::Uniform => 1.0,
+        SmoothWeights::Cotangent => {
+          let ([l, r, far], far_op) = mesh.get_edge_neighbors(edge_idx);
+          let l = mesh.get_position(l);
+          let r = mesh.get_position(r);
+
+          let weight = std::iter::once(far)
+            .chain(far_op)
+            .map(|far| cotangent(&mesh.get_position(far), &l, &r))
+            .sum::<f64>()
+            / 2.0;
+
+          weight.max(0.0)
+        }
+      };
+
+      edge_op = mesh.next_edge(edge_idx);
+    }
+
+    out
+  }
+
+  // one step of size lambda toward the weighted average of the neighbors
+  // (the mesh isn't changed if an implicit step doesn't converge)
+  fn step<D: DataStructure>(
+    &self,
+    mesh: &mut D,
+    lambda: f64,
+  ) -> Result<(), MeshError> {
+    let weights = self.edge_weights(mesh);
+
+    // interior vertices are the unknowns
+    let mut row_of = vec![None; mesh.max_idx_vertices()];
+    let mut vertices = Vec::new();
+    let mut edges = Vec::new();
+
+    let mut vertex_op = mesh.initial_vertex();
+
+    while let Some(vertex_idx) = vertex_op {
+      if !mesh.get_vertex_edges(vertex_idx, &mut edges) {
+        row_of[vertex_idx as usize] = Some(vertices.len());
+        vertices.push(vertex_idx);
+      }
+
+      vertex_op = mesh.next_vertex(vertex_idx);
+    }
+
+    // sum of the weights and weighted sum of the neighbors
+    let mut matrix = SparseMatrix {
+      diagonal: Vec::with_capacity(vertices.len()),
+      off_diagonal: Vec::with_capacity(vertices.len()),
+    };
+    let mut weighted_sums = Vec::with_capacity(vertices.len());
+
+    for vertex_idx in &vertices {
+      mesh.get_vertex_edges(*vertex_idx, &mut edges);
+
+      let mut total = 0.0;
+      let mut sum = nalgebra::Vector3::<f64>::zeros();
+      let mut row = Vec::new();
+
+      for (edge_idx, other_idx) in &edges {
+        let weight = weights[*edge_idx as usize];
+
+        total += weight;
+        sum += weight * mesh.get_position(*other_idx).map(|v| v as f64);
+
+        if let Some(column) = row_of[*other_idx as usize] {
+          row.push((column, -lambda * weight));
+        }
+      }
+
+      matrix.diagonal.push(total * (1.0 + lambda));
+      matrix.off_diagonal.push(row);
+      weighted_sums.push((total, sum));
+    }
+
+    let positions: Vec<nalgebra::Vector3<f64>> = vertices
+      .iter()
+      .map(|vertex_idx| mesh.get_position(*vertex_idx).map(|v| v as f64))
+      .collect();
+
+    let mut new_positions = positions.clone();
+
+    if self.implicit {
+      // (total + lambda laplacian) x' = total x, with boundary neighbors
+      // moved to the right hand side
+      for axis in 0..3 {
+        let b: Vec<f64> = vertices
+          .iter()
+          .enumerate()
+          .map(|(row, vertex_idx)| {
+            let (total, _) = weighted_sums[row];
+
+            mesh.get_vertex_edges(*vertex_idx, &mut edges);
+
+            let fixed: f64 = edges
+              .iter()
+              .filter(|(_, other_idx)| row_of[*other_idx as usize].is_none())
+              .map(|(edge_idx, other_idx)| {
+                weights[*edge_idx as usize]
+                  * mesh.get_position(*other_idx)[axis] as f64
+              })
+              .sum();
+
+            total * positions[row][axis] + lambda * fixed
+          })
+          .collect();
+
+        let mut x: Vec<f64> = positions.iter().map(|p| p[axis]).collect();
+
+        // vertices without weights stay where they are
+        let mut solvable = matrix.diagonal.iter().all(|d| *d > 0.0);
+
+        if solvable {
+          let (iterations, residual) = matrix.solve(&b, &mut x);
+
+          if iterations == MAX_CG_ITERATIONS {
+            return Err(MeshError::NotConverged {
+              iterations,
+              residual,
+            });
+          }
+
+          solvable = x.iter().all(|v| v.is_finite());
+        }
+
+        if solvable {
+          for (position, x) in new_positions.iter_mut().zip(x) {
+            position[axis] = x;
+          }
+        }
+      }
+    } else {
+      for (row, position) in new_positions.iter_mut().enumerate() {
+        let (total, sum) = weighted_sums[row];
+
+        if total > 0.0 {
+          *position += lambda * (sum / total - *position);
+        }
+      }
+    }
+
+    for (vertex_idx, position) in vertices.iter().zip(new_positions) {
+      mesh.set_position(*vertex_idx, &position.map(|v| v as f32));
+    }
+
+    Ok(())
+  }
+}
+
+impl Operation for Smooth {
+  fn try_apply<D: DataStructure>(&self, mesh: &mut D) -> Result<(), MeshError> {
+    mesh.check_triangle_mesh()?;
+    self.validate()?;
+
+    for _ in 0..self.iterations {
+      self.step(mesh, self.lambda as f64)?;
+
+      if self.taubin {
+        self.step(mesh, self.mu as f64)?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::mesh_operation::parallel::vertices;
+  use crate::HalfEdge;
+  use crate::IndexType;
+  use crate::Subdivide;
+
+  fn smooth(args: &[&str]) -> Smooth {
+    Smooth::try_parse_from(
+      std::iter::once("smooth").chain(args.iter().cloned()),
+    )
+    .unwrap()
+  }
+
+  // n by n grid with the interior vertices moved around in the xy plane
+  // and z from height
+  fn grid<F: Fn(f32, f32) -> f32>(n: IndexType, height: F) -> HalfEdge {
+    let idx = |x: IndexType, y: IndexType| x + y * (n + 1);
+
+    let positions = (0..(n + 1) * (n + 1)).map(|i| {
+      let (x, y) = (i % (n + 1), i / (n + 1));
+      let interior = x > 0 && x < n && y > 0 && y < n;
+      let jitter = if interior {
+        0.1 * ((i * 7) % 5) as f32 - 0.2
+      } else {
+        0.0
+      };
+
+      let (x, y) = (x as f32 + jitter, y as f32 - jitter);
+      Vector3::new(x, y, height(x, y))
+    });
+
+    let mut faces = Vec::new();
+
+    for y in 0..n {
+      for x in 0..n {
+        faces.push(vec![idx(x, y), idx(x + 1, y), idx(x + 1, y + 1)]);
+        faces.push(vec![idx(x, y), idx(x + 1, y + 1), idx(x, y + 1)]);
+      }
+    }
+
+    HalfEdge::from_iters(positions, faces)
+  }
+
+  // subdivided octahedron on the unit sphere
+  fn sphere() -> HalfEdge {
+    let positions = vec![
+      Vector3::new(0.0, 0.0, 1.0),
+      Vector3::new(0.0, 0.0, -1.0),
+      Vector3::new(1.0, 0.0, 0.0),
+      Vector3::new(0.0, 1.0, 0.0),
+      Vector3::new(-1.0, 0.0, 0.0),
+      Vector3::new(0.0, -1.0, 0.0),
+    ];
+    let faces = (0..4).flat_map(|i| {
+      let (a, b) = (2 + i, 2 + (i + 1) % 4);
+
+      vec![vec![0, a, b], vec![1, b, a]]
+    });
+
+    let mut mesh = HalfEdge::from_iters(positions, faces);
+
+    Subdivide::try_parse_from(["subdivide", "3"])
+      .unwrap()
+      .try_apply(&mut mesh)
+      .unwrap();
+
+    for v in vertices(&mesh) {
+      let position = mesh.get_position(v).normalize();
+      mesh.set_position(v, &position);
+    }
+
+    mesh
+  }
+
+  fn volume(mesh: &HalfEdge) -> f32 {
+    let mut volume = 0.0;
+
+    let mut face_op = mesh.initial_face();
+    while let Some(face) = face_op {
+      let (_, [p_0, p_1, p_2]) = mesh.get_face_normal(face);
+      volume += p_0.dot(&p_1.cross(&p_2)) / 6.0;
+
+      face_op = mesh.next_face(face);
+    }
+
+    volume
+  }
+
+  #[test]
+  fn implicit_keeps_plane() {
+    let height = |x: f32, y: f32| 0.3 * x - 0.2 * y + 1.0;
+    let mut mesh = grid(6, height);
+    let (before, _) = mesh.to_vecs();
+
+    smooth(&["3", "--implicit", "--lambda", "5", "--weights", "cotangent"])
+      .try_apply(&mut mesh)
+      .unwrap();
+
+    let (after, _) = mesh.to_vecs();
+
+    assert!(before
+      .iter()
+      .zip(&after)
+      .any(|(b, a)| (b - a).norm() > 1e-2));
+
+    for position in &after {
+      assert!((position[2] - height(position[0], position[1])).abs() < 1e-4);
+    }
+  }
+
+  #[test]
+  fn taubin_keeps_volume() {
+    let initial = volume(&sphere());
+
+    let mut laplacian = sphere();
+    smooth(&["10"]).try_apply(&mut laplacian).unwrap();
+
+    let mut taubin = sphere();
+    smooth(&["10", "--taubin"]).try_apply(&mut taubin).unwrap();
+
+    let shrink = |mesh: &HalfEdge| 1.0 - volume(mesh) / initial;
+
+    assert!(shrink(&taubin).abs() < 0.03, "{}", shrink(&taubin));
+    assert!(
+      shrink(&laplacian) > 5.0 * shrink(&taubin).abs(),
+      "{}",
+      shrink(&laplacian)
+    );
+  }
+
+  #[test]
+  fn boundary_is_fixed() {
+    let runs: [&[&str]; 3] = [&["5"], &["5", "--taubin"], &["5", "--implicit"]];
+
+    for args in &runs {
+      let mut mesh = grid(6, |x, y| (x * y).sin());
+      let (before, _) = mesh.to_vecs();
+
+      smooth(args).try_apply(&mut mesh).unwrap();
+
+      let mut edges = Vec::new();
+      let mut moved = false;
+
+      for v in vertices(&mesh) {
+        let position = mesh.get_position(v);
+
+        if mesh.get_vertex_edges(v, &mut edges) {
+          assert_eq!(position, before[v as usize]);
+        } else {
+          moved |= position != before[v as usize];
+        }
+      }
+
+      assert!(moved);
+    }
+  }
+}
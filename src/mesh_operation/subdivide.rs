@@ -1,15 +1,26 @@
+use crate::mesh_operation::parallel::map_vertices;
 use crate::mesh_operation::Operation;
+use crate::mesh_operation::SharpEdges;
 use crate::DataStructure;
+use crate::IndexType;
+use crate::MeshError;
 use crate::Vector3;
 use clap::Clap;
 
 #[derive(Clap)]
 pub struct Subdivide {
   iterations: u32,
+  #[clap(flatten)]
+  sharp_edges: SharpEdges,
 }
 
 impl Operation for Subdivide {
-  fn apply<D: DataStructure>(&self, mesh: &mut D) {
+  fn try_apply<D: DataStructure>(&self, mesh: &mut D) -> Result<(), MeshError> {
+    mesh.check_triangle_mesh()?;
+
+    // boundary edges are always treated as sharp
+    let mut sharp = self.sharp_edges.find(mesh)?;
+
     for _ in 0..self.iterations {
       let mut new_vertex_info = Vec::with_capacity(mesh.num_edges());
 
@@ -17,54 +28,92 @@ impl Operation for Subdivide {
       while let Some(edge) = edge_op {
         let ([near_0, near_1, far_0], far_op) = mesh.get_edge_neighbors(edge);
 
-        let far_1 = far_op.expect("TODO: handle boundaries");
-
-        let weight_near = 3.0 / 8.0;
-        let weight_far = 1.0 / 8.0;
-        let pos = weight_near
-          * (mesh.get_position(near_0) + mesh.get_position(near_1))
-          + weight_far * (mesh.get_position(far_0) + mesh.get_position(far_1));
-
-        new_vertex_info.push((edge, pos, [far_0, far_1]));
-
-        edge_op = mesh.next_edge(edge);
-      }
-
-      let mut neighbors = Vec::new();
-
-      // set positions of old vertices
-      let mut vertex_op = mesh.initial_vertex();
-      while let Some(vertex) = vertex_op {
-        let boundary = mesh.get_vertex_neighbors(vertex, &mut neighbors);
+        let near_pos = mesh.get_position(near_0) + mesh.get_position(near_1);
 
-        assert!(!boundary);
+        let pos = match far_op {
+          Some(far_1) if !sharp[edge as usize] => {
+            let weight_near = 3.0 / 8.0;
+            let weight_far = 1.0 / 8.0;
 
-        let n = neighbors.len() as f32;
-
-        let u = if neighbors.len() == 3 {
-          3.0 / 16.0
-        } else {
-          3.0 / (8.0 * n)
+            weight_near * near_pos
+              + weight_far
+                * (mesh.get_position(far_0) + mesh.get_position(far_1))
+          }
+          _ => 0.5 * near_pos,
         };
 
-        let avg =
-          neighbors
-            .iter()
-            .fold(Vector3::new(0.0, 0.0, 0.0), |acc, x| {
-              debug_assert!(*x != vertex);
-
-              acc + mesh.get_position(*x)
-            })
-            * u;
+        new_vertex_info.push((edge, pos, far_0, far_op));
 
-        let pos = avg
-          + mesh
-            .get_position(vertex)
-            .component_mul(&Vector3::from_element(1.0 - n * u));
+        edge_op = mesh.next_edge(edge);
+      }
 
+      // positions of old vertices (set after all are computed so each
+      // uses the positions from before this iteration)
+      let new_positions = map_vertices(
+        mesh,
+        |(edges, sharp_neighbors): &mut (
+          Vec<(IndexType, IndexType)>,
+          Vec<IndexType>,
+        ),
+         vertex| {
+          let boundary = mesh.get_vertex_edges(vertex, edges);
+
+          sharp_neighbors.clear();
+          sharp_neighbors.extend(
+            edges
+              .iter()
+              .filter(|(edge, _)| sharp[*edge as usize])
+              .map(|(_, other)| *other),
+          );
+
+          // with a boundary the two boundary edges are sharp
+          if boundary {
+            let first = edges[0].1;
+            let last = edges[edges.len() - 1].1;
+
+            for other in &[first, last] {
+              if !sharp_neighbors.contains(other) {
+                sharp_neighbors.push(*other);
+              }
+            }
+          }
+
+          let orig_pos = mesh.get_position(vertex);
+
+          match sharp_neighbors.len() {
+            // smooth (or dart) vertex
+            0 | 1 => {
+              let n = edges.len() as f32;
+
+              let u = if edges.len() == 3 {
+                3.0 / 16.0
+              } else {
+                3.0 / (8.0 * n)
+              };
+
+              let avg = edges.iter().fold(Vector3::zeros(), |acc, (_, x)| {
+                debug_assert!(*x != vertex);
+
+                acc + mesh.get_position(*x)
+              }) * u;
+
+              avg + orig_pos * (1.0 - n * u)
+            }
+            // crease or boundary vertex
+            2 => {
+              (3.0 / 4.0) * orig_pos
+                + (1.0 / 8.0)
+                  * (mesh.get_position(sharp_neighbors[0])
+                    + mesh.get_position(sharp_neighbors[1]))
+            }
+            // corner
+            _ => orig_pos,
+          }
+        },
+      );
+
+      for (vertex, pos) in new_positions {
         mesh.set_position(vertex, &pos);
-
-        vertex_op = mesh.next_vertex(vertex);
       }
 
       let mut to_flip = Vec::with_capacity(mesh.num_faces());
@@ -73,33 +122,127 @@ impl Operation for Subdivide {
 
       // collect so we don't iterate over new edges... (there are
       // more efficient approaches...)
-      for (edge_key, vertex_pos, [far_l, far_r]) in new_vertex_info {
-        let (new_vertex, [new_l, new_r, _, _]) = mesh.split_edge(edge_key);
+      for (edge_key, vertex_pos, far_l, far_r) in new_vertex_info {
+        let (new_vertex, [new_l, _, new_half], new_r) =
+          mesh.split_edge(edge_key);
 
         mesh.set_position(new_vertex, &vertex_pos);
 
+        sharp.resize(mesh.max_idx_edges(), false);
+        sharp[new_half as usize] = sharp[edge_key as usize];
+
         let [l_p_0, l_p_1] = mesh.get_endpoints(new_l);
-        let [r_p_0, r_p_1] = mesh.get_endpoints(new_r);
 
         debug_assert!(l_p_0 == new_vertex || l_p_1 == new_vertex);
-        debug_assert!(r_p_0 == new_vertex || r_p_1 == new_vertex);
-        debug_assert!(l_p_0 != far_r && l_p_1 != far_r);
-        debug_assert!(r_p_0 != far_l && r_p_1 != far_l);
 
         if l_p_0 == far_l || l_p_1 == far_l {
           to_flip.push(new_l);
         }
 
-        if r_p_0 == far_r || r_p_1 == far_r {
-          to_flip.push(new_r);
+        if let (Some(new_r), Some(far_r)) = (new_r, far_r) {
+          let [r_p_0, r_p_1] = mesh.get_endpoints(new_r);
+
+          debug_assert!(r_p_0 == new_vertex || r_p_1 == new_vertex);
+          debug_assert!(l_p_0 != far_r && l_p_1 != far_r);
+          debug_assert!(r_p_0 != far_l && r_p_1 != far_l);
+
+          if r_p_0 == far_r || r_p_1 == far_r {
+            to_flip.push(new_r);
+          }
         }
       }
 
       debug_assert_eq!(to_flip.len(), faces_before);
 
       for flip_edge in to_flip {
-        mesh.flip_edge(flip_edge).expect("should be valid flip");
+        mesh.try_flip_edge(flip_edge)?;
       }
     }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::file_format::test_path;
+  use crate::mesh_operation::parallel::vertices;
+  use crate::HalfEdge;
+
+  fn subdivide(args: &[&str]) -> Subdivide {
+    Subdivide::try_parse_from(
+      std::iter::once("subdivide").chain(args.iter().cloned()),
+    )
+    .unwrap()
+  }
+
+  fn has_position(mesh: &HalfEdge, position: &Vector3) -> bool {
+    vertices(mesh)
+      .iter()
+      .any(|v| (mesh.get_position(*v) - position).norm() < 1e-6)
+  }
+
+  #[test]
+  fn open_triangle_boundary_rule() {
+    let positions = vec![
+      Vector3::new(0.0, 0.0, 0.0),
+      Vector3::new(4.0, 0.0, 0.0),
+      Vector3::new(0.0, 8.0, 0.0),
+    ];
+    let mut mesh = HalfEdge::from_iters(positions.clone(), vec![vec![0, 1, 2]]);
+
+    subdivide(&["1"]).try_apply(&mut mesh).unwrap();
+
+    assert_eq!(mesh.num_vertices(), 6);
+    assert_eq!(mesh.num_faces(), 4);
+
+    // old vertices are 3/4 of themselves and 1/8 of each boundary neighbor
+    for i in 0..3 {
+      let expected = 0.75 * positions[i]
+        + 0.125 * (positions[(i + 1) % 3] + positions[(i + 2) % 3]);
+
+      assert!((mesh.get_position(i as IndexType) - expected).norm() < 1e-6);
+    }
+
+    // new boundary vertices are the midpoints of their edges
+    for i in 0..3 {
+      let midpoint = 0.5 * (positions[i] + positions[(i + 1) % 3]);
+
+      assert!(has_position(&mesh, &midpoint));
+    }
+  }
+
+  #[test]
+  fn creases_stay_sharp() {
+    // a pyramid without its base, creased along two opposite edges through
+    // the apex
+    let positions = vec![
+      Vector3::new(-1.0, -1.0, 0.0),
+      Vector3::new(1.0, -1.0, 0.0),
+      Vector3::new(1.0, 1.0, 0.0),
+      Vector3::new(-1.0, 1.0, 0.0),
+      Vector3::new(0.0, 0.0, 1.0),
+    ];
+    let faces = (0..4).map(|i| vec![i, (i + 1) % 4, 4]);
+    let mut mesh = HalfEdge::from_iters(positions.clone(), faces);
+
+    let path = test_path("creases.txt");
+    std::fs::write(&path, "# apex to two corners\n1 5\n3 5\n").unwrap();
+
+    let result = subdivide(&["1", "--creases", path.to_str().unwrap()])
+      .try_apply(&mut mesh);
+    std::fs::remove_file(&path).unwrap();
+    result.unwrap();
+
+    // the apex only follows its two crease neighbors
+    let apex = 0.75 * positions[4] + 0.125 * (positions[0] + positions[2]);
+
+    assert!((mesh.get_position(4) - apex).norm() < 1e-6);
+
+    // vertices on the creases are their midpoints, a smooth edge isn't
+    assert!(has_position(&mesh, &(0.5 * (positions[0] + positions[4]))));
+    assert!(has_position(&mesh, &(0.5 * (positions[2] + positions[4]))));
+    assert!(!has_position(&mesh, &(0.5 * (positions[1] + positions[4]))));
   }
 }
@@ -0,0 +1,5 @@
+mod point_grid;
+mod triangle_bvh;
+
+pub use point_grid::PointGrid;
+pub use triangle_bvh::TriangleBvh;
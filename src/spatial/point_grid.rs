@@ -0,0 +1,126 @@
+use crate::IndexType;
+use crate::Vector3;
+
+use std::collections::HashMap;
+
+type Cell = [i64; 3];
+
+// uniform hash grid over points for radius queries; the cell size should be
+// about the query radius so only neighboring cells need to be checked
+pub struct PointGrid {
+  cell_size: f32,
+  cells: HashMap<Cell, Vec<IndexType>>,
+  positions: Vec<Vector3>,
+}
+
+impl PointGrid {
+  pub fn new(cell_size: f32) -> Self {
+    assert!(cell_size > 0.0, "cell size must be positive");
+
+    PointGrid {
+      cell_size,
+      cells: HashMap::new(),
+      positions: Vec::new(),
+    }
+  }
+
+  fn cell(&self, position: &Vector3) -> Cell {
+    let to_cell = |v: f32| (v / self.cell_size).floor() as i64;
+
+    [
+      to_cell(position[0]),
+      to_cell(position[1]),
+      to_cell(position[2]),
+    ]
+  }
+
+  // returns the index of the new point (points are numbered in insertion
+  // order)
+  pub fn insert(&mut self, position: Vector3) -> IndexType {
+    let idx = self.positions.len() as IndexType;
+
+    self
+      .cells
+      .entry(self.cell(&position))
+      .or_default()
+      .push(idx);
+    self.positions.push(position);
+
+    idx
+  }
+
+  pub fn len(&self) -> usize {
+    self.positions.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.positions.is_empty()
+  }
+
+  pub fn position(&self, idx: IndexType) -> Vector3 {
+    self.positions[idx as usize]
+  }
+
+  // calls f with the index of each point within radius of position
+  pub fn for_each_within<F: FnMut(IndexType)>(
+    &self,
+    position: &Vector3,
+    radius: f32,
+    mut f: F,
+  ) {
+    let reach = (radius / self.cell_size).ceil() as i64;
+    let [c_x, c_y, c_z] = self.cell(position);
+    let radius_squared = radius * radius;
+
+    for x in (c_x - reach)..=(c_x + reach) {
+      for y in (c_y - reach)..=(c_y + reach) {
+        for z in (c_z - reach)..=(c_z + reach) {
+          if let Some(indices) = self.cells.get(&[x, y, z]) {
+            for &idx in indices {
+              let dist_squared =
+                (self.positions[idx as usize] - position).norm_squared();
+
+              if dist_squared <= radius_squared {
+                f(idx);
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+
+  // indices of all points within radius of position
+  pub fn within(&self, position: &Vector3, radius: f32) -> Vec<IndexType> {
+    let mut out = Vec::new();
+
+    self.for_each_within(position, radius, |idx| out.push(idx));
+
+    out
+  }
+
+  // closest point within radius of position (if any)
+  pub fn nearest_within(
+    &self,
+    position: &Vector3,
+    radius: f32,
+  ) -> Option<IndexType> {
+    let mut best: Option<(f32, IndexType)> = None;
+
+    self.for_each_within(position, radius, |idx| {
+      let dist_squared =
+        (self.positions[idx as usize] - position).norm_squared();
+
+      let closer = match best {
+        Some((best_dist, _)) => dist_squared < best_dist,
+        None => true,
+      };
+
+      if closer {
+        best = Some((dist_squared, idx));
+      }
+    });
+
+    best.map(|(_, idx)| idx)
+  }
+}
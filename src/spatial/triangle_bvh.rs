@@ -0,0 +1,305 @@
+use crate::IndexType;
+use crate::Vector3;
+
+// triangles per leaf
+const LEAF_SIZE: usize = 4;
+
+#[derive(Clone, Copy)]
+struct Bounds {
+  min: Vector3,
+  max: Vector3,
+}
+
+impl Bounds {
+  fn empty() -> Self {
+    Bounds {
+      min: Vector3::repeat(f32::INFINITY),
+      max: Vector3::repeat(f32::NEG_INFINITY),
+    }
+  }
+
+  fn grow(&mut self, point: &Vector3) {
+    self.min = self.min.zip_map(point, |a, b| a.min(b));
+    self.max = self.max.zip_map(point, |a, b| a.max(b));
+  }
+
+  fn distance_squared(&self, point: &Vector3) -> f32 {
+    Vector3::from_fn(|i, _| {
+      (self.min[i] - point[i])
+        .max(point[i] - self.max[i])
+        .max(0.0)
+    })
+    .norm_squared()
+  }
+}
+
+enum Node {
+  // children are the next node and the node at the index
+  Inner {
+    bounds: Bounds,
+    second: usize,
+  },
+  // range of `order`
+  Leaf {
+    bounds: Bounds,
+    start: usize,
+    end: usize,
+  },
+}
+
+impl Node {
+  fn bounds(&self) -> &Bounds {
+    match self {
+      Node::Inner { bounds, .. } | Node::Leaf { bounds, .. } => bounds,
+    }
+  }
+}
+
+// closest point on the triangle (Ericson, Real-Time Collision Detection
+// 5.1.5)
+fn closest_point_on_triangle(p: &Vector3, [a, b, c]: &[Vector3; 3]) -> Vector3 {
+  let ab = b - a;
+  let ac = c - a;
+  let ap = p - a;
+
+  let d_1 = ab.dot(&ap);
+  let d_2 = ac.dot(&ap);
+
+  if d_1 <= 0.0 && d_2 <= 0.0 {
+    return *a;
+  }
+
+  let bp = p - b;
+  let d_3 = ab.dot(&bp);
+  let d_4 = ac.dot(&bp);
+
+  if d_3 >= 0.0 && d_4 <= d_3 {
+    return *b;
+  }
+
+  let vc = d_1 * d_4 - d_3 * d_2;
+
+  if vc <= 0.0 && d_1 >= 0.0 && d_3 <= 0.0 {
+    return a + ab * (d_1 / (d_1 - d_3));
+  }
+
+  let cp = p - c;
+  let d_5 = ab.dot(&cp);
+  let d_6 = ac.dot(&cp);
+
+  if d_6 >= 0.0 && d_5 <= d_6 {
+    return *c;
+  }
+
+  let vb = d_5 * d_2 - d_1 * d_6;
+
+  if vb <= 0.0 && d_2 >= 0.0 && d_6 <= 0.0 {
+    return a + ac * (d_2 / (d_2 - d_6));
+  }
+
+  let va = d_3 * d_6 - d_5 * d_4;
+
+  if va <= 0.0 && (d_4 - d_3) >= 0.0 && (d_5 - d_6) >= 0.0 {
+    return b + (c - b) * ((d_4 - d_3) / ((d_4 - d_3) + (d_5 - d_6)));
+  }
+
+  let denom = 1.0 / (va + vb + vc);
+
+  if !denom.is_finite() {
+    // degenerate, the closest of the vertices is good enough
+    return **[a, b, c]
+      .iter()
+      .min_by(|x, y| {
+        (**x - p)
+          .norm_squared()
+          .partial_cmp(&(**y - p).norm_squared())
+          .unwrap()
+      })
+      .unwrap();
+  }
+
+  a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+// bounding volume hierarchy over a fixed set of triangles for closest point
+// queries (a copy of the triangles is kept, so the mesh they came from can
+// change)
+pub struct TriangleBvh {
+  triangles: Vec<[Vector3; 3]>,
+  // triangle indices, each leaf has a contiguous range
+  order: Vec<IndexType>,
+  nodes: Vec<Node>,
+}
+
+impl TriangleBvh {
+  pub fn new(triangles: Vec<[Vector3; 3]>) -> Self {
+    let mut out = TriangleBvh {
+      order: (0..triangles.len() as IndexType).collect(),
+      triangles,
+      nodes: Vec::new(),
+    };
+
+    if !out.triangles.is_empty() {
+      let centroids: Vec<Vector3> = out
+        .triangles
+        .iter()
+        .map(|[a, b, c]| (a + b + c) / 3.0)
+        .collect();
+
+      out.build(&centroids, 0, out.order.len());
+    }
+
+    out
+  }
+
+  // splits at the median along the longest axis of the centroids
+  fn build(&mut self, centroids: &[Vector3], start: usize, end: usize) {
+    let mut bounds = Bounds::empty();
+    let mut centroid_bounds = Bounds::empty();
+
+    for idx in &self.order[start..end] {
+      for point in &self.triangles[*idx as usize] {
+        bounds.grow(point);
+      }
+
+      centroid_bounds.grow(&centroids[*idx as usize]);
+    }
+
+    if end - start <= LEAF_SIZE {
+      self.nodes.push(Node::Leaf { bounds, start, end });
+      return;
+    }
+
+    let axis = (centroid_bounds.max - centroid_bounds.min).imax();
+    let middle = (start + end) / 2;
+
+    self.order[start..end].select_nth_unstable_by(middle - start, |x, y| {
+      centroids[*x as usize][axis]
+        .partial_cmp(&centroids[*y as usize][axis])
+        .unwrap()
+    });
+
+    let node_idx = self.nodes.len();
+    self.nodes.push(Node::Inner { bounds, second: 0 });
+
+    self.build(centroids, start, middle);
+
+    let second_idx = self.nodes.len();
+
+    if let Node::Inner { second, .. } = &mut self.nodes[node_idx] {
+      *second = second_idx;
+    }
+
+    self.build(centroids, middle, end);
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.triangles.is_empty()
+  }
+
+  // closest point on any triangle and the index of that triangle (None if
+  // there are no triangles)
+  pub fn closest_point(&self, point: &Vector3) -> Option<(Vector3, IndexType)> {
+    let mut best: Option<(f32, Vector3, IndexType)> = None;
+    let mut stack = Vec::new();
+
+    if !self.nodes.is_empty() {
+      stack.push(0);
+    }
+
+    while let Some(node_idx) = stack.pop() {
+      let node = &self.nodes[node_idx];
+
+      let best_dist = best.map_or(f32::INFINITY, |(dist, _, _)| dist);
+
+      if node.bounds().distance_squared(point) >= best_dist {
+        continue;
+      }
+
+      match node {
+        Node::Leaf { start, end, .. } => {
+          for idx in &self.order[*start..*end] {
+            let closest =
+              closest_point_on_triangle(point, &self.triangles[*idx as usize]);
+            let dist = (closest - point).norm_squared();
+
+            if dist < best.map_or(f32::INFINITY, |(dist, _, _)| dist) {
+              best = Some((dist, closest, *idx));
+            }
+          }
+        }
+        Node::Inner { second, .. } => {
+          let (first, second) = (node_idx + 1, *second);
+
+          // the closer child goes on top so it's searched first
+          let first_dist = self.nodes[first].bounds().distance_squared(point);
+          let second_dist = self.nodes[second].bounds().distance_squared(point);
+
+          if first_dist < second_dist {
+            stack.push(second);
+            stack.push(first);
+          } else {
+            stack.push(first);
+            stack.push(second);
+          }
+        }
+      }
+    }
+
+    best.map(|(_, closest, idx)| (closest, idx))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // scattered triangles (a fixed pseudo random pattern)
+  fn triangles(n: usize) -> Vec<[Vector3; 3]> {
+    let value = |i: usize| ((i * 7919 + 13) % 1009) as f32 / 100.0;
+    let point =
+      |i: usize| Vector3::new(value(3 * i), value(3 * i + 1), value(3 * i + 2));
+
+    (0..n)
+      .map(|i| {
+        let a = point(i);
+        let offset = |j: usize| 0.1 * point(n + 2 * i + j);
+
+        [a, a + offset(0), a + offset(1).yzx()]
+      })
+      .collect()
+  }
+
+  #[test]
+  fn closest_point_matches_brute_force() {
+    let triangles = triangles(200);
+    let bvh = TriangleBvh::new(triangles.clone());
+
+    for i in 0..100 {
+      let query = Vector3::new(
+        (i % 7) as f32 * 1.7 - 1.0,
+        (i % 11) as f32 * 1.1 - 1.0,
+        (i % 5) as f32 * 2.3 - 1.0,
+      );
+
+      let expected = triangles
+        .iter()
+        .map(|triangle| {
+          (closest_point_on_triangle(&query, triangle) - query).norm()
+        })
+        .fold(f32::INFINITY, f32::min);
+
+      let (closest, idx) = bvh.closest_point(&query).unwrap();
+
+      assert!(((closest - query).norm() - expected).abs() < 1e-5);
+      assert_eq!(
+        closest_point_on_triangle(&query, &triangles[idx as usize]),
+        closest
+      );
+    }
+
+    assert!(TriangleBvh::new(Vec::new())
+      .closest_point(&Vector3::zeros())
+      .is_none());
+  }
+}
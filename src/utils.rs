@@ -1,5 +1,130 @@
+use crate::DataStructure;
+use crate::IndexType;
 use crate::Vector3;
 
+use std::fs::File;
+use std::io::{prelude::*, BufReader};
+use std::path::Path;
+
 pub fn get_normal(vertices: [Vector3; 3]) -> Vector3 {
   ((vertices[1] - vertices[0]).cross(&(vertices[2] - vertices[0]))).normalize()
 }
+
+// smallest and largest coordinates of the points (infinite if there are none)
+pub fn bounds<I: IntoIterator<Item = Vector3>>(
+  points: I,
+) -> (Vector3, Vector3) {
+  points.into_iter().fold(
+    (
+      Vector3::repeat(f32::INFINITY),
+      Vector3::repeat(f32::NEG_INFINITY),
+    ),
+    |(min, max), v| {
+      (
+        min.zip_map(&v, |a, b| a.min(b)),
+        max.zip_map(&v, |a, b| a.max(b)),
+      )
+    },
+  )
+}
+
+// length of the diagonal of the bounding box of the mesh (0 without vertices)
+pub fn bounding_box_diagonal<D: DataStructure>(mesh: &D) -> f32 {
+  if mesh.num_vertices() == 0 {
+    return 0.0;
+  }
+
+  let (min, max) = bounds(
+    std::iter::successors(mesh.initial_vertex(), |v| mesh.next_vertex(*v))
+      .map(|v| mesh.get_position(v)),
+  );
+
+  (max - min).norm()
+}
+
+// 0 without edges
+pub fn mean_edge_length<D: DataStructure>(mesh: &D) -> f32 {
+  let mut sum = 0.0;
+  let mut count = 0;
+
+  let mut edge_op = mesh.initial_edge();
+
+  while let Some(edge_idx) = edge_op {
+    let [first, second] = mesh.get_endpoints(edge_idx);
+
+    sum += (mesh.get_position(first) - mesh.get_position(second)).norm() as f64;
+    count += 1;
+
+    edge_op = mesh.next_edge(edge_idx);
+  }
+
+  if count == 0 {
+    0.0
+  } else {
+    (sum / count as f64) as f32
+  }
+}
+
+// reads pairs of vertex indices (1 based, as in obj files), one pair per
+// line; empty lines and lines starting with '#' are skipped
+pub fn read_edge_list(path: &Path) -> std::io::Result<Vec<[IndexType; 2]>> {
+  let reader = BufReader::new(File::open(path)?);
+
+  let mut edges = Vec::new();
+
+  for line in reader.lines() {
+    let line = line?;
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+
+    let indices = line
+      .split_whitespace()
+      .map(|v| v.parse::<IndexType>().ok().filter(|v| *v > 0))
+      .collect::<Option<Vec<_>>>();
+
+    match indices.as_deref() {
+      Some([first, second]) => edges.push([first - 1, second - 1]),
+      _ => {
+        return Err(std::io::Error::new(
+          std::io::ErrorKind::InvalidData,
+          format!("expected two vertex indices, found \"{}\"", line),
+        ))
+      }
+    }
+  }
+
+  Ok(edges)
+}
+
+// union find over 0..n
+pub struct DisjointSets {
+  parents: Vec<usize>,
+}
+
+impl DisjointSets {
+  pub fn new(n: usize) -> Self {
+    DisjointSets {
+      parents: (0..n).collect(),
+    }
+  }
+
+  pub fn find(&mut self, mut idx: usize) -> usize {
+    while self.parents[idx] != idx {
+      // path halving
+      self.parents[idx] = self.parents[self.parents[idx]];
+      idx = self.parents[idx];
+    }
+
+    idx
+  }
+
+  pub fn union(&mut self, first: usize, second: usize) {
+    let first_root = self.find(first);
+    let second_root = self.find(second);
+
+    self.parents[second_root] = first_root;
+  }
+}